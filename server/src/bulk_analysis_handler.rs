@@ -0,0 +1,320 @@
+// bulk_analysis_handler.rs
+
+use crate::ai_client::send_structured_request_to_ai;
+use crate::business_process_handler::analyze_process_justification;
+use crate::cluster_optimizer::reoptimize_cluster;
+use crate::clustering_common;
+use crate::common::{
+    extract_process_json, format_time, generate_event_id, get_individuals_uris_by_query, load_schema, prepare_request_ai_parameters, set_to_individual_from_ai_response,
+    ClientType,
+};
+use crate::output_language::resolve_output_language;
+use crate::prompt_manager::PromptContext;
+use crate::prompt_version::resolve_prompt_version;
+use crate::queue_processor::BusinessProcessAnalysisModule;
+use crate::review_workflow;
+use tokio::runtime::Runtime;
+use v_common::onto::datatype::Lang;
+use v_common::onto::individual::Individual;
+use v_common::v_api::api_client::IndvOp;
+use v_common::v_api::obj::ResultCode;
+
+/// Обработчик, применяемый к каждому объекту массового анализа
+enum BulkHandler {
+    /// Оценка обоснованности бизнес-процессов
+    Justification,
+    /// Оптимизация кластеров процессов
+    Optimization,
+    /// Произвольный промпт, результат которого записывается в объект
+    Prompt(String),
+}
+
+impl BulkHandler {
+    fn from_request(request: &mut Individual) -> Result<Self, Box<dyn std::error::Error>> {
+        match request.get_first_literal("v-bpa:bulkHandler").unwrap_or_default().as_str() {
+            "v-bpa:JustificationHandler" => Ok(BulkHandler::Justification),
+            "v-bpa:OptimizationHandler" => Ok(BulkHandler::Optimization),
+            "v-bpa:PromptHandler" => {
+                let prompt_id = request.get_first_literal("v-bpa:bulkPrompt").ok_or("No prompt specified for prompt handler")?;
+                Ok(BulkHandler::Prompt(prompt_id))
+            },
+            other => Err(format!("Unknown bulk analysis handler: {}", other).into()),
+        }
+    }
+
+    /// Промпт, которым обработчик анализирует объекты
+    fn prompt_id(&self) -> &str {
+        match self {
+            BulkHandler::Justification => "v-bpa:AnalyzeBusinessPrompt",
+            BulkHandler::Optimization => "v-bpa:OptimizeProcessesPrompt",
+            BulkHandler::Prompt(prompt_id) => prompt_id.as_str(),
+        }
+    }
+
+    /// Свойства, изменение которых считается изменением вердикта: заданные запросом или записываемые схемой ответа промпта
+    fn verdict_properties(
+        &self,
+        module: &mut BusinessProcessAnalysisModule,
+        prompt_version: &str,
+        request: &mut Individual,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        if let Some(properties) = request.get_literals("v-bpa:verdictProperty") {
+            return Ok(properties);
+        }
+
+        match self {
+            BulkHandler::Justification => Ok(vec!["v-bpa:hasProcessJustification".to_string()]),
+            _ => Ok(load_schema(module, prompt_version, None, None)?.mapped_properties()),
+        }
+    }
+
+    /// Анализирует объект версией промпта, определенной для запроса
+    fn run(&self, module: &mut BusinessProcessAnalysisModule, prompt_version: &str, target_id: &str, event_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            BulkHandler::Justification => {
+                let mut process = load_individual(module, target_id)?;
+                analyze_process_justification(module, &mut process, Some(prompt_version), event_id)
+            },
+            BulkHandler::Optimization => reoptimize_cluster(module, target_id, Some(prompt_version), event_id),
+            BulkHandler::Prompt(_) => apply_prompt(module, prompt_version, target_id, event_id),
+        }
+    }
+}
+
+/// Выполняет массовый повторный анализ объектов, заданных запросом или списком
+///
+/// # Управление процессом
+/// - Запуск, приостановка, возобновление и отмена через v-bpa:controlAction
+/// - Позиция v-bpa:currentTargetIndex сохраняется после каждого объекта, возобновление продолжает с нее
+/// - Обновляются процент выполнения и оценка оставшегося времени
+/// - По завершении сохраняется количество объектов, у которых изменился вердикт
+pub fn process_bulk_analysis(module: &mut BusinessProcessAnalysisModule, request: &mut Individual, in_event_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let event_id = match generate_event_id("BULK", request.get_id(), in_event_id) {
+        Some(s) => s,
+        None => return Ok(()),
+    };
+
+    let control_action = request.get_first_literal("v-bpa:controlAction").unwrap_or_default();
+    let state = request.get_first_literal("v-bpa:hasExecutionState").unwrap_or_default();
+
+    match (control_action.as_str(), state.as_str()) {
+        ("v-bpa:StartExecution", "" | "v-bpa:ExecutionCompleted" | "v-bpa:ExecutionTerminated" | "v-bpa:ExecutionError") => {
+            if let Err(e) = initialize_bulk_analysis(module, request, &event_id) {
+                return handle_error(module, request, &event_id, e);
+            }
+        },
+        ("v-bpa:ResumeExecution", "v-bpa:ExecutionPaused") => {
+            info!("Resuming bulk analysis {}", request.get_id());
+            request.set_uri("v-bpa:hasExecutionState", "v-bpa:ExecutionInProgress");
+        },
+        ("v-bpa:CancelExecution", "v-bpa:ExecutionPaused") => {
+            info!("Cancelling paused bulk analysis {}", request.get_id());
+            request.set_uri("v-bpa:hasExecutionState", "v-bpa:ExecutionTerminated");
+            request.set_uri("v-bpa:controlAction", "v-bpa:NoActionExecution");
+            request.set_datetime("v-bpa:endDate", chrono::Utc::now().timestamp());
+            return clustering_common::update_individual(module, request, IndvOp::SetIn, &event_id);
+        },
+        _ => return Ok(()),
+    }
+
+    request.set_uri("v-bpa:controlAction", "v-bpa:NoActionExecution");
+    request.set_datetime("v-bpa:lastActivityAt", chrono::Utc::now().timestamp());
+    clustering_common::update_individual(module, request, IndvOp::SetIn, &event_id)?;
+
+    match run_bulk_analysis(module, request, &event_id) {
+        Ok(()) => Ok(()),
+        Err(e) => handle_error(module, request, &event_id, e),
+    }
+}
+
+/// Определяет объекты анализа и сбрасывает счетчики
+fn initialize_bulk_analysis(module: &mut BusinessProcessAnalysisModule, request: &mut Individual, event_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    BulkHandler::from_request(request)?;
+
+    let mut targets = request.get_literals("v-bpa:bulkTarget").unwrap_or_default();
+    if let Some(query) = request.get_first_literal("v-bpa:bulkTargetQuery") {
+        info!("Selecting bulk analysis targets with query: {}", query);
+        for id in get_individuals_uris_by_query(module, &query)? {
+            if !targets.contains(&id) {
+                targets.push(id);
+            }
+        }
+    }
+
+    if targets.is_empty() {
+        return Err("No targets found for bulk analysis".into());
+    }
+    info!("Initializing bulk analysis {} for {} targets", request.get_id(), targets.len());
+
+    let current_time = chrono::Utc::now().timestamp();
+    request.set_uris("v-bpa:analysisTarget", targets);
+    request.set_integer("v-bpa:currentTargetIndex", 0);
+    request.set_integer("v-bpa:analysisProgress", 0);
+    request.set_integer("v-bpa:processedCount", 0);
+    request.set_integer("v-bpa:changedVerdictCount", 0);
+    request.set_integer("v-bpa:failedCount", 0);
+    request.remove("v-bpa:changedTarget");
+    request.remove("v-bpa:bulkSummary");
+    request.remove("v-bpa:lastError");
+    request.remove("v-bpa:endDate");
+    // Новый запуск использует закрепленную или текущую версию промпта, а не версию предыдущего запуска
+    request.remove("v-bpa:usedPromptVersion");
+    request.set_datetime("v-bpa:startDate", current_time);
+    request.set_uri("v-bpa:hasExecutionState", "v-bpa:ExecutionInProgress");
+
+    clustering_common::update_individual(module, request, IndvOp::Put, event_id)
+}
+
+/// Последовательно анализирует объекты, начиная с v-bpa:currentTargetIndex
+fn run_bulk_analysis(module: &mut BusinessProcessAnalysisModule, request: &mut Individual, event_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let handler = BulkHandler::from_request(request)?;
+    // Версия промпта определяется при первом запуске и сохраняется в запросе, возобновление продолжает с ней
+    let prompt_version = resolve_prompt_version(module, handler.prompt_id(), Some(&mut *request));
+    let verdict_properties = handler.verdict_properties(module, &prompt_version, request)?;
+    let targets = request.get_literals("v-bpa:analysisTarget").unwrap_or_default();
+    let start_index = request.get_first_integer("v-bpa:currentTargetIndex").unwrap_or_default().max(0) as usize;
+    let run_started_at = chrono::Utc::now().timestamp();
+
+    for index in start_index..targets.len() {
+        if !check_control_action(module, request, event_id)? {
+            return Ok(());
+        }
+
+        let target_id = &targets[index];
+        info!("Bulk analysis {}: processing {} ({}/{})", request.get_id(), target_id, index + 1, targets.len());
+
+        let before = load_verdict(module, target_id, &verdict_properties);
+        match handler.run(module, &prompt_version, target_id, event_id) {
+            Ok(()) => {
+                if load_verdict(module, target_id, &verdict_properties) != before {
+                    increment(request, "v-bpa:changedVerdictCount");
+                    request.add_uri("v-bpa:changedTarget", target_id);
+                }
+            },
+            Err(e) => {
+                error!("Bulk analysis of {} failed: {}", target_id, e);
+                increment(request, "v-bpa:failedCount");
+            },
+        }
+        increment(request, "v-bpa:processedCount");
+
+        // Сохраняем позицию, прогресс и оценку оставшегося времени
+        let done = index + 1;
+        let elapsed = chrono::Utc::now().timestamp() - run_started_at;
+        let estimated_time = elapsed * (targets.len() - done) as i64 / (done - start_index) as i64;
+        request.set_integer("v-bpa:currentTargetIndex", done as i64);
+        request.set_integer("v-bpa:analysisProgress", (done * 100 / targets.len()) as i64);
+        request.set_integer("v-bpa:estimatedTime", estimated_time);
+        request.set_datetime("v-bpa:lastActivityAt", chrono::Utc::now().timestamp());
+        clustering_common::update_individual(module, request, IndvOp::SetIn, event_id)?;
+        info!("Bulk analysis {}: {}% done, remaining {}", request.get_id(), done * 100 / targets.len(), format_time(estimated_time));
+    }
+
+    let processed = request.get_first_integer("v-bpa:processedCount").unwrap_or_default();
+    let changed = request.get_first_integer("v-bpa:changedVerdictCount").unwrap_or_default();
+    let failed = request.get_first_integer("v-bpa:failedCount").unwrap_or_default();
+    let summary = format!("Обработано: {}, вердикт изменился: {}, ошибок: {}", processed, changed, failed);
+    info!("Bulk analysis {} completed: {}", request.get_id(), summary);
+
+    request.set_string("v-bpa:bulkSummary", &summary, Lang::none());
+    request.set_uri("v-bpa:hasExecutionState", "v-bpa:ExecutionCompleted");
+    request.set_datetime("v-bpa:endDate", chrono::Utc::now().timestamp());
+    request.set_integer("v-bpa:estimatedTime", 0);
+    clustering_common::update_individual(module, request, IndvOp::SetIn, event_id)
+}
+
+/// Проверяет команды приостановки и отмены, сохраненные пользователем во время выполнения
+fn check_control_action(module: &mut BusinessProcessAnalysisModule, request: &mut Individual, event_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut current_state = load_individual(module, request.get_id())?;
+
+    match current_state.get_first_literal("v-bpa:controlAction").unwrap_or_default().as_str() {
+        "v-bpa:StopExecution" => {
+            info!("Received stop command for bulk analysis {}", request.get_id());
+            request.set_uri("v-bpa:hasExecutionState", "v-bpa:ExecutionPaused");
+        },
+        "v-bpa:CancelExecution" => {
+            info!("Received cancel command for bulk analysis {}", request.get_id());
+            request.set_uri("v-bpa:hasExecutionState", "v-bpa:ExecutionTerminated");
+            request.set_datetime("v-bpa:endDate", chrono::Utc::now().timestamp());
+        },
+        _ => return Ok(true),
+    }
+
+    request.set_uri("v-bpa:controlAction", "v-bpa:NoActionExecution");
+    clustering_common::update_individual(module, request, IndvOp::SetIn, event_id)?;
+    Ok(false)
+}
+
+/// Применяет версию промпта к объекту: данные объекта передаются в AI, ответ записывается в объект
+fn apply_prompt(module: &mut BusinessProcessAnalysisModule, prompt_version: &str, target_id: &str, in_event_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let event_id = match generate_event_id("BULKP", target_id, in_event_id) {
+        Some(s) => s,
+        None => return Ok(()),
+    };
+
+    let mut target = load_individual(module, target_id)?;
+    let analysis_data = if target.any_exists("rdf:type", &["v-bpa:BusinessProcess"]) {
+        extract_process_json(&mut target, module)?
+    } else {
+        target.get_obj().as_json()
+    };
+
+    let mut schema = load_schema(module, prompt_version, None, None)?;
+    let output_language = resolve_output_language(module, prompt_version, None, Some(&mut target));
+    schema.output_language = Some(output_language.clone());
+    let context = PromptContext::for_request(module, None, Some(&mut target), &output_language);
+    let parameters = prepare_request_ai_parameters(module, prompt_version, analysis_data, &schema, &context)?;
+
+    let rt = Runtime::new()?;
+    let ai_response = rt.block_on(async { send_structured_request_to_ai(module, parameters, ClientType::Default).await })?;
+
+    // Результат, подтвержденный или исправленный человеком, не перезаписываем
+    let ai_properties = schema.mapped_properties();
+    if review_workflow::is_protected(&mut target) {
+        let mut suggestion = review_workflow::new_suggestion(&mut target);
+        set_to_individual_from_ai_response(module, &mut suggestion, &ai_response, &schema, &event_id)?;
+        review_workflow::save_suggestion(module, &mut target, &mut suggestion, &ai_properties, &event_id)?;
+    } else {
+        set_to_individual_from_ai_response(module, &mut target, &ai_response, &schema, &event_id)?;
+        review_workflow::mark_pending_review(&mut target, &ai_properties);
+    }
+    clustering_common::update_individual(module, &mut target, IndvOp::Put, &event_id)
+}
+
+/// Загружает значения свойств вердикта объекта
+fn load_verdict(module: &mut BusinessProcessAnalysisModule, target_id: &str, properties: &[String]) -> Vec<Vec<String>> {
+    match load_individual(module, target_id) {
+        Ok(mut target) => properties.iter().map(|p| target.get_literals(p).unwrap_or_default()).collect(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn increment(request: &mut Individual, predicate: &str) {
+    let value = request.get_first_integer(predicate).unwrap_or_default();
+    request.set_integer(predicate, value + 1);
+}
+
+fn load_individual(module: &mut BusinessProcessAnalysisModule, id: &str) -> Result<Individual, Box<dyn std::error::Error>> {
+    let mut individual = Individual::default();
+    if module.backend.storage.get_individual(id, &mut individual) != ResultCode::Ok {
+        return Err(format!("Failed to load individual {}", id).into());
+    }
+    individual.parse_all();
+    Ok(individual)
+}
+
+/// Обработчик ошибок массового анализа
+fn handle_error(
+    module: &mut BusinessProcessAnalysisModule,
+    request: &mut Individual,
+    event_id: &str,
+    error: Box<dyn std::error::Error>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    error!("Error in bulk analysis {}: {}", request.get_id(), error);
+    request.set_string("v-bpa:lastError", &error.to_string(), Lang::none());
+    request.set_uri("v-bpa:hasExecutionState", "v-bpa:ExecutionError");
+    request.set_uri("v-bpa:controlAction", "v-bpa:NoActionExecution");
+    request.set_datetime("v-bpa:endDate", chrono::Utc::now().timestamp());
+    clustering_common::update_individual(module, request, IndvOp::SetIn, event_id)?;
+    Err(error)
+}
@@ -1,14 +1,28 @@
 // business_process_handler.rs
 
-use crate::ai_client::send_structured_request_to_ai;
-use crate::common::{extract_process_json, generate_event_id, load_schema, prepare_request_ai_parameters, set_to_individual_from_ai_response, ClientType};
+use crate::ai_client::{send_structured_request_to_ai, AIResponseValues};
+use crate::common::{
+    extract_process_json, generate_event_id, get_individuals_uris_by_query, load_schema, prepare_request_ai_parameters, set_to_individual_from_ai_response, ClientType,
+};
+use crate::consensus::{self, ConsensusConfig};
+use crate::justification_citations::{add_citations_to_schema, save_citations};
+use crate::output_language::resolve_output_language;
+use crate::prompt_manager::PromptContext;
+use crate::prompt_version::resolve_prompt_version;
 use crate::queue_processor::BusinessProcessAnalysisModule;
-use crate::types::PropertyMapping;
+use crate::review_workflow;
+use crate::schema_compiler::CompiledSchema;
+use openai_dive::v1::resources::chat::ChatCompletionParameters;
 use std::collections::HashSet;
 use std::io;
 use tokio::runtime::Runtime;
 use v_common::onto::individual::Individual;
 use v_common::v_api::api_client::IndvOp;
+use v_common::v_api::obj::ResultCode;
+
+/// Источник изменений, которым процесс ставится в очередь на повторную оценку обоснованности.
+/// В отличие от "BPA", изменения с этим источником не пропускаются обработчиком очереди.
+const REJUSTIFICATION_SOURCE: &str = "BPA-rejustify";
 
 /// Анализирует обоснованность бизнес-процесса на основе связанных документов
 /// используя AI для оценки уровня обоснованности.
@@ -16,10 +30,16 @@ use v_common::v_api::api_client::IndvOp;
 /// # Arguments
 /// * `module` - Модуль анализа бизнес-процессов с настройками и клиентом AI
 /// * `bp_obj` - Индивид бизнес-процесса для анализа
+/// * `prompt_version` - Версия промпта v-bpa:AnalyzeBusinessPrompt, закрепленная запросом; если не задана, используется текущая версия
 ///
 /// # Returns
 /// * `Result<(), Box<dyn std::error::Error>>` - Результат анализа и сохранения оценки
-pub fn analyze_process_justification(module: &mut BusinessProcessAnalysisModule, bp_obj: &mut Individual, in_event_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+pub fn analyze_process_justification(
+    module: &mut BusinessProcessAnalysisModule,
+    bp_obj: &mut Individual,
+    prompt_version: Option<&str>,
+    in_event_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
     let event_id = match generate_event_id("APJ", bp_obj.get_id(), in_event_id) {
         Some(s) => s,
         None => return Ok(()),
@@ -33,8 +53,13 @@ pub fn analyze_process_justification(module: &mut BusinessProcessAnalysisModule,
     if !has_documents {
         info!("Process {} has no justification documents. Setting status to NoDocumentForJustification", bp_obj.get_id());
 
-        // Set the process justification status
-        bp_obj.set_uri("v-bpa:hasProcessJustification", "v-bpa:NoDocumentForJustification");
+        // Set the process justification status unless it is approved or edited by a person
+        if review_workflow::is_protected(bp_obj) {
+            info!("Justification of process {} is reviewed by a person, keeping it", bp_obj.get_id());
+        } else {
+            bp_obj.set_uri("v-bpa:hasProcessJustification", "v-bpa:NoDocumentForJustification");
+        }
+        clear_stale_justification(bp_obj);
 
         // Save the updated individual to storage
         if let Err(e) = module.backend.mstorage_api.update_or_err(&module.ticket, &event_id, "BPA", IndvOp::Put, bp_obj) {
@@ -50,21 +75,55 @@ pub fn analyze_process_justification(module: &mut BusinessProcessAnalysisModule,
 
     info!("Process Name: {}", process_json["processName"]);
 
-    let mut property_mapping = PropertyMapping::new();
-    let property_schema = load_schema(module, "v-bpa:AnalyzeBusinessPrompt", Some(HashSet::from(["v-bpa:NoDocumentForJustification"])), &mut property_mapping)?;
+    let prompt_version = prompt_version.map(str::to_string).unwrap_or_else(|| resolve_prompt_version(module, "v-bpa:AnalyzeBusinessPrompt", None));
+    let mut schema = load_schema(module, &prompt_version, Some(HashSet::from(["v-bpa:NoDocumentForJustification"])), None)?;
+    add_citations_to_schema(&mut schema);
+    let output_language = resolve_output_language(module, &prompt_version, None, Some(&mut *bp_obj));
+    schema.output_language = Some(output_language.clone());
 
-    // Подготавливаем параметры запроса и получаем маппинг свойств
-    let parameters = prepare_request_ai_parameters(module, "v-bpa:AnalyzeBusinessPrompt", process_json, property_schema, &mut property_mapping)?;
+    // Подготавливаем параметры запроса по схеме ответа
+    let context = PromptContext::for_request(module, None, Some(&mut *bp_obj), &output_language);
+    let parameters = prepare_request_ai_parameters(module, &prompt_version, process_json, &schema, &context)?;
     debug!("Parameters prepared for OpenAI: {:?}", parameters);
 
-    // Создаем новый рантайм для асинхронного выполнения
-    let rt = Runtime::new()?;
+    // Отправляем запрос к AI, при включенном консенсусе - по одному запросу на выборку
+    let consensus = ConsensusConfig::load(module, &prompt_version)?;
+    let (ai_response, confidence) = if consensus.is_enabled() {
+        let (response, confidence) = request_justification_consensus(module, &consensus, &parameters, &schema)?;
+        (response, Some(confidence))
+    } else {
+        let rt = Runtime::new()?;
+        (rt.block_on(async { send_structured_request_to_ai(module, parameters, ClientType::Default).await })?, None)
+    };
+
+    // Оценку, подтвержденную или исправленную человеком, не перезаписываем: новая оценка сохраняется как предложение
+    let ai_properties = schema.mapped_properties();
+    let is_protected = review_workflow::is_protected(bp_obj);
+    let mut suggestion = review_workflow::new_suggestion(bp_obj);
+    let (result, previous_citations) = if is_protected {
+        suggestion.set_uris("v-bpa:hasProcessDocument", bp_obj.get_literals("v-bpa:hasProcessDocument").unwrap_or_default());
+        (&mut suggestion, Vec::new())
+    } else {
+        let previous_citations = bp_obj.get_literals("v-bpa:hasJustificationCitation").unwrap_or_default();
+        (&mut *bp_obj, previous_citations)
+    };
+
+    // Сохраняем результат в индивиде по схеме ответа
+    set_to_individual_from_ai_response(module, result, &ai_response, &schema, &event_id)?;
+    match confidence {
+        Some(confidence) => consensus::set_verdict_confidence(result, confidence, consensus.threshold),
+        None => consensus::clear_verdict_confidence(result),
+    }
 
-    // Отправляем запрос к AI
-    let ai_response = rt.block_on(async { send_structured_request_to_ai(module, parameters, ClientType::Default).await })?;
+    // Сохраняем цитаты из документов, подтвержденные их текстом
+    save_citations(module, result, &ai_response, &previous_citations, &event_id)?;
 
-    // Сохраняем результат в индивиде с учетом маппинга свойств
-    set_to_individual_from_ai_response(module, bp_obj, &ai_response, &mut property_mapping)?;
+    if is_protected {
+        review_workflow::save_suggestion(module, bp_obj, &mut suggestion, &ai_properties, &event_id)?;
+    } else {
+        review_workflow::mark_pending_review(bp_obj, &ai_properties);
+    }
+    clear_stale_justification(bp_obj);
 
     // Сохраняем обновленный индивид в хранилище
     if let Err(e) = module.backend.mstorage_api.update_or_err(&module.ticket, &event_id, "BPA", IndvOp::Put, bp_obj) {
@@ -74,3 +133,78 @@ pub fn analyze_process_justification(module: &mut BusinessProcessAnalysisModule,
 
     Ok(())
 }
+
+/// Запрашивает оценку обоснованности у каждой выборки консенсуса и выбирает оценку голосованием
+///
+/// Возвращает ответ первой выборки с победившей оценкой и уверенность голосования.
+/// Выборки, завершившиеся ошибкой, в голосовании не участвуют.
+fn request_justification_consensus(
+    module: &mut BusinessProcessAnalysisModule,
+    consensus: &ConsensusConfig,
+    parameters: &ChatCompletionParameters,
+    schema: &CompiledSchema,
+) -> Result<(AIResponseValues, f64), Box<dyn std::error::Error>> {
+    let verdict_field = schema.field_for_property("v-bpa:hasProcessJustification").ok_or("Justification property not found in prompt schema")?;
+
+    let rt = Runtime::new()?;
+    let mut responses = Vec::new();
+    let mut votes = Vec::new();
+    for (sample, sample_parameters) in consensus.samples.iter().zip(consensus.sample_parameters(parameters)) {
+        let client_type = ClientType::for_model(module, &sample.model);
+        match rt.block_on(async { send_structured_request_to_ai(module, sample_parameters, client_type).await }) {
+            Ok(response) => {
+                let verdict = response.get("result").and_then(|r| r.get(verdict_field)).map(|v| v.to_string()).unwrap_or_default();
+                info!("Consensus sample {} (seed {}): {}", sample.model, sample.seed, verdict);
+                votes.push((verdict, sample.weight));
+                responses.push(response);
+            },
+            Err(e) => warn!("Consensus sample {} (seed {}) failed: {}", sample.model, sample.seed, e),
+        }
+    }
+
+    let (verdict, confidence) = consensus::vote(&votes).ok_or("All consensus samples failed")?;
+    let index = votes.iter().position(|(v, _)| *v == verdict).unwrap_or_default();
+    info!("Consensus verdict {} with confidence {:.2} from {} samples", verdict, confidence, votes.len());
+
+    Ok((responses.swap_remove(index), confidence))
+}
+
+/// Помечает устаревшей оценку обоснованности процессов, ссылающихся на измененный документ,
+/// и ставит их в очередь на повторный анализ
+///
+/// # Returns
+/// * Количество процессов, поставленных на повторный анализ
+pub fn schedule_rejustification(module: &mut BusinessProcessAnalysisModule, document_id: &str, event_id: &str) -> Result<usize, Box<dyn std::error::Error>> {
+    let query = format!("'rdf:type' === 'v-bpa:BusinessProcess' && 'v-bpa:hasProcessDocument' === '{}'", document_id);
+    let process_ids = get_individuals_uris_by_query(module, &query)?;
+
+    let mut scheduled = 0;
+    for process_id in process_ids {
+        let mut process = Individual::default();
+        if module.backend.storage.get_individual(&process_id, &mut process) != ResultCode::Ok {
+            warn!("Failed to load process {} citing document {}", process_id, document_id);
+            continue;
+        }
+        process.parse_all();
+
+        process.set_bool("v-bpa:isJustificationStale", true);
+        if !process.get_literals("v-bpa:staleJustificationDocument").unwrap_or_default().iter().any(|d| d == document_id) {
+            process.add_uri("v-bpa:staleJustificationDocument", document_id);
+        }
+
+        if let Err(e) = module.backend.mstorage_api.update_or_err(&module.ticket, event_id, REJUSTIFICATION_SOURCE, IndvOp::Put, &process) {
+            error!("Failed to schedule re-justification of process {}: {:?}", process_id, e);
+            continue;
+        }
+        info!("Process {} scheduled for re-justification after change of document {}", process_id, document_id);
+        scheduled += 1;
+    }
+
+    Ok(scheduled)
+}
+
+/// Снимает отметку об устаревшей оценке обоснованности
+fn clear_stale_justification(bp_obj: &mut Individual) {
+    bp_obj.remove("v-bpa:isJustificationStale");
+    bp_obj.remove("v-bpa:staleJustificationDocument");
+}
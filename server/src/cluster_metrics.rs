@@ -0,0 +1,127 @@
+// cluster_metrics.rs
+
+use std::collections::{BTreeMap, HashSet};
+use v_common::onto::datatype::Lang;
+use v_common::onto::individual::Individual;
+
+/// Пара процессов, признанных похожими
+pub type SimilarPair = (String, String);
+
+/// Показатели качества отдельного кластера
+#[derive(Debug, Default)]
+pub struct ClusterQuality {
+    pub size: usize,
+    /// Ребра графа схожести, по которым процессы попали в кластер
+    pub edges: Vec<SimilarPair>,
+    /// Доля похожих пар среди всех пар кластера (0..1)
+    pub density: f64,
+    pub total_labor_costs: f64,
+    /// Трудозатраты, которые можно сократить, оставив самый трудоемкий процесс кластера
+    pub potential_labor_savings: f64,
+}
+
+/// Сводные показатели попытки кластеризации
+#[derive(Debug, Default)]
+pub struct AttemptQuality {
+    pub cluster_count: usize,
+    /// Размер кластера -> количество кластеров такого размера
+    pub size_distribution: BTreeMap<usize, usize>,
+    /// Доля процессов, не вошедших ни в один кластер
+    pub singleton_ratio: f64,
+    pub mean_intra_cluster_similarity: f64,
+    pub potential_labor_savings: f64,
+}
+
+/// Разбирает строки v-bpa:similarPairs вида "process1,process2"
+pub fn parse_similar_pairs(pairs: &[String]) -> Vec<SimilarPair> {
+    pairs
+        .iter()
+        .filter_map(|pair| {
+            let parts: Vec<&str> = pair.split(',').collect();
+            if parts.len() == 2 {
+                Some((parts[0].to_string(), parts[1].to_string()))
+            } else {
+                warn!("Skipping malformed similar pair: {}", pair);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Вычисляет показатели кластера по его составу, похожим парам и трудозатратам процессов
+pub fn evaluate_cluster(processes: &HashSet<String>, similar_pairs: &[SimilarPair], labor_costs: &[f64]) -> ClusterQuality {
+    let edges: Vec<SimilarPair> = similar_pairs.iter().filter(|(a, b)| processes.contains(a) && processes.contains(b)).cloned().collect();
+
+    let size = processes.len();
+    let possible_pairs = size * size.saturating_sub(1) / 2;
+    let density = if possible_pairs > 0 {
+        edges.len() as f64 / possible_pairs as f64
+    } else {
+        0.0
+    };
+
+    let total_labor_costs: f64 = labor_costs.iter().sum();
+    let max_labor_costs = labor_costs.iter().cloned().fold(0.0, f64::max);
+
+    ClusterQuality {
+        size,
+        edges,
+        density,
+        total_labor_costs,
+        potential_labor_savings: total_labor_costs - max_labor_costs,
+    }
+}
+
+/// Сводит показатели кластеров в показатели попытки
+pub fn evaluate_attempt(clusters: &[ClusterQuality], total_processes: usize) -> AttemptQuality {
+    let mut quality = AttemptQuality {
+        cluster_count: clusters.len(),
+        ..Default::default()
+    };
+
+    let mut clustered_processes = 0;
+    for cluster in clusters {
+        *quality.size_distribution.entry(cluster.size).or_insert(0) += 1;
+        clustered_processes += cluster.size;
+        quality.potential_labor_savings += cluster.potential_labor_savings;
+    }
+
+    if total_processes > 0 {
+        quality.singleton_ratio = total_processes.saturating_sub(clustered_processes) as f64 / total_processes as f64;
+    }
+
+    if !clusters.is_empty() {
+        quality.mean_intra_cluster_similarity = clusters.iter().map(|c| c.density).sum::<f64>() / clusters.len() as f64;
+    }
+
+    quality
+}
+
+/// Записывает показатели и объяснение состава в индивид кластера
+pub fn set_cluster_quality(cluster: &mut Individual, quality: &ClusterQuality) {
+    cluster.set_integer("v-bpa:clusterSize", quality.size as i64);
+    cluster.set_decimal_from_f64("v-bpa:clusterDensity", quality.density);
+    cluster.set_decimal_from_f64("v-bpa:totalLaborCosts", quality.total_labor_costs);
+    cluster.set_decimal_from_f64("v-bpa:potentialLaborSavings", quality.potential_labor_savings);
+
+    cluster.remove("v-bpa:membershipEdge");
+    for (a, b) in &quality.edges {
+        cluster.add_string("v-bpa:membershipEdge", &format!("{},{}", a, b), Lang::none());
+    }
+}
+
+/// Записывает сводные показатели в индивид попытки кластеризации
+pub fn set_attempt_quality(clustering_attempt: &mut Individual, quality: &AttemptQuality) {
+    let distribution = quality.size_distribution.iter().map(|(size, count)| format!("{}:{}", size, count)).collect::<Vec<_>>().join(", ");
+
+    clustering_attempt.set_integer("v-bpa:clusterCount", quality.cluster_count as i64);
+    clustering_attempt.set_string("v-bpa:clusterSizeDistribution", &distribution, Lang::none());
+    clustering_attempt.set_decimal_from_f64("v-bpa:singletonRatio", quality.singleton_ratio);
+    clustering_attempt.set_decimal_from_f64("v-bpa:meanIntraClusterSimilarity", quality.mean_intra_cluster_similarity);
+    clustering_attempt.set_decimal_from_f64("v-bpa:potentialLaborSavings", quality.potential_labor_savings);
+
+    info!(
+        "Clustering quality: clusters={}, sizes=[{}], singleton_ratio={:.2}, mean_similarity={:.2}, potential_savings={:.1}",
+        quality.cluster_count, distribution, quality.singleton_ratio, quality.mean_intra_cluster_similarity, quality.potential_labor_savings
+    );
+}
@@ -1,7 +1,12 @@
 use crate::ai_client::send_structured_request_to_ai;
+use crate::cluster_savings::{calculate_savings, set_cluster_savings, ProcessWorkload};
 use crate::common::{extract_process_json, generate_event_id, load_schema, prepare_request_ai_parameters, set_to_individual_from_ai_response, ClientType};
+use crate::optimization_history::{record_optimization, OptimizationInput};
+use crate::output_language::resolve_output_language;
+use crate::prompt_manager::PromptContext;
+use crate::prompt_version::resolve_prompt_version;
 use crate::queue_processor::BusinessProcessAnalysisModule;
-use crate::types::PropertyMapping;
+use crate::review_workflow;
 use serde_json;
 use tokio::runtime::Runtime;
 use v_common::onto::individual::Individual;
@@ -23,6 +28,51 @@ pub fn analyze_and_optimize_cluster(module: &mut BusinessProcessAnalysisModule,
         return Err(format!("Failed to load cluster {}", cluster_id).into());
     }
 
+    // Кластер, не изменившийся с предыдущей попытки, уже содержит результаты оптимизации
+    if cluster.get_first_literal("v-bpa:hasStabilityStatus").as_deref() == Some("v-bpa:ClusterUnchanged") {
+        if let Some(previous_cluster) = cluster.get_first_literal("v-bpa:previousCluster") {
+            info!("Cluster {} is unchanged since {}, keeping previous optimization results", cluster_id, previous_cluster);
+            return Ok(());
+        }
+    }
+
+    optimize_cluster(module, cluster_id, &mut cluster, None, &event_id)
+}
+
+/// Повторно анализирует кластер, в том числе не изменившийся с предыдущей попытки кластеризации
+///
+/// prompt_version - версия v-bpa:OptimizeProcessesPrompt, закрепленная запросом массового анализа
+pub fn reoptimize_cluster(
+    module: &mut BusinessProcessAnalysisModule,
+    cluster_id: &str,
+    prompt_version: Option<&str>,
+    in_event_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let event_id = match generate_event_id("ROC", cluster_id, in_event_id) {
+        Some(s) => s,
+        None => return Ok(()),
+    };
+    info!("Starting repeated optimization analysis for cluster: {}", cluster_id);
+
+    let mut cluster = Individual::default();
+    if module.backend.storage.get_individual(cluster_id, &mut cluster) != ResultCode::Ok {
+        error!("Failed to load cluster {}", cluster_id);
+        return Err(format!("Failed to load cluster {}", cluster_id).into());
+    }
+
+    optimize_cluster(module, cluster_id, &mut cluster, prompt_version, &event_id)
+}
+
+/// Запрашивает у AI оптимизацию кластера и сохраняет результат
+///
+/// Если версия промпта не задана, используется текущая версия v-bpa:OptimizeProcessesPrompt.
+fn optimize_cluster(
+    module: &mut BusinessProcessAnalysisModule,
+    cluster_id: &str,
+    cluster: &mut Individual,
+    prompt_version: Option<&str>,
+    event_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
     // Получаем список процессов в кластере
     let process_ids = cluster.get_literals("v-bpa:hasProcess").unwrap_or_default();
     if process_ids.is_empty() {
@@ -32,6 +82,8 @@ pub fn analyze_and_optimize_cluster(module: &mut BusinessProcessAnalysisModule,
 
     // Загружаем данные всех процессов
     let mut processes_data = Vec::new();
+    let mut workloads = Vec::new();
+    let mut input_snapshot = Vec::new();
     for process_id in &process_ids {
         let mut process = Individual::default();
         if module.backend.storage.get_individual(process_id, &mut process) != ResultCode::Ok {
@@ -39,6 +91,8 @@ pub fn analyze_and_optimize_cluster(module: &mut BusinessProcessAnalysisModule,
             continue;
         }
         process.parse_all();
+        workloads.push(ProcessWorkload::from_process(&mut process));
+        input_snapshot.push((process_id.clone(), process.get_first_integer("v-s:updateCounter").unwrap_or_default()));
         if let Ok(data) = extract_process_json(&mut process, module) {
             processes_data.push(data);
         }
@@ -53,11 +107,14 @@ pub fn analyze_and_optimize_cluster(module: &mut BusinessProcessAnalysisModule,
     info!("Preparing optimization data for {} processes", processes_data.len());
     let analysis_data = prepare_optimization_data(&processes_data)?;
 
-    // Создаем параметры запроса и получаем маппинг свойств
-    let mut property_mapping = PropertyMapping::new();
-    let property_schema = load_schema(module, "v-bpa:OptimizeProcessesPrompt", None, &mut property_mapping)?;
+    // Компилируем схему ответа и создаем параметры запроса
+    let prompt_version = prompt_version.map(str::to_string).unwrap_or_else(|| resolve_prompt_version(module, "v-bpa:OptimizeProcessesPrompt", None));
+    let mut schema = load_schema(module, &prompt_version, None, None)?;
+    let output_language = resolve_output_language(module, &prompt_version, None, None);
+    schema.output_language = Some(output_language.clone());
 
-    let parameters = prepare_request_ai_parameters(module, "v-bpa:OptimizeProcessesPrompt", analysis_data, property_schema, &mut property_mapping)?;
+    let context = PromptContext::for_request(module, None, None, &output_language);
+    let parameters = prepare_request_ai_parameters(module, &prompt_version, analysis_data, &schema, &context)?;
 
     // Отправляем запрос к AI
     info!("Sending optimization request to AI for cluster {}", cluster_id);
@@ -73,11 +130,60 @@ pub fn analyze_and_optimize_cluster(module: &mut BusinessProcessAnalysisModule,
     }
     cluster_indv.parse_all();
 
-    // Сохраняем результат оптимизации с учетом маппинга
-    set_to_individual_from_ai_response(module, &mut cluster_indv, &optimization_result, &property_mapping)?;
+    // Предложение, подтвержденное или исправленное человеком, не перезаписываем: результат сохраняется как новое предложение AI
+    let ai_properties = schema.mapped_properties();
+    let is_protected = review_workflow::is_protected(&mut cluster_indv);
+    let mut suggestion = review_workflow::new_suggestion(&mut cluster_indv);
+    let proposal = if is_protected {
+        &mut suggestion
+    } else {
+        &mut cluster_indv
+    };
+
+    // Сохраняем результат оптимизации по схеме ответа
+    set_to_individual_from_ai_response(module, proposal, &optimization_result, &schema, event_id)?;
+
+    // Проверяем предложение AI расчетом экономии по трудоемкости процессов
+    let savings = match (proposal.get_first_integer("v-bpa:estimatedLaborCost"), proposal.get_first_integer("v-bpa:proposedFrequency")) {
+        (Some(labor_costs), Some(frequency)) => {
+            let proposed = ProcessWorkload {
+                labor_costs: labor_costs as f64,
+                frequency: frequency as f64,
+            };
+            let ai_estimate = proposal.get_first_float("v-bpa:estimatedAnnualSavings");
+            let savings = calculate_savings(&workloads, proposed, ai_estimate);
+            info!(
+                "Cluster {} savings: baseline={:.1} h/year, proposed={:.1} h/year, saved={:.1} h/year ({:.1}%)",
+                cluster_id, savings.baseline_annual_hours, savings.proposed_annual_hours, savings.annual_hours_saved, savings.reduction_percent
+            );
+            set_cluster_savings(proposal, &savings);
+            Some(savings)
+        },
+        _ => {
+            warn!("Optimization result for cluster {} has no proposed labor cost or frequency, savings not calculated", cluster_id);
+            None
+        },
+    };
+
+    // Сохраняем результат как очередную версию оптимизации кластера
+    let model = module.default_model.clone();
+    let input = OptimizationInput {
+        prompt_id: &prompt_version,
+        model: &model,
+        processes: &input_snapshot,
+    };
+    let optimization_id = record_optimization(module, &mut cluster_indv, &input, &optimization_result, &schema, savings.as_ref(), event_id)?;
+
+    if is_protected {
+        suggestion.set_uri("v-bpa:basedOnOptimization", &optimization_id);
+        review_workflow::save_suggestion(module, &mut cluster_indv, &mut suggestion, &ai_properties, event_id)?;
+    } else {
+        cluster_indv.set_uri("v-bpa:currentOptimization", &optimization_id);
+        review_workflow::mark_pending_review(&mut cluster_indv, &ai_properties);
+    }
 
     // Сохраняем обновленный индивид
-    if let Err(e) = module.backend.mstorage_api.update_or_err(&module.ticket, &event_id, "BPA", IndvOp::Put, &mut cluster_indv) {
+    if let Err(e) = module.backend.mstorage_api.update_or_err(&module.ticket, event_id, "BPA", IndvOp::Put, &cluster_indv) {
         error!("Failed to update individual {}: {:?}", cluster_indv.get_id(), e);
         return Err(Box::new(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to update individual, err={:?}", e))));
     }
@@ -0,0 +1,171 @@
+// cluster_savings.rs
+
+use v_common::onto::individual::Individual;
+
+/// Допустимое относительное расхождение оценки экономии от AI с расчетной
+const ESTIMATE_TOLERANCE: f64 = 0.2;
+/// Абсолютное расхождение (часов в год), которое не считается несогласием при малой экономии
+const ESTIMATE_MIN_DEVIATION: f64 = 1.0;
+
+/// Трудоемкость процесса: часов на одно выполнение и выполнений в год
+#[derive(Debug, Clone, Copy)]
+pub struct ProcessWorkload {
+    pub labor_costs: f64,
+    pub frequency: f64,
+}
+
+impl ProcessWorkload {
+    /// Загружает трудоемкость из индивида бизнес-процесса
+    pub fn from_process(process: &mut Individual) -> Self {
+        ProcessWorkload {
+            labor_costs: process.get_first_float("v-bpa:laborCosts").unwrap_or_default(),
+            frequency: process.get_first_integer("v-bpa:processFrequency").unwrap_or_default() as f64,
+        }
+    }
+
+    pub fn annual_hours(&self) -> f64 {
+        self.labor_costs * self.frequency
+    }
+}
+
+/// Расчет экономии от замены процессов кластера предложенным процессом
+#[derive(Debug, Default)]
+pub struct SavingsEstimate {
+    /// Суммарные трудозатраты процессов кластера, часов в год
+    pub baseline_annual_hours: f64,
+    /// Трудозатраты предложенного процесса, часов в год
+    pub proposed_annual_hours: f64,
+    pub annual_hours_saved: f64,
+    /// Сокращение трудозатрат в процентах от базовых
+    pub reduction_percent: f64,
+    /// Оценка экономии, которую вернул AI
+    pub ai_estimate: Option<f64>,
+    /// Оценка AI расходится с расчетом
+    pub estimate_disagrees: bool,
+}
+
+/// Вычисляет экономию по трудоемкости процессов кластера и предложенного процесса
+pub fn calculate_savings(members: &[ProcessWorkload], proposed: ProcessWorkload, ai_estimate: Option<f64>) -> SavingsEstimate {
+    let baseline_annual_hours: f64 = members.iter().map(ProcessWorkload::annual_hours).sum();
+    let proposed_annual_hours = proposed.annual_hours();
+    let annual_hours_saved = baseline_annual_hours - proposed_annual_hours;
+
+    let reduction_percent = if baseline_annual_hours > 0.0 {
+        annual_hours_saved / baseline_annual_hours * 100.0
+    } else {
+        0.0
+    };
+
+    let estimate_disagrees = ai_estimate.is_some_and(|estimate| {
+        let deviation = (estimate - annual_hours_saved).abs();
+        deviation > ESTIMATE_MIN_DEVIATION && deviation > annual_hours_saved.abs() * ESTIMATE_TOLERANCE
+    });
+
+    SavingsEstimate {
+        baseline_annual_hours,
+        proposed_annual_hours,
+        annual_hours_saved,
+        reduction_percent,
+        ai_estimate,
+        estimate_disagrees,
+    }
+}
+
+/// Записывает расчет экономии в индивид кластера
+pub fn set_cluster_savings(cluster: &mut Individual, savings: &SavingsEstimate) {
+    cluster.set_decimal_from_f64("v-bpa:baselineAnnualHours", savings.baseline_annual_hours);
+    cluster.set_decimal_from_f64("v-bpa:proposedAnnualHours", savings.proposed_annual_hours);
+    cluster.set_decimal_from_f64("v-bpa:annualHoursSaved", savings.annual_hours_saved);
+    cluster.set_decimal_from_f64("v-bpa:laborReductionPercent", savings.reduction_percent);
+    cluster.set_bool("v-bpa:savingsEstimateDisagrees", savings.estimate_disagrees);
+
+    if savings.estimate_disagrees {
+        warn!(
+            "AI savings estimate for cluster {} ({:.1} h/year) disagrees with calculated savings ({:.1} h/year)",
+            cluster.get_id(),
+            savings.ai_estimate.unwrap_or_default(),
+            savings.annual_hours_saved
+        );
+    }
+}
+
+/// Читает ранее сохраненный расчет экономии из индивида
+pub fn get_cluster_savings(individual: &mut Individual) -> Option<SavingsEstimate> {
+    let baseline_annual_hours = individual.get_first_float("v-bpa:baselineAnnualHours")?;
+    let proposed_annual_hours = individual.get_first_float("v-bpa:proposedAnnualHours").unwrap_or_default();
+
+    Some(SavingsEstimate {
+        baseline_annual_hours,
+        proposed_annual_hours,
+        annual_hours_saved: individual.get_first_float("v-bpa:annualHoursSaved").unwrap_or(baseline_annual_hours - proposed_annual_hours),
+        reduction_percent: individual.get_first_float("v-bpa:laborReductionPercent").unwrap_or_default(),
+        ai_estimate: individual.get_first_float("v-bpa:estimatedAnnualSavings"),
+        estimate_disagrees: individual.get_first_bool("v-bpa:savingsEstimateDisagrees").unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workload(labor_costs: f64, frequency: f64) -> ProcessWorkload {
+        ProcessWorkload {
+            labor_costs,
+            frequency,
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn test_calculate_savings() {
+        let members = [workload(2.0, 12.0), workload(1.5, 52.0)];
+
+        let savings = calculate_savings(&members, workload(1.0, 64.0), Some(38.0));
+
+        assert_close(savings.baseline_annual_hours, 102.0);
+        assert_close(savings.proposed_annual_hours, 64.0);
+        assert_close(savings.annual_hours_saved, 38.0);
+        assert_close(savings.reduction_percent, 38.0 / 102.0 * 100.0);
+        assert!(!savings.estimate_disagrees);
+    }
+
+    #[test]
+    fn test_calculate_savings_fractional_percent_not_rounded() {
+        let savings = calculate_savings(&[workload(1.0, 3.0)], workload(1.0, 2.0), None);
+
+        assert_close(savings.annual_hours_saved, 1.0);
+        assert_close(savings.reduction_percent, 100.0 / 3.0);
+        assert_eq!(savings.ai_estimate, None);
+        assert!(!savings.estimate_disagrees);
+    }
+
+    #[test]
+    fn test_calculate_savings_zero_frequency() {
+        // Процессы без частоты выполнения не дают базовых трудозатрат, процент сокращения не вычисляется
+        let savings = calculate_savings(&[workload(5.0, 0.0), workload(3.0, 0.0)], workload(2.0, 10.0), None);
+
+        assert_close(savings.baseline_annual_hours, 0.0);
+        assert_close(savings.annual_hours_saved, -20.0);
+        assert_close(savings.reduction_percent, 0.0);
+
+        let savings = calculate_savings(&[], workload(0.0, 0.0), None);
+        assert_close(savings.reduction_percent, 0.0);
+    }
+
+    #[test]
+    fn test_ai_estimate_disagreement() {
+        let members = [workload(10.0, 10.0)];
+        let proposed = workload(5.0, 10.0);
+
+        // Расхождение в пределах 20% от расчетной экономии допустимо
+        assert!(!calculate_savings(&members, proposed, Some(59.0)).estimate_disagrees);
+        assert!(calculate_savings(&members, proposed, Some(61.0)).estimate_disagrees);
+
+        // При малой экономии расхождение до часа в год не считается несогласием
+        let savings = calculate_savings(&[workload(1.0, 1.0)], workload(0.5, 1.0), Some(1.4));
+        assert!(!savings.estimate_disagrees);
+    }
+}
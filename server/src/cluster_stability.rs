@@ -0,0 +1,409 @@
+// cluster_stability.rs
+
+use crate::clustering_common;
+use crate::clustering_scope::ALL_PROCESSES_QUERY;
+use crate::common::get_individuals_uris_by_query;
+use crate::queue_processor::BusinessProcessAnalysisModule;
+use std::collections::HashSet;
+use v_common::onto::individual::Individual;
+use v_common::v_api::api_client::IndvOp;
+use v_common::v_api::obj::ResultCode;
+
+/// Минимальное сходство по Жаккару, при котором кластеры считаются одним и тем же кластером
+const MIN_MATCH_JACCARD: f64 = 0.5;
+
+/// Результаты оптимизации, переносимые в кластер из не изменившегося кластера предыдущей попытки:
+/// ответ v-bpa:OptimizeProcessesPrompt, расчет экономии и ссылка на версию оптимизации
+const OPTIMIZATION_PROPERTIES: &[&str] = &[
+    "rdfs:label",
+    "v-bpa:clusterReason",
+    "v-bpa:clusterSimilarities",
+    "v-bpa:clusterDifferences",
+    "v-bpa:proposedClusterName",
+    "v-bpa:proposedClusterDescription",
+    "v-bpa:proposedDepartment",
+    "v-bpa:proposedParticipants",
+    "v-bpa:proposedFrequency",
+    "v-bpa:estimatedLaborCost",
+    "v-bpa:estimatedAnnualSavings",
+    "v-bpa:baselineAnnualHours",
+    "v-bpa:proposedAnnualHours",
+    "v-bpa:annualHoursSaved",
+    "v-bpa:laborReductionPercent",
+    "v-bpa:savingsEstimateDisagrees",
+    "v-bpa:currentOptimization",
+    "v-bpa:usedPromptVersion",
+];
+
+/// Состояние кластера относительно предыдущей попытки кластеризации
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StabilityStatus {
+    New,
+    Grown,
+    Shrunk,
+    Changed,
+    Unchanged,
+}
+
+impl StabilityStatus {
+    pub fn uri(&self) -> &'static str {
+        match self {
+            StabilityStatus::New => "v-bpa:ClusterNew",
+            StabilityStatus::Grown => "v-bpa:ClusterGrown",
+            StabilityStatus::Shrunk => "v-bpa:ClusterShrunk",
+            StabilityStatus::Changed => "v-bpa:ClusterChanged",
+            StabilityStatus::Unchanged => "v-bpa:ClusterUnchanged",
+        }
+    }
+
+    /// Свойство индивида v-bpa:ClusteringDiff, в которое попадает кластер с таким состоянием
+    fn diff_predicate(&self) -> &'static str {
+        match self {
+            StabilityStatus::New => "v-bpa:newCluster",
+            StabilityStatus::Grown => "v-bpa:grownCluster",
+            StabilityStatus::Shrunk => "v-bpa:shrunkCluster",
+            StabilityStatus::Changed => "v-bpa:changedCluster",
+            StabilityStatus::Unchanged => "v-bpa:unchangedCluster",
+        }
+    }
+}
+
+/// Результат сопоставления нового кластера с кластерами предыдущей попытки
+#[derive(Debug, Clone)]
+pub struct ClusterMatch {
+    /// Индекс сопоставленного кластера предыдущей попытки
+    pub previous: Option<usize>,
+    pub jaccard: f64,
+    pub status: StabilityStatus,
+}
+
+/// Кластеры предыдущей завершенной попытки с той же областью кластеризации
+pub struct PreviousClusters {
+    pub attempt_id: String,
+    pub clusters: Vec<Individual>,
+    pub processes: Vec<HashSet<String>>,
+}
+
+/// Коэффициент Жаккара для двух множеств процессов
+pub fn jaccard(a: &HashSet<String>, b: &HashSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+/// Сопоставляет новые кластеры с кластерами предыдущей попытки
+///
+/// Пары выбираются жадно по убыванию коэффициента Жаккара, каждый кластер предыдущей попытки
+/// сопоставляется не более чем с одним новым кластером.
+pub fn match_clusters(new_clusters: &[HashSet<String>], previous_clusters: &[HashSet<String>]) -> Vec<ClusterMatch> {
+    let mut candidates = Vec::new();
+    for (new_idx, new_cluster) in new_clusters.iter().enumerate() {
+        for (prev_idx, prev_cluster) in previous_clusters.iter().enumerate() {
+            let similarity = jaccard(new_cluster, prev_cluster);
+            if similarity >= MIN_MATCH_JACCARD {
+                candidates.push((new_idx, prev_idx, similarity));
+            }
+        }
+    }
+    candidates.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut matches = vec![
+        ClusterMatch {
+            previous: None,
+            jaccard: 0.0,
+            status: StabilityStatus::New,
+        };
+        new_clusters.len()
+    ];
+    let mut used_previous = HashSet::new();
+
+    for (new_idx, prev_idx, similarity) in candidates {
+        if matches[new_idx].previous.is_some() || used_previous.contains(&prev_idx) {
+            continue;
+        }
+        used_previous.insert(prev_idx);
+
+        let new_size = new_clusters[new_idx].len();
+        let prev_size = previous_clusters[prev_idx].len();
+        let status = if new_clusters[new_idx] == previous_clusters[prev_idx] {
+            StabilityStatus::Unchanged
+        } else if new_size > prev_size {
+            StabilityStatus::Grown
+        } else if new_size < prev_size {
+            StabilityStatus::Shrunk
+        } else {
+            StabilityStatus::Changed
+        };
+
+        matches[new_idx] = ClusterMatch {
+            previous: Some(prev_idx),
+            jaccard: similarity,
+            status,
+        };
+    }
+
+    matches
+}
+
+/// Загружает кластеры последней завершенной попытки кластеризации с той же областью
+pub fn load_previous_clusters(
+    module: &mut BusinessProcessAnalysisModule,
+    clustering_attempt: &mut Individual,
+) -> Result<Option<PreviousClusters>, Box<dyn std::error::Error>> {
+    let scope_query = clustering_attempt.get_first_literal("v-bpa:scopeQuery").unwrap_or_else(|| ALL_PROCESSES_QUERY.to_string());
+
+    let attempt_ids = get_individuals_uris_by_query(
+        module,
+        &format!("'rdf:type' === 'v-bpa:ClusterizationAttempt' && 'v-bpa:hasExecutionState' === 'v-bpa:ExecutionCompleted' && '@' != '{}'", clustering_attempt.get_id()),
+    )?;
+
+    // Выбираем самую позднюю попытку с той же областью кластеризации
+    let mut latest: Option<(i64, Individual)> = None;
+    for attempt_id in attempt_ids {
+        let mut attempt = Individual::default();
+        if module.backend.storage.get_individual(&attempt_id, &mut attempt) != ResultCode::Ok {
+            warn!("Failed to load clustering attempt {}", attempt_id);
+            continue;
+        }
+        attempt.parse_all();
+
+        if attempt.get_first_literal("v-bpa:scopeQuery").unwrap_or_else(|| ALL_PROCESSES_QUERY.to_string()) != scope_query {
+            continue;
+        }
+
+        let end_date = attempt.get_first_datetime("v-bpa:endDate").unwrap_or_default();
+        if latest.as_ref().is_none_or(|(latest_end, _)| end_date > *latest_end) {
+            latest = Some((end_date, attempt));
+        }
+    }
+
+    let mut previous_attempt = match latest {
+        Some((_, attempt)) => attempt,
+        None => {
+            info!("No previous completed clustering attempt with scope [{}]", scope_query);
+            return Ok(None);
+        },
+    };
+
+    let mut previous = PreviousClusters {
+        attempt_id: previous_attempt.get_id().to_string(),
+        clusters: Vec::new(),
+        processes: Vec::new(),
+    };
+
+    for cluster_id in previous_attempt.get_literals("v-bpa:foundClusters").unwrap_or_default() {
+        let mut cluster = Individual::default();
+        if module.backend.storage.get_individual(&cluster_id, &mut cluster) != ResultCode::Ok {
+            warn!("Failed to load previous cluster {}", cluster_id);
+            continue;
+        }
+        cluster.parse_all();
+
+        previous.processes.push(cluster.get_literals("v-bpa:hasProcess").unwrap_or_default().into_iter().collect());
+        previous.clusters.push(cluster);
+    }
+
+    info!("Loaded {} clusters of previous clustering attempt {}", previous.clusters.len(), previous.attempt_id);
+    Ok(Some(previous))
+}
+
+/// Записывает в кластер его состояние относительно предыдущей попытки
+pub fn set_cluster_stability(cluster: &mut Individual, cluster_match: &ClusterMatch, previous: Option<&PreviousClusters>) {
+    cluster.set_uri("v-bpa:hasStabilityStatus", cluster_match.status.uri());
+    cluster.remove("v-bpa:previousCluster");
+    cluster.remove("v-bpa:stabilityJaccard");
+
+    if let (Some(prev_idx), Some(previous)) = (cluster_match.previous, previous) {
+        cluster.set_uri("v-bpa:previousCluster", previous.clusters[prev_idx].get_id());
+        cluster.set_decimal_from_f64("v-bpa:stabilityJaccard", cluster_match.jaccard);
+    }
+}
+
+/// Переносит в кластер результаты оптимизации кластера предыдущей попытки
+///
+/// Состояние проверки, предложения AI, принятое объединение и отметки консенсуса не переносятся:
+/// они относятся к предыдущему кластеру
+pub fn copy_optimization_results(cluster: &mut Individual, previous_cluster: &Individual) {
+    let resources = previous_cluster.get_obj().get_resources();
+    for predicate in OPTIMIZATION_PROPERTIES {
+        if let Some(values) = resources.get(*predicate) {
+            cluster.set_resources(predicate, values);
+        }
+    }
+}
+
+/// Создает индивид v-bpa:ClusteringDiff с изменениями кластеров относительно предыдущей попытки
+///
+/// # Arguments
+/// * `new_clusters` - Идентификаторы созданных кластеров и результаты их сопоставления
+pub fn create_clustering_diff(
+    module: &mut BusinessProcessAnalysisModule,
+    clustering_attempt: &mut Individual,
+    previous: Option<&PreviousClusters>,
+    new_clusters: &[(String, ClusterMatch)],
+    event_id: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut diff = Individual::default();
+    let diff_id = format!("d:bpa_clustering_diff_{}", uuid::Uuid::new_v4());
+    diff.set_id(&diff_id);
+    diff.set_uri("rdf:type", "v-bpa:ClusteringDiff");
+    diff.set_uri("v-bpa:currentAttempt", clustering_attempt.get_id());
+
+    for (cluster_id, cluster_match) in new_clusters {
+        diff.add_uri(cluster_match.status.diff_predicate(), cluster_id);
+    }
+
+    if let Some(previous) = previous {
+        diff.set_uri("v-bpa:previousAttempt", &previous.attempt_id);
+
+        let matched: HashSet<usize> = new_clusters.iter().filter_map(|(_, m)| m.previous).collect();
+        for (idx, cluster) in previous.clusters.iter().enumerate() {
+            if !matched.contains(&idx) {
+                diff.add_uri("v-bpa:dissolvedCluster", cluster.get_id());
+            }
+        }
+    }
+
+    clustering_common::update_individual(module, &mut diff, IndvOp::Put, event_id)?;
+
+    clustering_attempt.set_uri("v-bpa:hasClusteringDiff", &diff_id);
+    info!("Created clustering diff {} for attempt {}", diff_id, clustering_attempt.get_id());
+
+    Ok(diff_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use v_common::onto::datatype::Lang;
+
+    fn set(processes: &[&str]) -> HashSet<String> {
+        processes.iter().map(|p| p.to_string()).collect()
+    }
+
+    #[test]
+    fn test_jaccard() {
+        assert_eq!(jaccard(&set(&["a", "b"]), &set(&["a", "b"])), 1.0);
+        assert_eq!(jaccard(&set(&["a", "b", "c"]), &set(&["b", "c", "d"])), 0.5);
+        assert_eq!(jaccard(&set(&["a"]), &set(&["b"])), 0.0);
+        assert_eq!(jaccard(&set(&[]), &set(&[])), 0.0);
+        assert_eq!(jaccard(&set(&["a"]), &set(&[])), 0.0);
+    }
+
+    #[test]
+    fn test_match_clusters_statuses() {
+        let new_clusters = vec![set(&["a", "b"]), set(&["c", "d", "e"]), set(&["f", "g"]), set(&["h", "i", "x"]), set(&["m", "n"])];
+        let previous_clusters = vec![set(&["a", "b"]), set(&["c", "d"]), set(&["f", "g", "k"]), set(&["h", "i", "j"]), set(&["m", "p", "q"])];
+
+        let matches = match_clusters(&new_clusters, &previous_clusters);
+
+        let statuses: Vec<StabilityStatus> = matches.iter().map(|m| m.status).collect();
+        assert_eq!(statuses, vec![StabilityStatus::Unchanged, StabilityStatus::Grown, StabilityStatus::Shrunk, StabilityStatus::Changed, StabilityStatus::New]);
+        assert_eq!(matches.iter().map(|m| m.previous).collect::<Vec<_>>(), vec![Some(0), Some(1), Some(2), Some(3), None]);
+        // Сходство ровно 0.5 достаточно для сопоставления
+        assert_eq!(matches[3].jaccard, 0.5);
+        assert_eq!(matches[4].jaccard, 0.0);
+    }
+
+    #[test]
+    fn test_match_clusters_greedy_by_similarity() {
+        // Предыдущий кластер достается новому кластеру с наибольшим сходством, даже если тот идет позже
+        let new_clusters = vec![set(&["a", "b", "c", "x"]), set(&["a", "b", "c"])];
+        let previous_clusters = vec![set(&["a", "b", "c"])];
+
+        let matches = match_clusters(&new_clusters, &previous_clusters);
+
+        assert_eq!(matches[0].previous, None);
+        assert_eq!(matches[0].status, StabilityStatus::New);
+        assert_eq!(matches[1].previous, Some(0));
+        assert_eq!(matches[1].status, StabilityStatus::Unchanged);
+    }
+
+    #[test]
+    fn test_match_clusters_ties() {
+        // При равном сходстве выигрывает пара, найденная первой: меньший индекс нового, затем предыдущего кластера
+        let new_clusters = vec![set(&["a", "b", "c", "d"])];
+        let previous_clusters = vec![set(&["a", "b", "c"]), set(&["b", "c", "d"])];
+
+        let matches = match_clusters(&new_clusters, &previous_clusters);
+        assert_eq!(matches[0].previous, Some(0));
+        assert_eq!(matches[0].status, StabilityStatus::Grown);
+
+        let new_clusters = vec![set(&["a", "b"]), set(&["a", "b"])];
+        let previous_clusters = vec![set(&["a", "b"])];
+
+        let matches = match_clusters(&new_clusters, &previous_clusters);
+        assert_eq!(matches[0].previous, Some(0));
+        assert_eq!(matches[1].previous, None);
+        assert_eq!(matches[1].status, StabilityStatus::New);
+    }
+
+    #[test]
+    fn test_match_clusters_split() {
+        // Из двух частей разделенного кластера с предыдущим сопоставляется только одна
+        let new_clusters = vec![set(&["a", "b"]), set(&["c", "d"])];
+        let previous_clusters = vec![set(&["a", "b", "c", "d"])];
+
+        let matches = match_clusters(&new_clusters, &previous_clusters);
+
+        assert_eq!(matches[0].previous, Some(0));
+        assert_eq!(matches[0].status, StabilityStatus::Shrunk);
+        assert_eq!(matches[1].previous, None);
+        assert_eq!(matches[1].status, StabilityStatus::New);
+    }
+
+    #[test]
+    fn test_match_clusters_merge() {
+        // Объединенный кластер сопоставляется с наиболее похожим из предыдущих кластеров
+        let new_clusters = vec![set(&["a", "b", "c", "d", "e"])];
+        let previous_clusters = vec![set(&["a", "b"]), set(&["c", "d", "e"])];
+
+        let matches = match_clusters(&new_clusters, &previous_clusters);
+
+        assert_eq!(matches[0].previous, Some(1));
+        assert_eq!(matches[0].jaccard, 0.6);
+        assert_eq!(matches[0].status, StabilityStatus::Grown);
+    }
+
+    #[test]
+    fn test_match_clusters_empty() {
+        assert!(match_clusters(&[], &[set(&["a", "b"])]).is_empty());
+
+        let matches = match_clusters(&[set(&["a", "b"])], &[]);
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].status, StabilityStatus::New);
+
+        // Пустые кластеры ни с чем не сопоставляются
+        let matches = match_clusters(&[set(&[])], &[set(&[])]);
+        assert_eq!(matches[0].previous, None);
+        assert_eq!(matches[0].status, StabilityStatus::New);
+    }
+
+    #[test]
+    fn test_copy_optimization_results() {
+        let mut previous_cluster = Individual::default();
+        previous_cluster.set_id("d:previous_cluster");
+        previous_cluster.set_string("v-bpa:proposedClusterName", "Merged process", Lang::none());
+        previous_cluster.set_integer("v-bpa:proposedFrequency", 12);
+        previous_cluster.set_decimal_from_f64("v-bpa:annualHoursSaved", 120.5);
+        previous_cluster.set_uri("v-bpa:currentOptimization", "d:optimization_1");
+        previous_cluster.set_uri("v-bpa:hasReviewState", "v-bpa:ReviewApproved");
+        previous_cluster.set_uri("v-bpa:mergedProcess", "d:merged");
+        previous_cluster.set_uri("v-bpa:hasProposalStatus", "v-bpa:ProposalAccepted");
+
+        let mut cluster = Individual::default();
+        cluster.set_id("d:cluster");
+        copy_optimization_results(&mut cluster, &previous_cluster);
+
+        assert_eq!(cluster.get_id(), "d:cluster");
+        assert_eq!(cluster.get_first_literal("v-bpa:proposedClusterName").as_deref(), Some("Merged process"));
+        assert_eq!(cluster.get_first_integer("v-bpa:proposedFrequency"), Some(12));
+        assert_eq!(cluster.get_first_float("v-bpa:annualHoursSaved"), Some(120.5));
+        assert_eq!(cluster.get_first_literal("v-bpa:currentOptimization").as_deref(), Some("d:optimization_1"));
+        assert_eq!(cluster.get_first_literal("v-bpa:hasReviewState"), None);
+        assert_eq!(cluster.get_first_literal("v-bpa:mergedProcess"), None);
+        assert_eq!(cluster.get_first_literal("v-bpa:hasProposalStatus"), None);
+    }
+}
@@ -1,8 +1,10 @@
 use crate::ai_client::save_to_interaction_file;
-use crate::common::calculate_cost;
+use crate::common::{calculate_cost, ClientType};
 use crate::queue_processor::BusinessProcessAnalysisModule;
+use openai_dive::v1::api::Client;
 use openai_dive::v1::resources::chat::{ChatCompletionParametersBuilder, ChatCompletionResponseFormat, ChatMessage, ChatMessageContent, JsonSchemaBuilder};
 use std::io;
+use tokio::runtime::Runtime;
 use v_common::onto::individual::Individual;
 use v_common::v_api::api_client::IndvOp;
 
@@ -42,14 +44,45 @@ pub fn prepare_comparison_parameters(
     Ok(parameters)
 }
 
-/// Отправляет запрос к API AI и получает результат сравнения
-pub async fn send_comparison_request(
+/// Отправляет пакет запросов сравнения к API AI одновременно
+///
+/// Результаты возвращаются в том же порядке, что и параметры запросов.
+/// Если хотя бы одно сравнение завершилось ошибкой, возвращается ошибка для всего пакета,
+/// чтобы вызывающий код не сдвигал позицию сравнения.
+pub fn send_comparison_requests_concurrently(
     module: &mut BusinessProcessAnalysisModule,
-    parameters: openai_dive::v1::resources::chat::ChatCompletionParameters,
-) -> Result<bool, Box<dyn std::error::Error>> {
-    save_to_interaction_file(&serde_json::to_string_pretty(&parameters)?, "comparison_request", "json")?;
+    parameters: Vec<openai_dive::v1::resources::chat::ChatCompletionParameters>,
+) -> Result<Vec<bool>, Box<dyn std::error::Error>> {
+    let rt = Runtime::new()?;
 
-    let result = module.default_client.chat().create(parameters).await?;
+    let handles: Vec<_> = parameters
+        .into_iter()
+        .map(|params| {
+            let client = match ClientType::for_model(module, &params.model) {
+                ClientType::Default => module.default_client.clone(),
+                ClientType::Reasoning => module.reasoning_client.clone(),
+            };
+            let model = params.model.clone();
+            rt.spawn(request_comparison(client, model, params))
+        })
+        .collect();
+
+    let results = rt.block_on(async {
+        let mut results = Vec::with_capacity(handles.len());
+        for handle in handles {
+            results.push(handle.await.unwrap_or_else(|e| Err(e.to_string())));
+        }
+        results
+    });
+
+    results.into_iter().map(|r| r.map_err(|e| e.into())).collect()
+}
+
+/// Выполняет один запрос сравнения через указанный клиент
+async fn request_comparison(client: Client, model: String, parameters: openai_dive::v1::resources::chat::ChatCompletionParameters) -> Result<bool, String> {
+    save_to_interaction_file(&serde_json::to_string_pretty(&parameters).map_err(|e| e.to_string())?, "comparison_request", "json").map_err(|e| e.to_string())?;
+
+    let result = client.chat().create(parameters).await.map_err(|e| e.to_string())?;
 
     if let Some(usage) = result.usage {
         info!(
@@ -57,7 +90,7 @@ pub async fn send_comparison_request(
             usage.prompt_tokens,
             usage.completion_tokens.unwrap_or(0),
             usage.total_tokens,
-            calculate_cost(usage.total_tokens as f64, &module.default_model)
+            calculate_cost(usage.total_tokens as f64, &model)
         );
     }
 
@@ -67,18 +100,18 @@ pub async fn send_comparison_request(
             ..
         } = &choice.message
         {
-            save_to_interaction_file(text, "comparison_response", "json")?;
+            save_to_interaction_file(text, "comparison_response", "json").map_err(|e| e.to_string())?;
 
-            let response: serde_json::Value = serde_json::from_str(text)?;
+            let response: serde_json::Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
             let similarity = response["are_similar"].as_bool().unwrap_or(false);
             Ok(similarity)
         } else {
             error!("Unexpected message format in AI response");
-            Err("Unexpected message format".into())
+            Err("Unexpected message format".to_string())
         }
     } else {
         error!("No response received from AI");
-        Err("No response from AI".into())
+        Err("No response from AI".to_string())
     }
 }
 
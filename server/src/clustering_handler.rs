@@ -1,16 +1,29 @@
+use crate::cluster_metrics::{self, ClusterQuality};
+use crate::cluster_stability::{self, ClusterMatch, PreviousClusters, StabilityStatus};
 use crate::clustering_common;
-use crate::common::{extract_process_json, format_time, generate_event_id, get_individuals_uris_by_query, get_individuals_uris_by_type};
-use crate::prompt_manager::get_system_prompt;
+use crate::clustering_scope;
+use crate::common::{extract_process_json, format_time, generate_event_id, get_individuals_uris_by_query};
+use crate::consensus::{self, ConsensusConfig};
+use crate::prompt_manager::{get_system_prompt, PromptContext};
+use crate::prompt_version::resolve_prompt_version;
 use crate::queue_processor::BusinessProcessAnalysisModule;
 use serde_json;
 use std::collections::{HashMap, HashSet};
 use std::io;
-use tokio::runtime::Runtime;
 use v_common::onto::datatype::Lang;
 use v_common::onto::individual::Individual;
 use v_common::v_api::api_client::IndvOp;
 use v_common::v_api::obj::ResultCode;
 
+/// Количество одновременно сравниваемых пар по умолчанию
+const DEFAULT_COMPARISON_CONCURRENCY: usize = 4;
+/// Максимальное количество одновременно сравниваемых пар
+const MAX_COMPARISON_CONCURRENCY: usize = 16;
+/// Время без активности, после которого блокировка попытки кластеризации истекает (секунды)
+const ATTEMPT_LOCK_LEASE: i64 = 600;
+/// Промпт сравнения пар процессов
+const CLUSTERIZE_PROMPT: &str = "v-bpa:ClusterizeProcessesPrompt";
+
 /// Результат сравнения пары процессов
 #[derive(Debug)]
 enum ComparisonResult {
@@ -25,6 +38,29 @@ struct ComparisonState {
     y: usize,
     last_metrics_calc: i64, // Timestamp последнего вычисления метрик
     last_progress: i64,
+    prompt_version: String, // Версия промпта сравнения, закрепленная за попыткой
+    consensus: ConsensusConfig,
+}
+
+impl ComparisonState {
+    /// Создает состояние сравнения с пары (x, y); настройки консенсуса загружаются один раз на попытку
+    fn new(module: &mut BusinessProcessAnalysisModule, prompt_version: String, x: usize, y: usize) -> Result<Self, Box<dyn std::error::Error>> {
+        let consensus = ConsensusConfig::load(module, &prompt_version)?;
+        Ok(ComparisonState {
+            x,
+            y,
+            last_metrics_calc: chrono::Utc::now().timestamp(),
+            last_progress: 0,
+            prompt_version,
+            consensus,
+        })
+    }
+}
+
+/// Общие для всех кластеров попытки данные, используемые при создании кластера
+struct ClusterBuildContext<'a> {
+    previous: Option<&'a PreviousClusters>,
+    confidence_threshold: f64, // Порог уверенности консенсуса версии промпта попытки
 }
 
 /// Обновляет временные метки для отслеживания активности процесса кластеризации
@@ -51,6 +87,73 @@ fn update_activity_timestamps(clustering_attempt: &mut Individual, status: &str)
     Ok(())
 }
 
+/// Захватывает блокировку попытки кластеризации
+///
+/// Попытки кластеризации выполняются независимо друг от друга, блокируется только сама попытка.
+/// Блокировка принадлежит обработчику, записавшему свой v-bpa:lockOwner, и действует, пока
+/// v-bpa:lastActivityAt обновлялось не позднее ATTEMPT_LOCK_LEASE секунд назад, в каком бы состоянии ни была попытка.
+/// Блокировку аварийно завершившегося обработчика можно перехватить после истечения аренды.
+///
+/// Хранилище не поддерживает атомарную запись с проверкой, поэтому после записи владелец перечитывается:
+/// если одновременно записался другой обработчик, блокировка остается за ним.
+///
+/// Возвращает false, если попытку выполняет другой обработчик
+fn acquire_attempt_lock(module: &mut BusinessProcessAnalysisModule, clustering_attempt: &mut Individual, event_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let current_time = chrono::Utc::now().timestamp();
+
+    let mut current_state = Individual::default();
+    if module.backend.storage.get_individual(clustering_attempt.get_id(), &mut current_state) == ResultCode::Ok {
+        current_state.parse_all();
+
+        if let Some(owner) = current_state.get_first_literal("v-bpa:lockOwner") {
+            let last_activity = current_state.get_first_datetime("v-bpa:lastActivityAt").unwrap_or_default();
+            if current_time - last_activity < ATTEMPT_LOCK_LEASE {
+                info!("Clustering attempt {} is already being processed by {}, skipping", clustering_attempt.get_id(), owner);
+                return Ok(false);
+            }
+            warn!(
+                "Lock of clustering attempt {} held by {} expired (no activity for {}), taking over",
+                clustering_attempt.get_id(),
+                owner,
+                format_time(current_time - last_activity)
+            );
+        }
+    }
+
+    let lock_owner = uuid::Uuid::new_v4().to_string();
+    clustering_attempt.set_string("v-bpa:lockOwner", &lock_owner, Lang::none());
+    clustering_attempt.set_datetime("v-bpa:lastActivityAt", current_time);
+    clustering_common::update_individual(module, clustering_attempt, IndvOp::SetIn, event_id)?;
+
+    let mut stored_state = Individual::default();
+    if module.backend.storage.get_individual(clustering_attempt.get_id(), &mut stored_state) == ResultCode::Ok {
+        stored_state.parse_all();
+        if let Some(owner) = stored_state.get_first_literal("v-bpa:lockOwner").filter(|owner| *owner != lock_owner) {
+            info!("Clustering attempt {} was locked by {} at the same time, skipping", clustering_attempt.get_id(), owner);
+            clustering_attempt.remove("v-bpa:lockOwner");
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/// Освобождает блокировку попытки, когда попытка завершена, приостановлена или завершилась ошибкой
+fn release_attempt_lock(module: &mut BusinessProcessAnalysisModule, clustering_attempt: &mut Individual, event_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let owner = match clustering_attempt.get_first_literal("v-bpa:lockOwner") {
+        Some(owner) => owner,
+        None => return Ok(()),
+    };
+
+    let mut lock = Individual::default();
+    lock.set_id(clustering_attempt.get_id());
+    lock.set_string("v-bpa:lockOwner", &owner, Lang::none());
+    clustering_common::update_individual(module, &mut lock, IndvOp::RemoveFrom, event_id)?;
+
+    clustering_attempt.remove("v-bpa:lockOwner");
+    Ok(())
+}
+
 /// Проверяет наличие команд управления процессом
 fn check_control_action(module: &mut BusinessProcessAnalysisModule, clustering_attempt: &mut Individual, event_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
     // Get fresh state from storage
@@ -60,6 +163,13 @@ fn check_control_action(module: &mut BusinessProcessAnalysisModule, clustering_a
         return Err(format!("Failed to load current state for attempt {}", clustering_attempt.get_id()).into());
     }
 
+    // Проверяем, что блокировку не перехватил другой обработчик
+    let owner = current_state.get_first_literal("v-bpa:lockOwner");
+    if owner.is_some() && owner != clustering_attempt.get_first_literal("v-bpa:lockOwner") {
+        warn!("Lock of clustering attempt {} was taken over by {:?}, stopping", clustering_attempt.get_id(), owner);
+        return Ok(false);
+    }
+
     // Check control action from fresh state
     if let Some(control_action) = current_state.get_first_literal("v-bpa:controlAction") {
         match control_action.as_str() {
@@ -69,6 +179,7 @@ fn check_control_action(module: &mut BusinessProcessAnalysisModule, clustering_a
                 clustering_attempt.set_uri("v-bpa:hasExecutionState", "v-bpa:ExecutionPaused");
                 update_activity_timestamps(clustering_attempt, "v-bpa:Paused")?;
                 clustering_common::update_individual(module, clustering_attempt, IndvOp::SetIn, event_id)?;
+                release_attempt_lock(module, clustering_attempt, event_id)?;
                 return Ok(false);
             },
             "v-bpa:CancelExecution" => {
@@ -77,6 +188,7 @@ fn check_control_action(module: &mut BusinessProcessAnalysisModule, clustering_a
                 clustering_attempt.set_uri("v-bpa:hasExecutionState", "v-bpa:ExecutionTerminated");
                 update_activity_timestamps(clustering_attempt, "v-bpa:Cancelled")?;
                 clustering_common::update_individual(module, clustering_attempt, IndvOp::SetIn, event_id)?;
+                release_attempt_lock(module, clustering_attempt, event_id)?;
                 return Ok(false);
             },
             _ => (),
@@ -108,26 +220,16 @@ pub fn analyze_process_clusters(
         Some(s) => s,
         None => return Ok(()),
     };
-    let ca = get_individuals_uris_by_query(
-        module,
-        &format!("'rdf:type' == 'v-bpa:ClusterizationAttempt' && 'v-bpa:hasExecutionState' == 'v-bpa:ExecutionInProgress' && '@' != '{}'", clustering_attempt.get_id()),
-    )?;
-    if !ca.is_empty() {
-        let error_msg = "Невозможно начать расчет - уже существует активный процесс кластеризации";
-        clustering_attempt.set_string("v-bpa:lastError", error_msg, Lang::none());
-        clustering_attempt.set_uri("v-bpa:hasClusterizationStatus", "v-bpa:Failed");
-        clustering_attempt.set_uri("v-bpa:hasExecutionState", "v-bpa:ExecutionError");
-        update_activity_timestamps(clustering_attempt, "v-bpa:Failed")?;
-        clustering_common::update_individual(module, clustering_attempt, IndvOp::SetIn, &event_id)?;
-        return Err(error_msg.into());
-    }
-
     let mut comparison_state = None;
 
     if !clustering_attempt.any_exists("v-bpa:controlAction", &["v-bpa:StartExecution", "v-bpa:ResumeExecution"]) {
         return Ok(());
     }
 
+    if !acquire_attempt_lock(module, clustering_attempt, &event_id)? {
+        return Ok(());
+    }
+
     info!("Starting process cluster analysis for attempt: {}", clustering_attempt.get_id());
 
     loop {
@@ -143,16 +245,11 @@ pub fn analyze_process_clusters(
             "" => {
                 info!("Starting new clustering attempt: {}", clustering_attempt.get_id());
                 match initialize_clustering(module, clustering_attempt, &event_id) {
-                    Ok(_) => {
+                    Ok(state) => {
                         update_activity_timestamps(clustering_attempt, "")?;
                         clustering_attempt.set_uri("v-bpa:hasExecutionState", "v-bpa:ExecutionInProgress");
                         clustering_common::update_individual(module, clustering_attempt, IndvOp::SetIn, &event_id)?;
-                        comparison_state = Some(ComparisonState {
-                            x: 0,
-                            y: 1,
-                            last_metrics_calc: chrono::Utc::now().timestamp(),
-                            last_progress: 0,
-                        });
+                        comparison_state = Some(state);
                     },
                     Err(e) => handle_error(module, clustering_attempt, &event_id, e)?,
                 }
@@ -174,6 +271,7 @@ pub fn analyze_process_clusters(
                         clustering_attempt.set_uri("v-bpa:controlAction", "v-bpa:NoActionExecution");
 
                         clustering_common::update_individual(module, clustering_attempt, IndvOp::SetIn, &event_id)?;
+                        release_attempt_lock(module, clustering_attempt, &event_id)?;
                         return Ok(());
                     }
                 }
@@ -186,15 +284,11 @@ pub fn analyze_process_clusters(
 
                     let indices: Vec<usize> = current_pair.split(',').map(|s| s.parse::<usize>()).collect::<Result<Vec<_>, _>>()?;
 
-                    comparison_state = Some(ComparisonState {
-                        x: indices[0],
-                        y: indices[1],
-                        last_metrics_calc: chrono::Utc::now().timestamp(),
-                        last_progress: 0,
-                    });
+                    let prompt_version = resolve_prompt_version(module, CLUSTERIZE_PROMPT, Some(&mut *clustering_attempt));
+                    comparison_state = Some(ComparisonState::new(module, prompt_version, indices[0], indices[1])?);
                 }
 
-                match compare_next_pair(module, clustering_attempt, comparison_state.as_mut().unwrap(), &event_id) {
+                match compare_next_pairs(module, clustering_attempt, comparison_state.as_mut().unwrap(), &event_id) {
                     Ok(ComparisonResult::Completed) => {
                         clustering_attempt.set_uri("v-bpa:hasClusterizationStatus", "v-bpa:PairsCompared");
                         update_activity_timestamps(clustering_attempt, "v-bpa:PairsCompared")?;
@@ -216,6 +310,7 @@ pub fn analyze_process_clusters(
                         clustering_attempt.set_uri("v-bpa:controlAction", "v-bpa:NoActionExecution");
 
                         clustering_common::update_individual(module, clustering_attempt, IndvOp::SetIn, &event_id)?;
+                        release_attempt_lock(module, clustering_attempt, &event_id)?;
                     },
                     Err(e) => handle_error(module, clustering_attempt, &event_id, e)?,
                 }
@@ -242,6 +337,7 @@ pub fn analyze_process_clusters(
 
                 update_activity_timestamps(clustering_attempt, "v-bpa:Failed")?;
                 clustering_common::update_individual(module, clustering_attempt, IndvOp::SetIn, &event_id)?;
+                release_attempt_lock(module, clustering_attempt, &event_id)?;
                 return Err("Invalid clustering status".into());
             },
         }
@@ -305,13 +401,22 @@ fn calculate_clustering_metrics(clustering_attempt: &mut Individual, state: &Com
 }
 
 /// Инициализирует процесс кластеризации
-/// - Загружает все бизнес-процессы
+/// - Загружает бизнес-процессы из области попытки (подразделения, список процессов, фильтр)
 /// - Подготавливает состояние для сравнения
 /// - Устанавливает начальные значения прогресса
-fn initialize_clustering(module: &mut BusinessProcessAnalysisModule, clustering_attempt: &mut Individual, event_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+fn initialize_clustering(
+    module: &mut BusinessProcessAnalysisModule,
+    clustering_attempt: &mut Individual,
+    event_id: &str,
+) -> Result<ComparisonState, Box<dyn std::error::Error>> {
     info!("Initializing clustering process");
 
-    let process_ids = get_individuals_uris_by_type(module, "v-bpa:BusinessProcess")?;
+    let query = clustering_scope::build_process_query(module, clustering_attempt)?;
+    info!("Selecting processes for clustering with query: {}", query);
+    clustering_attempt.set_string("v-bpa:scopeQuery", &query, Lang::none());
+
+    let process_ids = get_individuals_uris_by_query(module, &query)?;
+    let process_ids = clustering_scope::exclude_superseded(module, process_ids);
 
     if process_ids.is_empty() {
         error!("Clustering initialization failed: no business processes found");
@@ -328,8 +433,16 @@ fn initialize_clustering(module: &mut BusinessProcessAnalysisModule, clustering_
     info!("Setting initial clustering status to ComparingPairs");
     clustering_attempt.set_uri("v-bpa:hasClusterizationStatus", "v-bpa:ComparingPairs");
     clustering_attempt.remove("v-bpa:similarPairs");
+    clustering_attempt.remove("v-bpa:similarPairConfidence");
+    clustering_attempt.remove("v-bpa:uncertainPairs");
+    clustering_attempt.remove("v-bpa:hasReviewTag");
     clustering_attempt.remove("v-bpa:controlAction");
 
+    // Все пары попытки сравниваются одной версией промпта, даже если промпт изменится во время кластеризации
+    let prompt_version = resolve_prompt_version(module, CLUSTERIZE_PROMPT, Some(&mut *clustering_attempt));
+    info!("Clustering attempt {} uses prompt version {}", clustering_attempt.get_id(), prompt_version);
+    let state = ComparisonState::new(module, prompt_version, 0, 1)?;
+
     // Инициализируем начальные значения прогресса и времени
     clustering_attempt.set_integer("v-bpa:clusterizationProgress", 0);
     clustering_attempt.set_integer("v-bpa:estimatedTime", ((process_len * (process_len - 1)) / 2 * 1) as i64);
@@ -337,15 +450,19 @@ fn initialize_clustering(module: &mut BusinessProcessAnalysisModule, clustering_
     clustering_common::update_individual(module, clustering_attempt, IndvOp::Put, event_id)?;
     info!("Successfully initialized clustering attempt {} with {} processes", clustering_attempt.get_id(), process_len);
 
-    Ok(())
+    Ok(state)
 }
 
-/// Сравнивает следующую пару процессов и обновляет состояние
+/// Сравнивает следующий пакет пар процессов и обновляет состояние
 /// Возвращает:
 /// - Completed если все пары сравнены
 /// - Continue если есть еще пары для сравнения
 /// - Ошибку при проблемах сравнения
-fn compare_next_pair(
+///
+/// Пары пакета сравниваются одновременно, размер пакета задается v-bpa:comparisonConcurrency.
+/// Позиция v-bpa:currentPairIndex сдвигается только после получения результатов всего пакета,
+/// поэтому приостановка и возобновление продолжают работу с первой несравненной пары.
+fn compare_next_pairs(
     module: &mut BusinessProcessAnalysisModule,
     clustering_attempt: &mut Individual,
     state: &mut ComparisonState,
@@ -358,44 +475,68 @@ fn compare_next_pair(
         return Ok(ComparisonResult::Completed);
     }
 
+    // Формируем пакет пар начиная с текущей позиции, при консенсусе каждая пара занимает несколько запросов
+    let consensus = &state.consensus;
+    let batch_size = get_batch_size(get_comparison_concurrency(clustering_attempt), consensus.samples.len());
+    let (batch, next) = next_pair_batch((state.x, state.y), processes.len(), batch_size);
+
     // Замеряем время начала сравнения
     let comparison_start = chrono::Utc::now().timestamp();
 
-    // Сравниваем текущую пару
-    let is_similar = compare_processes(module, &processes[state.x], &processes[state.y])?;
+    // Подготавливаем запросы для всех пар пакета
+    let system_prompt = get_system_prompt(module, &state.prompt_version, &PromptContext::new())?;
+    let mut parameters = Vec::with_capacity(batch.len() * consensus.samples.len());
+    for (x, y) in &batch {
+        let pair_parameters = prepare_pair_comparison_parameters(module, &processes[*x], &processes[*y], &system_prompt)?;
+        parameters.extend(consensus.sample_parameters(&pair_parameters));
+    }
+
+    // Сравниваем пары пакета одновременно и выбираем вердикт каждой пары голосованием выборок
+    let sample_verdicts = clustering_common::send_comparison_requests_concurrently(module, parameters)?;
+    let verdicts: Vec<(bool, f64)> = sample_verdicts
+        .chunks(consensus.samples.len())
+        .map(|chunk| {
+            let votes: Vec<(bool, f64)> = chunk.iter().zip(&consensus.samples).map(|(verdict, sample)| (*verdict, sample.weight)).collect();
+            consensus::vote(&votes).unwrap_or((false, 0.0))
+        })
+        .collect();
 
     // Считаем время сравнения
     let comparison_time = chrono::Utc::now().timestamp() - comparison_start;
 
-    info!(
-        "Comparison result for processes {} and {}: {} (took {})",
-        processes[state.x],
-        processes[state.y],
-        if is_similar {
-            "similar"
-        } else {
-            "different"
-        },
-        format_time(comparison_time)
-    );
+    let mut recorded_pairs = false;
+    for ((x, y), (is_similar, confidence)) in batch.iter().zip(verdicts) {
+        info!(
+            "Comparison result for processes {} and {}: {} (confidence {:.2})",
+            processes[*x],
+            processes[*y],
+            if is_similar {
+                "similar"
+            } else {
+                "different"
+            },
+            confidence
+        );
 
-    if is_similar {
-        let pair = format!("{},{}", processes[state.x], processes[state.y]);
-        clustering_attempt.add_string("v-bpa:similarPairs", &pair, Lang::none());
+        if record_pair_verdict(clustering_attempt, &processes[*x], &processes[*y], is_similar, confidence, consensus.threshold) {
+            recorded_pairs = true;
+        }
     }
+    info!("Compared batch of {} pairs (took {})", batch.len(), format_time(comparison_time));
 
     let old_x = state.x;
-    if state.y + 1 < processes.len() {
-        state.y += 1;
-    } else {
-        state.x += 1;
-        state.y = state.x + 1;
-    }
+    state.x = next.0;
+    state.y = next.1;
 
     // Вычисляем метрики если прошло больше 3 секунд или другие условия
     let current_time = chrono::Utc::now().timestamp();
     let progress = calculate_progress(state, processes.len());
-    if (current_time - state.last_metrics_calc) >= 1 || is_similar || state.x != old_x || (state.x == 0 && state.y == 2) || state.last_progress != progress {
+    if (current_time - state.last_metrics_calc) >= 1
+        || recorded_pairs
+        || state.x != old_x
+        || (old_x == 0 && batch.first() == Some(&(0, 1)))
+        || state.last_progress != progress
+    {
         let estimated_time = calculate_clustering_metrics(clustering_attempt, state, processes.len())?;
 
         // Сохраняем метрики в базу только если нашли похожие процессы или изменился x
@@ -412,8 +553,71 @@ fn compare_next_pair(
     Ok(ComparisonResult::Continue)
 }
 
-/// Сравнивает два процесса с помощью AI
-fn compare_processes(module: &mut BusinessProcessAnalysisModule, process1_id: &str, process2_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+/// Записывает вердикт пары в попытку кластеризации, возвращает true, если попытка изменилась
+///
+/// Похожая пара попадает в v-bpa:similarPairs, ее уверенность - в v-bpa:similarPairConfidence, если выборки разошлись.
+/// Отрицательный вердикт с уверенностью ниже порога не попадает ни в один кластер, поэтому пара сохраняется
+/// в v-bpa:uncertainPairs, а попытка помечается v-bpa:NeedsReview.
+fn record_pair_verdict(clustering_attempt: &mut Individual, process1: &str, process2: &str, is_similar: bool, confidence: f64, threshold: f64) -> bool {
+    let pair = format!("{},{}", process1, process2);
+    if is_similar {
+        clustering_attempt.add_string("v-bpa:similarPairs", &pair, Lang::none());
+        if confidence < 1.0 {
+            clustering_attempt.add_string("v-bpa:similarPairConfidence", &format!("{},{:.4}", pair, confidence), Lang::none());
+        }
+        return true;
+    }
+
+    if confidence < threshold {
+        info!("Uncertain negative verdict for processes {} (confidence {:.2}), marking attempt for review", pair, confidence);
+        clustering_attempt.add_string("v-bpa:uncertainPairs", &format!("{},{:.4}", pair, confidence), Lang::none());
+        clustering_attempt.set_uri("v-bpa:hasReviewTag", "v-bpa:NeedsReview");
+        return true;
+    }
+
+    false
+}
+
+/// Возвращает индекс пары, следующей за указанной
+fn next_pair_index((x, y): (usize, usize), total_processes: usize) -> (usize, usize) {
+    if y + 1 < total_processes {
+        (x, y + 1)
+    } else {
+        (x + 1, x + 2)
+    }
+}
+
+/// Формирует пакет из не более batch_size пар, начиная с пары start
+///
+/// Возвращает пары пакета и пару, с которой начнется следующий пакет
+fn next_pair_batch(start: (usize, usize), total_processes: usize, batch_size: usize) -> (Vec<(usize, usize)>, (usize, usize)) {
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut next = start;
+    while batch.len() < batch_size && next.0 < total_processes && next.1 < total_processes {
+        batch.push(next);
+        next = next_pair_index(next, total_processes);
+    }
+    (batch, next)
+}
+
+/// Количество пар в пакете: каждая пара занимает по одному запросу на выборку консенсуса
+fn get_batch_size(concurrency: usize, sample_count: usize) -> usize {
+    (concurrency / sample_count.max(1)).max(1)
+}
+
+/// Возвращает количество одновременно сравниваемых пар для попытки кластеризации
+fn get_comparison_concurrency(clustering_attempt: &mut Individual) -> usize {
+    let concurrency = clustering_attempt.get_first_integer("v-bpa:comparisonConcurrency").unwrap_or(DEFAULT_COMPARISON_CONCURRENCY as i64);
+    concurrency.clamp(1, MAX_COMPARISON_CONCURRENCY as i64) as usize
+}
+
+/// Подготавливает параметры запроса для сравнения двух процессов
+fn prepare_pair_comparison_parameters(
+    module: &mut BusinessProcessAnalysisModule,
+    process1_id: &str,
+    process2_id: &str,
+    system_prompt: &str,
+) -> Result<openai_dive::v1::resources::chat::ChatCompletionParameters, Box<dyn std::error::Error>> {
     let mut process1 = Individual::default();
     let mut process2 = Individual::default();
 
@@ -432,15 +636,8 @@ fn compare_processes(module: &mut BusinessProcessAnalysisModule, process1_id: &s
 
     // Подготавливаем данные для сравнения
     let comparison_data = prepare_comparison_data(module, &mut process1, &mut process2)?;
-    let system_prompt = get_system_prompt(module, "v-bpa:ClusterizeProcessesPrompt")?;
-
-    let parameters = clustering_common::prepare_comparison_parameters(module.default_model.clone(), system_prompt, comparison_data)?;
-
-    // Отправляем запрос к AI
-    let rt = Runtime::new()?;
-    let is_similar = rt.block_on(async { clustering_common::send_comparison_request(module, parameters).await })?;
 
-    Ok(is_similar)
+    clustering_common::prepare_comparison_parameters(module.default_model.clone(), system_prompt.to_string(), comparison_data)
 }
 
 /// Подготавливает данные о процессах для анализа AI
@@ -457,71 +654,118 @@ fn prepare_comparison_data(
     Ok(result)
 }
 
-/// Формирует кластеры на основе найденных похожих пар процессов
+/// Формирует кластеры на основе найденных похожих пар процессов,
+/// сопоставляет их с кластерами предыдущей попытки и сохраняет показатели качества кластеризации
 fn build_clusters(module: &mut BusinessProcessAnalysisModule, clustering_attempt: &mut Individual, event_id: &str) -> Result<(), Box<dyn std::error::Error>> {
     info!("Starting cluster building process");
 
-    let similar_pairs = clustering_attempt.get_literals("v-bpa:similarPairs").unwrap_or_default();
+    let similar_pairs = cluster_metrics::parse_similar_pairs(&clustering_attempt.get_literals("v-bpa:similarPairs").unwrap_or_default());
+    let total_processes = clustering_attempt.get_literals("v-bpa:processesToAnalyze").map_or(0, |p| p.len());
     info!("Found {} similar pairs to process", similar_pairs.len());
 
+    // Очищаем предыдущие кластеры
+    clustering_attempt.remove("v-bpa:foundClusters");
+
+    let mut clusters_quality = Vec::new();
+    let mut candidate_clusters = Vec::new();
+
     if similar_pairs.is_empty() {
         info!("No similar pairs found, skipping cluster creation");
-        clustering_attempt.set_uri("v-bpa:hasClusterizationStatus", "v-bpa:Completed");
-        clustering_common::update_individual(module, clustering_attempt, IndvOp::SetIn, event_id)?;
-        return Ok(());
-    }
+    } else {
+        // Строим граф связей между процессами
+        let mut adjacency_list: HashMap<String, HashSet<String>> = HashMap::new();
 
-    // Строим граф связей между процессами
-    let mut adjacency_list: HashMap<String, HashSet<String>> = HashMap::new();
+        info!("Building process relationship graph");
 
-    info!("Building process relationship graph");
+        // Добавляем только связанные процессы
+        for (a, b) in &similar_pairs {
+            info!("Adding bidirectional connection between {} and {}", a, b);
+            adjacency_list.entry(a.clone()).or_default().insert(b.clone());
+            adjacency_list.entry(b.clone()).or_default().insert(a.clone());
+        }
 
-    // Добавляем только связанные процессы
-    for pair in similar_pairs {
-        let parts: Vec<&str> = pair.split(',').collect();
-        if parts.len() == 2 {
-            info!("Adding bidirectional connection between {} and {}", parts[0], parts[1]);
-            adjacency_list.entry(parts[0].to_string()).or_default().insert(parts[1].to_string());
-            adjacency_list.entry(parts[1].to_string()).or_default().insert(parts[0].to_string());
+        // Находим связные компоненты (кластеры)
+        let clusters = find_connected_components(&adjacency_list);
+        info!("Found {} potential clusters", clusters.len());
+
+        // Кластеры создаются только для групп из двух и более процессов
+        for (cluster_index, processes) in clusters.iter().enumerate() {
+            if processes.len() >= 2 {
+                candidate_clusters.push(processes.clone());
+            } else {
+                info!("Skipping cluster {} as it contains only {} process", cluster_index + 1, processes.len());
+            }
         }
+        info!("Found {} clusters with two or more processes among {} potential groups", candidate_clusters.len(), clusters.len());
     }
 
-    // Находим связные компоненты (кластеры)
-    let clusters = find_connected_components(&adjacency_list);
-    info!("Found {} potential clusters", clusters.len());
+    // Сопоставляем кластеры с кластерами предыдущей попытки с той же областью
+    let previous = match cluster_stability::load_previous_clusters(module, clustering_attempt) {
+        Ok(previous) => previous,
+        Err(e) => {
+            warn!("Failed to load previous clustering attempt, stability tracking skipped: {}", e);
+            None
+        },
+    };
+    let previous_processes = previous.as_ref().map(|p| p.processes.clone()).unwrap_or_default();
+    let matches = cluster_stability::match_clusters(&candidate_clusters, &previous_processes);
 
-    // Очищаем предыдущие кластеры
-    clustering_attempt.remove("v-bpa:foundClusters");
+    let prompt_version = resolve_prompt_version(module, CLUSTERIZE_PROMPT, Some(&mut *clustering_attempt));
+    let context = ClusterBuildContext {
+        previous: previous.as_ref(),
+        confidence_threshold: ConsensusConfig::load(module, &prompt_version)?.threshold,
+    };
 
-    // Создаем кластеры только для групп из двух и более процессов
-    let mut created_clusters = 0;
-    for (cluster_index, processes) in clusters.iter().enumerate() {
-        if processes.len() >= 2 {
-            info!("Processing cluster {} with {} processes", cluster_index + 1, processes.len());
-            match create_cluster(module, processes.iter().cloned().collect::<Vec<_>>(), clustering_attempt, event_id) {
-                Ok(cluster_id) => {
-                    info!("Successfully created cluster {} with {} processes", cluster_id, processes.len());
-                    created_clusters += 1;
-                },
-                Err(e) => {
-                    error!("Failed to create cluster {}: {}", cluster_index + 1, e);
-                    clustering_attempt.set_string("v-bpa:lastError", &e.to_string(), Lang::none());
-                    clustering_attempt.set_uri("v-bpa:hasClusterizationStatus", "v-bpa:Failed");
-                    clustering_attempt.set_uri("v-bpa:hasExecutionState", "v-bpa:ExecutionError");
-                    update_activity_timestamps(clustering_attempt, "v-bpa:Failed")?;
-                    clustering_common::update_individual(module, clustering_attempt, IndvOp::SetIn, &event_id)?;
-                    return Err(e);
-                },
-            }
-        } else {
-            info!("Skipping cluster {} as it contains only {} process", cluster_index + 1, processes.len());
+    let mut created_clusters = Vec::new();
+    for (cluster_index, (processes, cluster_match)) in candidate_clusters.iter().zip(matches).enumerate() {
+        info!("Processing cluster {} with {} processes", cluster_index + 1, processes.len());
+        let labor_costs = load_labor_costs(module, processes);
+        let quality = cluster_metrics::evaluate_cluster(processes, &similar_pairs, &labor_costs);
+
+        match create_cluster(module, processes.iter().cloned().collect::<Vec<_>>(), &quality, &cluster_match, &context, clustering_attempt, event_id) {
+            Ok(cluster_id) => {
+                info!("Successfully created cluster {} with {} processes, stability: {}", cluster_id, processes.len(), cluster_match.status.uri());
+                clusters_quality.push(quality);
+                created_clusters.push((cluster_id, cluster_match));
+            },
+            Err(e) => {
+                error!("Failed to create cluster {}: {}", cluster_index + 1, e);
+                clustering_attempt.set_string("v-bpa:lastError", &e.to_string(), Lang::none());
+                clustering_attempt.set_uri("v-bpa:hasClusterizationStatus", "v-bpa:Failed");
+                clustering_attempt.set_uri("v-bpa:hasExecutionState", "v-bpa:ExecutionError");
+                update_activity_timestamps(clustering_attempt, "v-bpa:Failed")?;
+                clustering_common::update_individual(module, clustering_attempt, IndvOp::SetIn, &event_id)?;
+                return Err(e);
+            },
         }
     }
 
-    info!("Created {} clusters from {} potential groups", created_clusters, clusters.len());
+    // Сохраняем изменения кластеров относительно предыдущей попытки
+    cluster_stability::create_clustering_diff(module, clustering_attempt, previous.as_ref(), &created_clusters, event_id)?;
+
+    // Сохраняем показатели качества кластеризации
+    let attempt_quality = cluster_metrics::evaluate_attempt(&clusters_quality, total_processes);
+    cluster_metrics::set_attempt_quality(clustering_attempt, &attempt_quality);
+    clustering_common::update_individual(module, clustering_attempt, IndvOp::SetIn, event_id)?;
+
     Ok(())
 }
 
+/// Загружает трудозатраты процессов кластера
+fn load_labor_costs(module: &mut BusinessProcessAnalysisModule, processes: &HashSet<String>) -> Vec<f64> {
+    let mut labor_costs = Vec::with_capacity(processes.len());
+    for process_id in processes {
+        let mut process = Individual::default();
+        if module.backend.storage.get_individual(process_id, &mut process) != ResultCode::Ok {
+            warn!("Failed to load process {} for labor costs", process_id);
+            continue;
+        }
+        process.parse_all();
+        labor_costs.push(process.get_first_float("v-bpa:laborCosts").unwrap_or_default());
+    }
+    labor_costs
+}
+
 /// Находит связные компоненты в графе процессов с помощью поиска в ширину
 ///
 /// # Алгоритм
@@ -568,20 +812,39 @@ fn find_connected_components(adjacency_list: &HashMap<String, HashSet<String>>)
 }
 
 /// Создает новый кластер процессов в базе
+///
+/// Кластер, не изменившийся с предыдущей попытки, получает результаты оптимизации предыдущего кластера,
+/// повторный анализ для него не выполняется.
 fn create_cluster(
     module: &mut BusinessProcessAnalysisModule,
     processes: Vec<String>,
+    quality: &ClusterQuality,
+    cluster_match: &ClusterMatch,
+    context: &ClusterBuildContext,
     clustering_attempt: &mut Individual,
     event_id: &str,
 ) -> Result<String, Box<dyn std::error::Error>> {
     info!("Creating new cluster for {} processes", processes.len());
     let mut cluster = Individual::default();
+    if let (StabilityStatus::Unchanged, Some(prev_idx), Some(previous)) = (cluster_match.status, cluster_match.previous, context.previous) {
+        info!("Reusing optimization results of unchanged cluster {}", previous.clusters[prev_idx].get_id());
+        cluster_stability::copy_optimization_results(&mut cluster, &previous.clusters[prev_idx]);
+    }
 
     // Генерируем уникальный ID для кластера
     let cluster_id = format!("d:bpa_cluster_{}", uuid::Uuid::new_v4());
     cluster.set_id(&cluster_id);
     cluster.set_uri("rdf:type", "v-bpa:ProcessCluster");
+    cluster.set_uri("v-bpa:clusterizationAttempt", clustering_attempt.get_id());
     cluster.set_uris("v-bpa:hasProcess", processes);
+    cluster_metrics::set_cluster_quality(&mut cluster, quality);
+    cluster_stability::set_cluster_stability(&mut cluster, cluster_match, context.previous);
+
+    // Уверенность кластера - наименьшая уверенность похожих пар внутри него
+    match get_cluster_confidence(clustering_attempt, &cluster.get_literals("v-bpa:hasProcess").unwrap_or_default()) {
+        Some(confidence) => consensus::set_verdict_confidence(&mut cluster, confidence, context.confidence_threshold),
+        None => consensus::clear_verdict_confidence(&mut cluster),
+    }
 
     if let Err(e) = module.backend.mstorage_api.update_or_err(&module.ticket, event_id, "BPA", IndvOp::Put, &mut cluster) {
         error!("Failed to save cluster {}: {:?}", cluster_id, e);
@@ -594,6 +857,23 @@ fn create_cluster(
     Ok(cluster_id)
 }
 
+/// Возвращает наименьшую уверенность похожих пар кластера из v-bpa:similarPairConfidence
+/// вида "process1,process2,confidence", если выборки расходились хотя бы по одной паре
+fn get_cluster_confidence(clustering_attempt: &mut Individual, processes: &[String]) -> Option<f64> {
+    clustering_attempt
+        .get_literals("v-bpa:similarPairConfidence")
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|value| {
+            let parts: Vec<&str> = value.split(',').collect();
+            if parts.len() != 3 || !processes.iter().any(|p| p == parts[0]) || !processes.iter().any(|p| p == parts[1]) {
+                return None;
+            }
+            parts[2].parse::<f64>().ok()
+        })
+        .reduce(f64::min)
+}
+
 /// Обработчик ошибок процесса кластеризации
 fn handle_error(
     module: &mut BusinessProcessAnalysisModule,
@@ -607,5 +887,91 @@ fn handle_error(
     clustering_attempt.set_uri("v-bpa:hasExecutionState", "v-bpa:ExecutionError");
     update_activity_timestamps(clustering_attempt, "v-bpa:Failed")?;
     clustering_common::update_individual(module, clustering_attempt, IndvOp::SetIn, event_id)?;
+    release_attempt_lock(module, clustering_attempt, event_id)?;
     Err(error)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_pair_index() {
+        assert_eq!(next_pair_index((0, 1), 4), (0, 2));
+        assert_eq!(next_pair_index((0, 3), 4), (1, 2));
+        assert_eq!(next_pair_index((2, 3), 4), (3, 4));
+    }
+
+    #[test]
+    fn test_next_pair_index_covers_all_pairs() {
+        let total_processes = 5;
+        let mut pairs = Vec::new();
+        let mut pair = (0, 1);
+        while pair.0 < total_processes && pair.1 < total_processes {
+            pairs.push(pair);
+            pair = next_pair_index(pair, total_processes);
+        }
+
+        let expected: Vec<(usize, usize)> = (0..total_processes).flat_map(|x| (x + 1..total_processes).map(move |y| (x, y))).collect();
+        assert_eq!(pairs, expected);
+    }
+
+    #[test]
+    fn test_next_pair_batch() {
+        let (batch, next) = next_pair_batch((0, 1), 4, 4);
+        assert_eq!(batch, vec![(0, 1), (0, 2), (0, 3), (1, 2)]);
+        assert_eq!(next, (1, 3));
+
+        // Последний пакет короче и завершает сравнение
+        let (batch, next) = next_pair_batch(next, 4, 4);
+        assert_eq!(batch, vec![(1, 3), (2, 3)]);
+        assert_eq!(next, (3, 4));
+
+        let (batch, _) = next_pair_batch(next, 4, 4);
+        assert!(batch.is_empty());
+    }
+
+    #[test]
+    fn test_record_pair_verdict() {
+        let mut attempt = Individual::default();
+
+        assert!(record_pair_verdict(&mut attempt, "d:p1", "d:p2", true, 1.0, 0.75));
+        assert!(record_pair_verdict(&mut attempt, "d:p1", "d:p3", true, 0.6, 0.75));
+        assert_eq!(attempt.get_literals("v-bpa:similarPairs").unwrap(), vec!["d:p1,d:p2", "d:p1,d:p3"]);
+        assert_eq!(attempt.get_literals("v-bpa:similarPairConfidence").unwrap(), vec!["d:p1,d:p3,0.6000"]);
+
+        // Уверенный отрицательный вердикт ничего не записывает
+        assert!(!record_pair_verdict(&mut attempt, "d:p2", "d:p3", false, 0.8, 0.75));
+        assert_eq!(attempt.get_literals("v-bpa:uncertainPairs"), None);
+        assert_eq!(attempt.get_first_literal("v-bpa:hasReviewTag"), None);
+
+        // Спорный отрицательный вердикт сохраняется и требует проверки
+        assert!(record_pair_verdict(&mut attempt, "d:p2", "d:p4", false, 0.6, 0.75));
+        assert_eq!(attempt.get_literals("v-bpa:uncertainPairs").unwrap(), vec!["d:p2,d:p4,0.6000"]);
+        assert_eq!(attempt.get_first_literal("v-bpa:hasReviewTag").as_deref(), Some("v-bpa:NeedsReview"));
+        assert_eq!(attempt.get_literals("v-bpa:similarPairs").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_comparison_concurrency_clamped() {
+        let mut attempt = Individual::default();
+        assert_eq!(get_comparison_concurrency(&mut attempt), DEFAULT_COMPARISON_CONCURRENCY);
+
+        attempt.set_integer("v-bpa:comparisonConcurrency", 8);
+        assert_eq!(get_comparison_concurrency(&mut attempt), 8);
+
+        attempt.set_integer("v-bpa:comparisonConcurrency", 100);
+        assert_eq!(get_comparison_concurrency(&mut attempt), MAX_COMPARISON_CONCURRENCY);
+
+        attempt.set_integer("v-bpa:comparisonConcurrency", 0);
+        assert_eq!(get_comparison_concurrency(&mut attempt), 1);
+    }
+
+    #[test]
+    fn test_batch_size_divided_by_consensus_samples() {
+        assert_eq!(get_batch_size(4, 1), 4);
+        assert_eq!(get_batch_size(16, 3), 5);
+        // Пакет содержит хотя бы одну пару, даже если выборок больше, чем одновременных запросов
+        assert_eq!(get_batch_size(4, 9), 1);
+    }
+}
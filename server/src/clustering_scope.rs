@@ -0,0 +1,140 @@
+// clustering_scope.rs
+
+use crate::queue_processor::BusinessProcessAnalysisModule;
+use v_common::onto::individual::Individual;
+use v_common::v_api::obj::ResultCode;
+
+/// Запрос, выбирающий все бизнес-процессы, если область попытки не задана
+pub const ALL_PROCESSES_QUERY: &str = "'rdf:type' === 'v-bpa:BusinessProcess'";
+
+/// Верхняя граница числового диапазона, если в фильтре задан только минимум
+const RANGE_UPPER_BOUND: f64 = 1_000_000_000.0;
+
+/// Текстовые поля фильтра бизнес-процессов и соответствующие им свойства процесса
+const TEXT_FILTERS: &[(&str, &str)] = &[
+    ("rdfs:label_filter", "rdfs:label"),
+    ("v-bpa:processDescription_filter", "v-bpa:processDescription"),
+    ("v-bpa:processParticipant_filter", "v-bpa:processParticipant"),
+    ("v-bpa:responsibleDepartment_filter", "v-bpa:responsibleDepartment"),
+];
+
+/// Числовые поля фильтра бизнес-процессов, задаваемые диапазоном [min, max]
+const RANGE_FILTERS: &[(&str, &str)] = &[("v-bpa:laborCosts_filter", "v-bpa:laborCosts"), ("v-bpa:processFrequency_filter", "v-bpa:processFrequency")];
+
+/// Формирует полнотекстовый запрос для выбора процессов попытки кластеризации
+///
+/// Без свойств области выбираются все бизнес-процессы. Свойства области объединяются через "и":
+/// - v-bpa:scopeDepartment - ответственные подразделения (любое из перечисленных)
+/// - v-bpa:scopeProcess - явный список процессов
+/// - v-bpa:scopeFilter - индивид v-bpa:BusinessProcessFilter
+pub fn build_process_query(module: &mut BusinessProcessAnalysisModule, clustering_attempt: &mut Individual) -> Result<String, Box<dyn std::error::Error>> {
+    let mut conditions = vec![ALL_PROCESSES_QUERY.to_string()];
+
+    if let Some(departments) = clustering_attempt.get_literals("v-bpa:scopeDepartment") {
+        if let Some(condition) = any_of("v-bpa:responsibleDepartment", "==", &departments) {
+            conditions.push(condition);
+        }
+    }
+
+    if let Some(processes) = clustering_attempt.get_literals("v-bpa:scopeProcess") {
+        if let Some(condition) = any_of("@", "===", &processes) {
+            conditions.push(condition);
+        }
+    }
+
+    if let Some(filter_id) = clustering_attempt.get_first_literal("v-bpa:scopeFilter") {
+        let mut filter = Individual::default();
+        if module.backend.storage.get_individual(&filter_id, &mut filter) != ResultCode::Ok {
+            error!("Failed to load scope filter {}", filter_id);
+            return Err(format!("Failed to load scope filter {}", filter_id).into());
+        }
+        filter.parse_all();
+        conditions.extend(filter_conditions(&mut filter));
+    }
+
+    Ok(conditions.join(" && "))
+}
+
+/// Исключает процессы, замененные объединенным процессом, - они повторно не кластеризуются
+///
+/// Полнотекстовый запрос не поддерживает отрицание булевых значений, поэтому отметка v-bpa:isSuperseded
+/// проверяется после загрузки процессов. Процессы, которые не удалось загрузить, также исключаются.
+pub fn exclude_superseded(module: &mut BusinessProcessAnalysisModule, process_ids: Vec<String>) -> Vec<String> {
+    process_ids
+        .into_iter()
+        .filter(|process_id| {
+            let mut process = Individual::default();
+            if module.backend.storage.get_individual(process_id, &mut process) != ResultCode::Ok {
+                warn!("Failed to load process {}, excluding it from clustering", process_id);
+                return false;
+            }
+            process.parse_all();
+            if is_superseded(&mut process) {
+                info!("Process {} is superseded by a merged process, excluding it from clustering", process_id);
+                return false;
+            }
+            true
+        })
+        .collect()
+}
+
+/// Процесс заменен объединенным процессом принятого предложения оптимизации
+fn is_superseded(process: &mut Individual) -> bool {
+    process.get_first_bool("v-bpa:isSuperseded") == Some(true)
+}
+
+/// Преобразует значения индивида v-bpa:BusinessProcessFilter в условия запроса
+fn filter_conditions(filter: &mut Individual) -> Vec<String> {
+    let mut conditions = Vec::new();
+
+    for (filter_predicate, predicate) in TEXT_FILTERS {
+        let values: Vec<String> = filter.get_literals(filter_predicate).unwrap_or_default().into_iter().filter(|v| !v.trim().is_empty()).collect();
+        if let Some(condition) = any_of(predicate, "==", &values) {
+            conditions.push(condition);
+        }
+    }
+
+    for (filter_predicate, predicate) in RANGE_FILTERS {
+        let bounds: Vec<f64> = filter.get_resources(filter_predicate).unwrap_or_default().iter().map(|r| r.get_float()).collect();
+        let min = bounds.first().cloned().unwrap_or_default();
+        let max = bounds.get(1).cloned().filter(|v| *v > 0.0).unwrap_or(RANGE_UPPER_BOUND);
+        if min > 0.0 || max < RANGE_UPPER_BOUND {
+            conditions.push(format!("'{}' == [{},{}]", predicate, min, max));
+        }
+    }
+
+    let justifications = filter.get_literals("v-bpa:hasProcessJustification_filter").unwrap_or_default();
+    if let Some(condition) = any_of("v-bpa:hasProcessJustification", "===", &justifications) {
+        conditions.push(condition);
+    }
+
+    conditions
+}
+
+/// Формирует условие "любое из значений" для свойства
+fn any_of(predicate: &str, operator: &str, values: &[String]) -> Option<String> {
+    if values.is_empty() {
+        return None;
+    }
+    let alternatives: Vec<String> = values.iter().map(|v| format!("'{}' {} '{}'", predicate, operator, v.replace('\'', " "))).collect();
+    Some(format!("({})", alternatives.join(" || ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_superseded() {
+        let mut process = Individual::default();
+        process.set_id("d:process");
+        assert!(!is_superseded(&mut process));
+
+        process.set_uri("v-bpa:mergedInto", "d:merged");
+        process.set_bool("v-bpa:isSuperseded", true);
+        assert!(is_superseded(&mut process));
+
+        process.set_bool("v-bpa:isSuperseded", false);
+        assert!(!is_superseded(&mut process));
+    }
+}
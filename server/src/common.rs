@@ -1,7 +1,14 @@
 // common.rs
 use crate::ai_client::{save_to_interaction_file, AIResponseValues};
+use crate::entity_resolution;
+use crate::output_language::language_instruction;
+use crate::prompt_manager::{get_system_prompt, PromptContext};
+use crate::prompt_version::PROMPT_VERSION_TYPE;
 use crate::queue_processor::BusinessProcessAnalysisModule;
-use crate::types::{PropertyMapping, PropertySchema};
+use crate::schema_cache::SchemaCache;
+use crate::schema_compiler::{CompiledSchema, SchemaCompiler};
+use crate::types::PropertyMapping;
+use crate::value_types::DEFAULT_LANGUAGE;
 use humantime::format_duration;
 use openai_dive::v1::resources::chat::{
     ChatCompletionParameters, ChatCompletionParametersBuilder, ChatCompletionResponseFormat, ChatMessage, ChatMessageContent, JsonSchemaBuilder,
@@ -10,9 +17,9 @@ use serde_json::Value;
 use std::collections::HashSet;
 use std::time::Duration;
 use std::{io, thread, time};
-use v_common::onto::datatype::Lang;
 use v_common::onto::individual::Individual;
 use v_common::search::common::{FTQuery, QueryResult};
+use v_common::v_api::api_client::IndvOp;
 use v_common::v_api::obj::ResultCode;
 
 #[derive(Debug, Clone, Copy)]
@@ -21,28 +28,19 @@ pub enum ClientType {
     Reasoning,
 }
 
-/// Gets prompt text from ontology individual
-///
-/// # Arguments
-/// * `module` - Business process analysis module
-/// * `prompt_id` - ID of prompt individual
-///
-/// # Returns
-/// * `Result<String, Box<dyn std::error::Error>>` - Prompt text or error
-pub fn get_prompt_text(module: &mut BusinessProcessAnalysisModule, prompt_id: &str) -> Result<String, Box<dyn std::error::Error>> {
-    // Load prompt individual
-    let mut prompt_individual = Individual::default();
-    if module.backend.storage.get_individual(prompt_id, &mut prompt_individual) != ResultCode::Ok {
-        return Err(format!("Failed to get prompt with ID: {}", prompt_id).into());
+impl ClientType {
+    /// Выбирает клиента провайдера, обслуживающего модель
+    ///
+    /// Модель провайдера рассуждений отправляется через его клиент, остальные модели - через клиент по умолчанию.
+    pub fn for_model(module: &BusinessProcessAnalysisModule, model: &str) -> Self {
+        if model != module.default_model && model == module.reasoning_model {
+            ClientType::Reasoning
+        } else {
+            ClientType::Default
+        }
     }
-
-    prompt_individual.parse_all();
-
-    // Get prompt text
-    let prompt_text = prompt_individual.get_first_literal("v-bpa:promptText").ok_or("Prompt text not found")?;
-
-    Ok(prompt_text)
 }
+
 /// Формирует JSON-представление бизнес-процесса из индивида, включая связанные документы
 ///
 /// # Arguments
@@ -71,6 +69,7 @@ pub fn extract_process_json(bp_obj: &mut Individual, module: &mut BusinessProces
         if module.backend.storage.get_individual(&ref_id, &mut document) == ResultCode::Ok {
             document.parse_all();
             let document_json = serde_json::json!({
+                "id": ref_id,
                 "name": document.get_first_literal("v-bpa:documentName").unwrap_or_default(),
                 "content": document.get_first_literal("v-bpa:documentContent").unwrap_or_default()
             });
@@ -157,59 +156,62 @@ pub fn get_individuals_uris_by_query(module: &mut BusinessProcessAnalysisModule,
     Ok(res.result)
 }
 
+/// Загружает и компилирует схему ответа промпта (v-bpa:responseSchema или v-bpa:properties)
+///
+/// Скомпилированная схема берется из кэша модуля, пока промпт и используемые схемой индивиды онтологии не изменятся.
+/// Схема, загруженная из версии промпта, записывает версию в индивиды результата.
+///
+/// # Arguments
+/// * `excluded` - экземпляры, которые не попадают в перечисления схемы
+/// * `language` - язык промпта, заданный запросом; если не задан, используется v-bpa:promptLanguage промпта
 pub fn load_schema(
     module: &mut BusinessProcessAnalysisModule,
     system_prompt_id: &str,
     excluded: Option<HashSet<&str>>,
-    property_mapping: &mut PropertyMapping,
-) -> Result<PropertySchema, Box<dyn std::error::Error>> {
+    language: Option<&str>,
+) -> Result<CompiledSchema, Box<dyn std::error::Error>> {
+    let cache_key = SchemaCache::key(system_prompt_id, excluded.as_ref(), language);
+    if let Some(schema) = module.schema_cache.get(&cache_key) {
+        debug!("Using cached response schema of {}", system_prompt_id);
+        return Ok(schema);
+    }
+
     let mut prompt_individual = Individual::default();
     if module.backend.storage.get_individual(system_prompt_id, &mut prompt_individual) != ResultCode::Ok {
         return Err("Failed to load prompt".into());
     }
-    let properties = prompt_individual.get_literals("v-bpa:properties").unwrap_or_default();
-    info!("@A0 properties={:?}", properties);
-
-    // Собираем определения свойств
-    let schema = collect_define_from_schema(module, properties, excluded, property_mapping);
+    prompt_individual.parse_all();
 
-    info!("@A1 schema={:?}", schema);
+    let language = language.map(str::to_string).or_else(|| prompt_individual.get_first_literal("v-bpa:promptLanguage")).unwrap_or_else(|| DEFAULT_LANGUAGE.to_string());
+    let mut schema = SchemaCompiler::new(module).exclude_instances(excluded).language(&language).compile_prompt(&mut prompt_individual)?;
+    if prompt_individual.any_exists("rdf:type", &[PROMPT_VERSION_TYPE]) {
+        schema.prompt_version = Some(system_prompt_id.to_string());
+    }
+    info!("Compiled response schema of {} with {} fields, language {}", system_prompt_id, schema.root.fields.len(), language);
 
+    module.schema_cache.insert(cache_key, system_prompt_id, schema.clone());
     Ok(schema)
 }
 
 /// Prepares parameters for AI request based on ontology prompt
+///
+/// The prompt template is rendered with the request context.
+/// The AI is instructed to respond in the output language of the schema, Russian if the handler did not set it.
 pub fn prepare_request_ai_parameters(
     module: &mut BusinessProcessAnalysisModule,
     system_prompt_id: &str,
     analysis_data: Value,
-    properties_schema: PropertySchema,
-    property_mapping: &mut PropertyMapping,
+    schema: &CompiledSchema,
+    context: &PromptContext,
 ) -> Result<ChatCompletionParameters, Box<dyn std::error::Error>> {
-    let mut prompt_individual = Individual::default();
-    if module.backend.storage.get_individual(system_prompt_id, &mut prompt_individual) != ResultCode::Ok {
-        return Err("Failed to load prompt".into());
-    }
-    let prompt_text = prompt_individual.get_first_literal("v-bpa:promptText").ok_or("Prompt text not found")?;
-
-    // Collect property names for required list
-    let required: Vec<String> = property_mapping
-        .keys()
-        .filter(|k| !k.contains('*')) // Exclude enum value mappings
-        .cloned()
-        .collect();
+    let prompt_text = get_system_prompt(module, system_prompt_id, context)?;
 
     // Build complete schema
-    let schema = serde_json::json!({
+    let request_schema = serde_json::json!({
         "type": "object",
         "additionalProperties": false,
         "properties": {
-            "result": {
-                "type": "object",
-                "additionalProperties": false,
-                "properties": properties_schema,
-                "required": required
-            }
+            "result": schema.to_ai_schema()
         },
         "required": ["result"]
     });
@@ -218,7 +220,7 @@ pub fn prepare_request_ai_parameters(
         .model(module.default_model.clone())
         .messages(vec![
             ChatMessage::System {
-                content: ChatMessageContent::Text("You must respond only in Russian language. Use only Russian for all text fields.".to_string()),
+                content: ChatMessageContent::Text(language_instruction(schema.output_language.as_deref().unwrap_or(DEFAULT_LANGUAGE))),
                 name: None,
             },
             ChatMessage::System {
@@ -230,7 +232,7 @@ pub fn prepare_request_ai_parameters(
                 name: None,
             },
         ])
-        .response_format(ChatCompletionResponseFormat::JsonSchema(JsonSchemaBuilder::default().name("process_optimization").schema(schema).strict(true).build()?))
+        .response_format(ChatCompletionResponseFormat::JsonSchema(JsonSchemaBuilder::default().name("process_optimization").schema(request_schema).strict(true).build()?))
         .build()?;
 
     // Save request parameters to file
@@ -243,9 +245,12 @@ pub fn prepare_request_ai_parameters(
 ///
 /// # Arguments
 /// * `module` - Модуль анализа с настройками и доступом к хранилищу
-/// * `individual_id` - Идентификатор индивида для обновления
+/// * `individual` - Индивид для обновления
 /// * `ai_response` - Значения из ответа AI
-/// * `property_mapping` - Маппинг свойств (короткие имена -> URI)
+/// * `schema` - Скомпилированная схема ответа промпта
+/// * `event_id` - Идентификатор события, с которым сохраняются созданные индивиды
+///
+/// Извлеченные сущности сопоставляются с существующими индивидами, созданные для вложенных объектов индивиды сохраняются.
 ///
 /// # Returns
 /// * `Result<(), Box<dyn std::error::Error>>` - Результат сохранения
@@ -253,9 +258,10 @@ pub fn set_to_individual_from_ai_response(
     module: &mut BusinessProcessAnalysisModule,
     individual: &mut Individual,
     ai_response: &AIResponseValues,
-    property_mapping: &PropertyMapping,
+    schema: &CompiledSchema,
+    event_id: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    // Получаем вложенный объект optimized_process
+    // Получаем вложенный объект result
     let response_values = if let Some(res) = ai_response.get("result") {
         if let Some(obj) = res.as_object() {
             obj
@@ -270,96 +276,16 @@ pub fn set_to_individual_from_ai_response(
 
     info!("@D response_values={:?}", response_values);
 
-    for (short_name, value) in response_values {
-        if let Some(full_prop) = property_mapping.get(short_name) {
-            // Загружаем определение свойства
-            let mut prop_individual = Individual::default();
-            if module.backend.storage.get_individual(full_prop, &mut prop_individual) != ResultCode::Ok {
-                warn!("Failed to load property definition for {}", full_prop);
-                continue;
-            }
-            prop_individual.parse_all();
-
-            let range = prop_individual.get_first_literal("rdfs:range").unwrap_or_default();
-
-            // Очищаем предыдущие значения свойства
-            individual.remove(full_prop);
-
-            if !range.starts_with("xsd:") {
-                // Обработка значений-ссылок
-                if let Some(arr) = value.as_array() {
-                    for val in arr {
-                        if let Some(str_val) = val.as_str() {
-                            let enum_key = format!("{}*{}", short_name, str_val);
-                            if let Some(uri) = property_mapping.get(&enum_key) {
-                                info!("Adding enum value {} -> {} for property {}", str_val, uri, full_prop);
-                                individual.add_uri(full_prop, uri);
-                            } else {
-                                info!("Adding value {} for property {}", str_val, full_prop);
-                                individual.add_string(full_prop, str_val, Lang::none());
-                            }
-                        }
-                    }
-                } else if let Some(str_val) = value.as_str() {
-                    let enum_key = format!("{}*{}", short_name, str_val);
-                    if let Some(uri) = property_mapping.get(&enum_key) {
-                        info!("Setting enum value {} -> {} for property {}", str_val, uri, full_prop);
-                        individual.set_uri(full_prop, uri);
-                    } else {
-                        info!("Setting value {} for property {}", str_val, full_prop);
-                        individual.set_string(full_prop, str_val, Lang::none());
-                    }
-                }
-            } else {
-                // Обработка xsd:* типов
-                match range.as_str() {
-                    "xsd:string" => {
-                        if let Some(arr) = value.as_array() {
-                            for val in arr {
-                                if let Some(str_val) = val.as_str() {
-                                    individual.add_string(full_prop, str_val, Lang::none());
-                                }
-                            }
-                        } else if let Some(str_val) = value.as_str() {
-                            individual.set_string(full_prop, str_val, Lang::none());
-                        }
-                    },
-                    "xsd:integer" => {
-                        if let Some(arr) = value.as_array() {
-                            for val in arr {
-                                if let Some(num_val) = val.as_i64() {
-                                    individual.add_integer(full_prop, num_val);
-                                }
-                            }
-                        } else if let Some(num_val) = value.as_i64() {
-                            individual.set_integer(full_prop, num_val);
-                        }
-                    },
-                    "xsd:decimal" => {
-                        if let Some(arr) = value.as_array() {
-                            for val in arr {
-                                if let Some(num_val) = val.as_f64() {
-                                    individual.add_decimal_from_f64(full_prop, num_val);
-                                }
-                            }
-                        } else if let Some(num_val) = value.as_f64() {
-                            individual.add_decimal_from_f64(full_prop, num_val);
-                        }
-                    },
-                    _ => {
-                        warn!("Unknown range type {} for property {}, treating as string", range, full_prop);
-                        if let Some(str_val) = value.as_str() {
-                            individual.set_string(full_prop, str_val, Lang::none());
-                        }
-                    },
-                }
-            }
-        } else {
-            warn!("Property mapping not found for short name: {}", short_name);
+    let (mut related_individuals, candidates) = schema.apply_values(response_values, individual, true)?;
+    entity_resolution::resolve_entities(module, individual, &mut related_individuals, candidates)?;
+
+    for related in related_individuals.iter_mut() {
+        if let Err(e) = module.backend.mstorage_api.update_or_err(&module.ticket, event_id, "BPA", IndvOp::Put, related) {
+            error!("Failed to save related individual {}: {:?}", related.get_id(), e);
+            return Err(format!("Failed to save related individual, err={:?}", e).into());
         }
     }
 
-    info!("Successfully set AI analysis results for individual {}", individual.get_id());
     Ok(())
 }
 
@@ -368,149 +294,9 @@ pub fn format_time(seconds: i64) -> String {
     format_duration(duration).to_string()
 }
 
-pub fn collect_define_from_schema(
-    module: &mut BusinessProcessAnalysisModule,
-    properties: Vec<String>,
-    excluded: Option<HashSet<&str>>,
-    property_mapping: &mut PropertyMapping,
-) -> PropertySchema {
-    let mut properties_defs = Vec::new();
-
-    for full_prop in properties {
-        let mut prop_individual = Individual::default();
-        if module.backend.storage.get_individual(&full_prop, &mut prop_individual) != ResultCode::Ok {
-            continue;
-        }
-        prop_individual.parse_all();
-
-        let is_functional_property = prop_individual.any_exists("rdf:type", &["owl:FunctionalProperty"]);
-        let range = prop_individual.get_first_literal("rdfs:range").unwrap_or_default();
-        let description = prop_individual.get_first_literal_with_lang("rdfs:label", &[Lang::new_from_i64(1)]).unwrap_or_else(|| full_prop.clone());
-
-        let short_name = full_prop.split(':').last().unwrap_or(&*full_prop).to_string();
-        property_mapping.insert(short_name.clone(), full_prop.clone());
-
-        let property_def = if !range.starts_with("xsd:") {
-            //info!("@A2 Processing class range: {} for property {}", range, full_prop);
-
-            match get_individuals_by_type(module, &range) {
-                Ok(mut instances) => {
-                    let enum_values = instances
-                        .iter_mut()
-                        .filter(|instance| {
-                            if let Some(ex) = &excluded {
-                                !ex.contains(instance.get_id())
-                            } else {
-                                true
-                            }
-                        })
-                        .filter_map(|instance| {
-                            let label = instance.get_first_literal_with_lang("rdfs:label", &[Lang::new_from_i64(1)]);
-                            if let Some(label) = &label {
-                                property_mapping.insert(format!("{}*{}", short_name, label), instance.get_id().to_string());
-                            }
-                            label
-                        })
-                        .collect::<Vec<_>>();
-
-                    //info!("@A3 Found enum values for {}: {:?}", full_prop, enum_values);
-
-                    if !enum_values.is_empty() {
-                        if is_functional_property {
-                            serde_json::json!({
-                                short_name: {
-                                    "type": "string",
-                                    "enum": enum_values,
-                                    "description": description
-                                }
-                            })
-                        } else {
-                            serde_json::json!({
-                                short_name: {
-                                    "type": "array",
-                                    "items": {
-                                        "type": "string",
-                                        "enum": enum_values
-                                    },
-                                    "description": description
-                                }
-                            })
-                        }
-                    } else {
-                        if is_functional_property {
-                            serde_json::json!({
-                                short_name: {
-                                    "type": "string",
-                                    "description": description
-                                }
-                            })
-                        } else {
-                            serde_json::json!({
-                                short_name: {
-                                    "type": "array",
-                                    "items": {"type": "string"},
-                                    "description": description
-                                }
-                            })
-                        }
-                    }
-                },
-                Err(e) => {
-                    error!("Failed to get instances of type {}: {:?}", range, e);
-                    if is_functional_property {
-                        serde_json::json!({
-                            short_name: {
-                                "type": "string",
-                                "description": description
-                            }
-                        })
-                    } else {
-                        serde_json::json!({
-                            short_name: {
-                                "type": "array",
-                                "items": {"type": "string"},
-                                "description": description
-                            }
-                        })
-                    }
-                },
-            }
-        } else {
-            let item_type = match range.as_str() {
-                "xsd:string" => "string",
-                "xsd:integer" => "integer",
-                "xsd:decimal" => "number",
-                _ => "string",
-            };
-
-            if is_functional_property {
-                serde_json::json!({
-                    short_name: {
-                        "type": item_type,
-                        "description": description
-                    }
-                })
-            } else {
-                serde_json::json!({
-                    short_name: {
-                        "type": "array",
-                        "items": {"type": item_type},
-                        "description": description
-                    }
-                })
-            }
-        };
-
-        properties_defs.push(property_def);
-    }
-
-    // Собираем все свойства в один объект
-    let mut properties_obj = PropertySchema::new();
-    for prop_def in properties_defs {
-        properties_obj.extend(prop_def.as_object().unwrap().clone());
-    }
-
-    properties_obj
+/// Приводит текст к виду для сравнения: нижний регистр, только буквы и цифры, одиночные пробелы
+pub fn normalize_text(text: &str) -> String {
+    text.to_lowercase().split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).collect::<Vec<_>>().join(" ")
 }
 
 ////////////////
@@ -530,12 +316,14 @@ fn transform_uri_to_display_value(uri: &str, property_mapping: &PropertyMapping)
 }
 
 /// Преобразует человекочитаемое значение обратно в URI
-fn transform_display_value_to_uri(predicate: &str, display_value: &str, property_mapping: &PropertyMapping) -> Option<String> {
+///
+/// Значение, не совпавшее с меткой на языке промпта, сопоставляется с метками перечисления схемы на всех языках.
+fn transform_display_value_to_uri(predicate: &str, display_value: &str, property_mapping: &PropertyMapping, schema: &CompiledSchema) -> Option<String> {
     let enum_key = format!("{}*{}", predicate, display_value);
     info!("Looking up URI for key: {}", enum_key);
-    if let Some(uri) = property_mapping.get(&enum_key) {
+    if let Some(uri) = property_mapping.get(&enum_key).cloned().or_else(|| schema.find_enum_uri(predicate, display_value)) {
         info!("Found URI: {} for display value: {}", uri, display_value);
-        Some(uri.clone())
+        Some(uri)
     } else {
         info!("No URI found for display value: {} (key: {})", display_value, enum_key);
         None
@@ -588,7 +376,7 @@ pub fn convert_full_to_short_predicates(input: &Value, property_mapping: &mut Pr
 }
 
 /// Преобразует короткие имена предикатов в полные URI и их значения обратно в URI
-pub fn convert_short_to_full_predicates(input: &Value, property_mapping: &PropertyMapping) -> Result<Value, Box<dyn std::error::Error>> {
+pub fn convert_short_to_full_predicates(input: &Value, property_mapping: &PropertyMapping, schema: &CompiledSchema) -> Result<Value, Box<dyn std::error::Error>> {
     match input {
         Value::Object(map) => {
             let mut new_map = serde_json::Map::new();
@@ -606,7 +394,7 @@ pub fn convert_short_to_full_predicates(input: &Value, property_mapping: &Proper
                             .iter()
                             .map(|v| {
                                 if let Value::String(display_value) = v {
-                                    if let Some(uri) = transform_display_value_to_uri(short_predicate, display_value, property_mapping) {
+                                    if let Some(uri) = transform_display_value_to_uri(short_predicate, display_value, property_mapping, schema) {
                                         Value::String(uri)
                                     } else {
                                         v.clone()
@@ -619,7 +407,7 @@ pub fn convert_short_to_full_predicates(input: &Value, property_mapping: &Proper
                         Value::Array(transformed_arr)
                     },
                     Value::String(display_value) => {
-                        if let Some(uri) = transform_display_value_to_uri(short_predicate, display_value, property_mapping) {
+                        if let Some(uri) = transform_display_value_to_uri(short_predicate, display_value, property_mapping, schema) {
                             Value::String(uri)
                         } else {
                             value.clone()
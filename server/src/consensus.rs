@@ -0,0 +1,223 @@
+// consensus.rs
+
+use crate::queue_processor::BusinessProcessAnalysisModule;
+use openai_dive::v1::resources::chat::ChatCompletionParameters;
+use std::collections::HashMap;
+use std::hash::Hash;
+use v_common::onto::individual::Individual;
+use v_common::v_api::obj::ResultCode;
+
+/// Seed первой выборки, для остальных выборок seed увеличивается на номер выборки
+const BASE_SEED: u32 = 43;
+/// Порог уверенности по умолчанию, ниже которого результат требует проверки
+const DEFAULT_CONFIDENCE_THRESHOLD: f64 = 0.75;
+/// Максимальное количество выборок для одного вердикта
+const MAX_SAMPLES: usize = 9;
+
+/// Способ подсчета голосов выборок
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VoteMethod {
+    /// Каждая выборка имеет один голос
+    Majority,
+    /// Голос выборки равен весу ее модели
+    Weighted,
+}
+
+/// Одна выборка консенсуса: модель, seed и вес голоса
+#[derive(Debug, Clone)]
+pub struct ConsensusSample {
+    pub model: String,
+    pub seed: u32,
+    pub weight: f64,
+}
+
+/// Настройки консенсуса, заданные в промпте
+///
+/// - v-bpa:consensusSamples - количество выборок (по умолчанию 1)
+/// - v-bpa:consensusModel - модели выборок в виде "model" или "model=weight"
+/// - v-bpa:consensusVote - v-bpa:MajorityVote или v-bpa:WeightedVote
+/// - v-bpa:confidenceThreshold - порог уверенности для пометки v-bpa:NeedsReview
+#[derive(Debug, Clone)]
+pub struct ConsensusConfig {
+    pub samples: Vec<ConsensusSample>,
+    pub threshold: f64,
+}
+
+impl ConsensusConfig {
+    /// Загружает настройки консенсуса из индивида промпта
+    pub fn load(module: &mut BusinessProcessAnalysisModule, prompt_id: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut prompt = Individual::default();
+        if module.backend.storage.get_individual(prompt_id, &mut prompt) != ResultCode::Ok {
+            return Err(format!("Failed to load prompt {}", prompt_id).into());
+        }
+        prompt.parse_all();
+
+        let method = match prompt.get_first_literal("v-bpa:consensusVote").as_deref() {
+            Some("v-bpa:WeightedVote") => VoteMethod::Weighted,
+            _ => VoteMethod::Majority,
+        };
+
+        let mut models: Vec<(String, f64)> =
+            prompt.get_literals("v-bpa:consensusModel").unwrap_or_default().iter().filter_map(|value| parse_model_weight(value, method)).collect();
+        if models.is_empty() {
+            models.push((module.default_model.clone(), 1.0));
+        }
+        for (model, _) in &models {
+            if *model != module.default_model && *model != module.reasoning_model {
+                warn!("Consensus model {} of prompt {} is not configured for any provider, it is sent to the default provider", model, prompt_id);
+            }
+        }
+
+        let sample_count = (prompt.get_first_integer("v-bpa:consensusSamples").unwrap_or(1).max(1) as usize).max(models.len()).min(MAX_SAMPLES);
+        let samples = (0..sample_count)
+            .map(|index| {
+                let (model, weight) = &models[index % models.len()];
+                ConsensusSample {
+                    model: model.clone(),
+                    seed: BASE_SEED + index as u32,
+                    weight: *weight,
+                }
+            })
+            .collect();
+
+        Ok(ConsensusConfig {
+            samples,
+            threshold: prompt.get_first_float("v-bpa:confidenceThreshold").unwrap_or(DEFAULT_CONFIDENCE_THRESHOLD),
+        })
+    }
+
+    /// Консенсус включен, если вердикт формируется более чем одной выборкой
+    pub fn is_enabled(&self) -> bool {
+        self.samples.len() > 1
+    }
+
+    /// Размножает параметры запроса по выборкам, подставляя модель и seed каждой выборки
+    pub fn sample_parameters(&self, parameters: &ChatCompletionParameters) -> Vec<ChatCompletionParameters> {
+        self.samples
+            .iter()
+            .map(|sample| {
+                let mut sample_parameters = parameters.clone();
+                sample_parameters.model = sample.model.clone();
+                sample_parameters.seed = Some(sample.seed);
+                sample_parameters
+            })
+            .collect()
+    }
+}
+
+/// Разбирает значение v-bpa:consensusModel вида "model" или "model=weight"
+fn parse_model_weight(value: &str, method: VoteMethod) -> Option<(String, f64)> {
+    let (model, weight) = match value.rsplit_once('=') {
+        Some((model, weight)) => match weight.trim().parse::<f64>() {
+            Ok(weight) if weight > 0.0 => (model.trim(), weight),
+            _ => {
+                warn!("Skipping consensus model with invalid weight: {}", value);
+                return None;
+            },
+        },
+        None => (value.trim(), 1.0),
+    };
+    if model.is_empty() {
+        return None;
+    }
+
+    let weight = match method {
+        VoteMethod::Majority => 1.0,
+        VoteMethod::Weighted => weight,
+    };
+    Some((model.to_string(), weight))
+}
+
+/// Подсчитывает голоса выборок и возвращает победивший вариант и уверенность -
+/// долю веса голосов, поданных за него
+///
+/// При равенстве голосов побеждает вариант, первым встретившийся среди выборок.
+pub fn vote<T: Eq + Hash + Clone>(votes: &[(T, f64)]) -> Option<(T, f64)> {
+    let total: f64 = votes.iter().map(|(_, weight)| weight).sum();
+    if votes.is_empty() || total <= 0.0 {
+        return None;
+    }
+
+    let mut tally: HashMap<&T, f64> = HashMap::new();
+    for (value, weight) in votes {
+        *tally.entry(value).or_default() += weight;
+    }
+
+    let mut winner: Option<(&T, f64)> = None;
+    for (value, _) in votes {
+        let weight = tally[value];
+        if winner.is_none_or(|(_, best)| weight > best) {
+            winner = Some((value, weight));
+        }
+    }
+
+    winner.map(|(value, weight)| (value.clone(), weight / total))
+}
+
+/// Записывает уверенность вердикта в индивид и помечает его v-bpa:NeedsReview при низкой уверенности
+pub fn set_verdict_confidence(individual: &mut Individual, confidence: f64, threshold: f64) {
+    individual.set_decimal_from_f64("v-bpa:verdictConfidence", confidence);
+    if confidence < threshold {
+        info!("Verdict of {} has low confidence {:.2}, marking for review", individual.get_id(), confidence);
+        individual.set_uri("v-bpa:hasReviewTag", "v-bpa:NeedsReview");
+    } else {
+        individual.remove("v-bpa:hasReviewTag");
+    }
+}
+
+/// Убирает уверенность вердикта, оставшуюся от анализа с включенным консенсусом
+pub fn clear_verdict_confidence(individual: &mut Individual) {
+    individual.remove("v-bpa:verdictConfidence");
+    individual.remove("v-bpa:hasReviewTag");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vote_majority() {
+        let votes = [(true, 1.0), (false, 1.0), (true, 1.0)];
+        let (verdict, confidence) = vote(&votes).unwrap();
+
+        assert!(verdict);
+        assert!((confidence - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_vote_weighted() {
+        let votes = [("a", 0.5), ("b", 2.0), ("a", 1.0)];
+
+        assert_eq!(vote(&votes), Some(("b", 2.0 / 3.5)));
+    }
+
+    #[test]
+    fn test_vote_tie_first_value_wins() {
+        assert_eq!(vote(&[(false, 1.0), (true, 1.0)]), Some((false, 0.5)));
+        assert_eq!(vote(&[(true, 1.0), (false, 1.0)]), Some((true, 0.5)));
+        assert_eq!(vote(&[("b", 1.0), ("a", 2.0), ("b", 1.0)]), Some(("b", 0.5)));
+    }
+
+    #[test]
+    fn test_vote_unanimous() {
+        assert_eq!(vote(&[(true, 1.0)]), Some((true, 1.0)));
+        assert_eq!(vote(&[(false, 0.7), (false, 0.3)]), Some((false, 1.0)));
+    }
+
+    #[test]
+    fn test_vote_without_weight() {
+        assert_eq!(vote::<bool>(&[]), None);
+        assert_eq!(vote(&[(true, 0.0), (false, 0.0)]), None);
+    }
+
+    #[test]
+    fn test_parse_model_weight() {
+        assert_eq!(parse_model_weight("gpt-4o", VoteMethod::Weighted), Some(("gpt-4o".to_string(), 1.0)));
+        assert_eq!(parse_model_weight(" gpt-4o = 2.5 ", VoteMethod::Weighted), Some(("gpt-4o".to_string(), 2.5)));
+        // При голосовании большинством вес модели не учитывается
+        assert_eq!(parse_model_weight("gpt-4o=2.5", VoteMethod::Majority), Some(("gpt-4o".to_string(), 1.0)));
+        assert_eq!(parse_model_weight("gpt-4o=0", VoteMethod::Weighted), None);
+        assert_eq!(parse_model_weight("gpt-4o=abc", VoteMethod::Weighted), None);
+        assert_eq!(parse_model_weight("=2", VoteMethod::Weighted), None);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::business_process_handler::schedule_rejustification;
 use crate::common::generate_event_id;
 use crate::queue_processor::BusinessProcessAnalysisModule;
 use v_common::onto::individual::Individual;
@@ -44,6 +45,12 @@ pub fn handle_document_status(module: &mut BusinessProcessAnalysisModule, docume
         return Err(format!("Failed to update document: {:?}", e).into());
     }
 
+    // Justification of processes citing a modified document has to be re-evaluated
+    if document.any_exists("v-bpa:hasStatusTag", &["v-bpa:ModifiedDocumentTag"]) {
+        let scheduled = schedule_rejustification(module, document.get_id(), &event_id)?;
+        info!("Scheduled re-justification of {} processes citing document {}", scheduled, document.get_id());
+    }
+
     Ok(())
 }
 
@@ -0,0 +1,291 @@
+// entity_resolution.rs
+
+use crate::common::{get_individuals_uris_by_query, normalize_text};
+use crate::queue_processor::BusinessProcessAnalysisModule;
+use serde_json::{Map, Value};
+use std::collections::HashSet;
+use v_common::onto::datatype::Lang;
+use v_common::onto::individual::Individual;
+use v_common::v_api::obj::ResultCode;
+
+/// Порог совпадения по умолчанию, начиная с которого найденный индивид считается той же сущностью
+const DEFAULT_MATCH_THRESHOLD: f64 = 0.8;
+/// Ключевое поле сущности по умолчанию
+const DEFAULT_MATCH_FIELD: &str = "rdfs:label";
+/// Максимальное количество найденных в индексе индивидов, сравниваемых с сущностью
+const MAX_CANDIDATES: usize = 20;
+
+/// Настройки сопоставления сущности, заданные в описании объекта схемы ответа (для массивов - в items)
+///
+/// - match_by - ключевые поля сущности (по умолчанию rdfs:label)
+/// - match_threshold - порог совпадения от 0 до 1
+/// - create_new_individuals - создавать индивид для строкового значения ссылочного свойства, если совпадение не найдено
+#[derive(Debug, Clone)]
+pub struct MatchSettings {
+    pub fields: Vec<String>,
+    pub threshold: f64,
+    pub create_new: bool,
+}
+
+impl MatchSettings {
+    pub fn from_schema(additional: &Map<String, Value>) -> Self {
+        let mut fields: Vec<String> = match additional.get("match_by") {
+            Some(Value::Array(arr)) => arr.iter().filter_map(Value::as_str).map(str::to_string).collect(),
+            Some(Value::String(field)) => vec![field.clone()],
+            _ => Vec::new(),
+        };
+        if fields.is_empty() {
+            fields.push(DEFAULT_MATCH_FIELD.to_string());
+        }
+
+        MatchSettings {
+            fields,
+            threshold: additional.get("match_threshold").and_then(Value::as_f64).unwrap_or(DEFAULT_MATCH_THRESHOLD),
+            create_new: additional.get("create_new_individuals").and_then(Value::as_bool).unwrap_or(false),
+        }
+    }
+}
+
+impl Default for MatchSettings {
+    fn default() -> Self {
+        MatchSettings::from_schema(&Map::new())
+    }
+}
+
+/// Сущность из ответа AI, которую нужно сопоставить с существующими индивидами
+#[derive(Debug, Clone)]
+pub enum EntityCandidate {
+    /// Индивид, созданный из объекта ответа AI и связанный с родителем свойством link_property
+    Individual { id: String, parent_id: String, link_property: String, type_uri: String, settings: MatchSettings },
+    /// Строковое значение ссылочного свойства, не найденное среди значений перечисления
+    Reference { parent_id: String, property: String, type_uri: String, label: String, is_multiple: bool, settings: MatchSettings },
+}
+
+/// Сопоставляет сущности ответа AI с существующими индивидами того же типа по ключевым полям,
+/// поиск выполняется через полнотекстовый индекс
+///
+/// Найденный индивид заменяет созданный в связи родителя, созданный индивид и вложенные в него индивиды не сохраняются.
+/// Для строкового значения без совпадения создается новый индивид, если это разрешено create_new_individuals,
+/// иначе значение записывается строкой.
+pub fn resolve_entities(
+    module: &mut BusinessProcessAnalysisModule,
+    main_individual: &mut Individual,
+    related_individuals: &mut Vec<Individual>,
+    candidates: Vec<EntityCandidate>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut dropped: HashSet<String> = HashSet::new();
+
+    let (entities, references): (Vec<_>, Vec<_>) = candidates.into_iter().partition(|candidate| matches!(candidate, EntityCandidate::Individual { .. }));
+
+    // Вложенные индивиды создаются раньше родителей, поэтому обход с конца сопоставляет сначала родителей
+    for candidate in entities.into_iter().rev() {
+        if let EntityCandidate::Individual {
+            id,
+            parent_id,
+            link_property,
+            type_uri,
+            settings,
+        } = candidate
+        {
+            if dropped.contains(&parent_id) {
+                dropped.insert(id);
+                continue;
+            }
+
+            let key_values = match related_individuals.iter_mut().find(|individual| individual.get_id() == id) {
+                Some(individual) => get_key_values(individual, &settings.fields),
+                None => continue,
+            };
+
+            if let Some(existing_id) = find_existing_entity(module, &type_uri, &key_values, settings.threshold)? {
+                info!("Entity {} of type {} resolved to existing individual {}", id, type_uri, existing_id);
+                if let Some(parent) = find_individual(main_individual, related_individuals, &parent_id) {
+                    replace_link(parent, &link_property, &id, &existing_id);
+                }
+                dropped.insert(id);
+            }
+        }
+    }
+
+    // Строковые значения обрабатываются в порядке ответа, чтобы сохранить порядок значений множественных свойств
+    for candidate in references {
+        if let EntityCandidate::Reference {
+            parent_id,
+            property,
+            type_uri,
+            label,
+            is_multiple,
+            settings,
+        } = candidate
+        {
+            if dropped.contains(&parent_id) {
+                continue;
+            }
+
+            let key_values = vec![(settings.fields[0].clone(), vec![label.clone()])];
+            let existing_id = find_existing_entity(module, &type_uri, &key_values, settings.threshold)?;
+
+            let mut created = None;
+            let link = match existing_id {
+                Some(existing_id) => {
+                    info!("Value '{}' of {} resolved to existing individual {}", label, property, existing_id);
+                    Some(existing_id)
+                },
+                None if settings.create_new => {
+                    let individual = new_entity(&type_uri, &settings.fields[0], &label);
+                    info!("No existing {} matches '{}', created {}", type_uri, label, individual.get_id());
+                    let id = individual.get_id().to_string();
+                    created = Some(individual);
+                    Some(id)
+                },
+                None => None,
+            };
+
+            if let Some(parent) = find_individual(main_individual, related_individuals, &parent_id) {
+                match (&link, is_multiple) {
+                    (Some(uri), true) => parent.add_uri(&property, uri),
+                    (Some(uri), false) => parent.set_uri(&property, uri),
+                    (None, true) => parent.add_string(&property, &label, Lang::none()),
+                    (None, false) => parent.set_string(&property, &label, Lang::none()),
+                }
+            }
+            if let Some(individual) = created {
+                related_individuals.push(individual);
+            }
+        }
+    }
+
+    related_individuals.retain(|individual| !dropped.contains(individual.get_id()));
+    Ok(())
+}
+
+/// Ищет существующий индивид заданного типа, ключевые поля которого совпадают с сущностью не ниже порога
+fn find_existing_entity(
+    module: &mut BusinessProcessAnalysisModule,
+    type_uri: &str,
+    key_values: &[(String, Vec<String>)],
+    threshold: f64,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    // Для поиска в индексе используется первое заполненное ключевое поле, остальные учитываются при оценке
+    let (field, text) = match key_values.iter().find_map(|(field, values)| values.first().map(|value| (field, normalize_text(value)))) {
+        Some((field, text)) if !text.is_empty() => (field, text),
+        _ => return Ok(None),
+    };
+
+    let query = format!("'rdf:type' === '{}' && '{}' == '{}'", type_uri, field, text);
+    let mut best: Option<(String, f64)> = None;
+
+    for id in get_individuals_uris_by_query(module, &query)?.into_iter().take(MAX_CANDIDATES) {
+        let mut existing = Individual::default();
+        if module.backend.storage.get_individual(&id, &mut existing) != ResultCode::Ok {
+            continue;
+        }
+        existing.parse_all();
+        if existing.get_first_bool("v-s:deleted").unwrap_or(false) {
+            continue;
+        }
+
+        let score = match_score(&mut existing, key_values);
+        if score >= threshold && best.as_ref().is_none_or(|(_, best_score)| score > *best_score) {
+            best = Some((id, score));
+        }
+    }
+
+    if let Some((id, score)) = &best {
+        debug!("Best match for '{}' in {}: {} ({:.2})", text, type_uri, id, score);
+    }
+    Ok(best.map(|(id, _)| id))
+}
+
+/// Оценка совпадения сущности с индивидом: среднее по ключевым полям лучшего сходства значений
+fn match_score(existing: &mut Individual, key_values: &[(String, Vec<String>)]) -> f64 {
+    if key_values.is_empty() {
+        return 0.0;
+    }
+
+    let total: f64 = key_values
+        .iter()
+        .map(|(field, values)| {
+            let existing_values = existing.get_literals(field).unwrap_or_default();
+            values.iter().flat_map(|value| existing_values.iter().map(move |existing_value| text_similarity(value, existing_value))).fold(0.0, f64::max)
+        })
+        .sum();
+
+    total / key_values.len() as f64
+}
+
+/// Сходство двух строк: коэффициент Жаккара множеств слов после нормализации
+fn text_similarity(a: &str, b: &str) -> f64 {
+    let a_words: HashSet<String> = normalize_text(a).split(' ').filter(|w| !w.is_empty()).map(str::to_string).collect();
+    let b_words: HashSet<String> = normalize_text(b).split(' ').filter(|w| !w.is_empty()).map(str::to_string).collect();
+
+    let union = a_words.union(&b_words).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a_words.intersection(&b_words).count() as f64 / union as f64
+}
+
+/// Сходство нормализованных меток: лучшее из сходства по словам и по символам
+///
+/// Сходство по символам (расстояние Левенштейна) учитывает опечатки и другие формы слова,
+/// которые не совпадают как целые слова.
+pub fn label_similarity(a: &str, b: &str) -> f64 {
+    let a_chars: Vec<char> = a.chars().collect();
+    let b_chars: Vec<char> = b.chars().collect();
+    let max_len = a_chars.len().max(b_chars.len());
+    if max_len == 0 {
+        return 0.0;
+    }
+
+    let mut previous: Vec<usize> = (0..=b_chars.len()).collect();
+    for (i, a_char) in a_chars.iter().enumerate() {
+        let mut current = vec![i + 1; b_chars.len() + 1];
+        for (j, b_char) in b_chars.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    let char_similarity = 1.0 - previous[b_chars.len()] as f64 / max_len as f64;
+    char_similarity.max(text_similarity(a, b))
+}
+
+/// Возвращает заполненные ключевые поля индивида
+fn get_key_values(individual: &mut Individual, fields: &[String]) -> Vec<(String, Vec<String>)> {
+    fields.iter().filter_map(|field| individual.get_literals(field).filter(|values| !values.is_empty()).map(|values| (field.clone(), values))).collect()
+}
+
+fn find_individual<'a>(main_individual: &'a mut Individual, related_individuals: &'a mut [Individual], id: &str) -> Option<&'a mut Individual> {
+    if main_individual.get_id() == id {
+        return Some(main_individual);
+    }
+    related_individuals.iter_mut().find(|individual| individual.get_id() == id)
+}
+
+/// Заменяет в связи родителя созданный индивид найденным
+fn replace_link(parent: &mut Individual, link_property: &str, created_id: &str, existing_id: &str) {
+    let links: Vec<String> = parent
+        .get_literals(link_property)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|link| {
+            if link == created_id {
+                existing_id.to_string()
+            } else {
+                link
+            }
+        })
+        .collect();
+    parent.set_uris(link_property, links);
+}
+
+/// Создает индивид для значения ссылочного свойства, для которого не нашлось существующей сущности
+fn new_entity(type_uri: &str, key_field: &str, label: &str) -> Individual {
+    let mut individual = Individual::default();
+    individual.set_id(&format!("d:{}", uuid::Uuid::new_v4()));
+    individual.set_uri("rdf:type", type_uri);
+    individual.set_string(key_field, label, Lang::none());
+    individual
+}
@@ -5,9 +5,11 @@ use crate::common::{
     convert_full_to_short_predicates, convert_short_to_full_predicates, generate_event_id, load_schema, prepare_request_ai_parameters,
     set_to_individual_from_ai_response, ClientType,
 };
+use crate::output_language::resolve_output_language;
 use crate::process_structured_schema;
+use crate::prompt_manager::PromptContext;
+use crate::prompt_version::resolve_prompt_version;
 use crate::queue_processor::BusinessProcessAnalysisModule;
-use crate::types::PropertyMapping;
 use serde_json::Value;
 use tokio::runtime::Runtime;
 use v_common::onto::datatype::Lang;
@@ -56,8 +58,13 @@ fn process_ontology_input(
     }
     target_type_def.parse_all();
 
-    let mut property_mapping = PropertyMapping::new();
-    let property_schema = load_schema(module, &prompt_individual.get_id(), None, &mut property_mapping)?;
+    // Текст и схема берутся из версии промпта, закрепленной за запросом, или из текущей версии
+    let prompt_version = resolve_prompt_version(module, prompt_individual.get_id(), Some(&mut *request));
+    let language = request.get_first_literal("v-bpa:promptLanguage");
+    let mut schema = load_schema(module, &prompt_version, None, language.as_deref())?;
+    let output_language = resolve_output_language(module, &prompt_version, Some(&mut *request), None);
+    schema.output_language = Some(output_language.clone());
+    let mut property_mapping = schema.property_mapping();
 
     // Process input data if available
     let structured_input = if let Some(input_str) = request.get_first_literal("v-bpa:structuredInput") {
@@ -76,8 +83,12 @@ fn process_ontology_input(
     // Prepare data for analysis
     let analysis_data = prepare_analysis_data(&raw_input, &mut target_type_def, structured_input)?;
 
+    // Prompt template variables: date, language, department and target type
+    let mut context = PromptContext::for_request(module, Some(&mut *request), None, &output_language);
+    context.set_target_type(module, &target_type, &output_language);
+
     // Create request parameters and get property mapping
-    let req_to_ai = prepare_request_ai_parameters(module, &prompt_individual.get_id(), analysis_data, property_schema, &mut property_mapping)?;
+    let req_to_ai = prepare_request_ai_parameters(module, &prompt_version, analysis_data, &schema, &context)?;
 
     save_to_interaction_file(&serde_json::to_string_pretty(&req_to_ai)?, "request", "json")?;
 
@@ -91,7 +102,7 @@ fn process_ontology_input(
     if is_structured_input {
         if let Some(result) = ai_response.get("result") {
             // Convert short names and human-readable values back to URIs
-            let mapped_result = convert_short_to_full_predicates(result, &property_mapping)?;
+            let mapped_result = convert_short_to_full_predicates(result, &property_mapping, &schema)?;
             request.set_string("v-bpa:structuredOutput", &mapped_result.to_string(), Lang::none());
         }
     } else {
@@ -103,7 +114,7 @@ fn process_ontology_input(
         result_individual.set_uri("v-bpa:targetType", &target_type);
 
         // Сохраняем результат анализа AI, включая очищенный текст
-        set_to_individual_from_ai_response(module, &mut result_individual, &ai_response, &property_mapping)?;
+        set_to_individual_from_ai_response(module, &mut result_individual, &ai_response, &schema, event_id)?;
 
         // Save updated individual
         if let Err(e) = module.backend.mstorage_api.update_or_err(&module.ticket, event_id, "BPA", IndvOp::Put, &mut result_individual) {
@@ -0,0 +1,127 @@
+// justification_citations.rs
+
+use crate::ai_client::AIResponseValues;
+use crate::common::normalize_text;
+use crate::queue_processor::BusinessProcessAnalysisModule;
+use crate::schema_compiler::CompiledSchema;
+use serde_json::Value;
+use std::collections::HashMap;
+use v_common::onto::datatype::Lang;
+use v_common::onto::individual::Individual;
+use v_common::v_api::api_client::IndvOp;
+use v_common::v_api::obj::ResultCode;
+
+/// Короткое имя свойства со ссылками на цитаты в ответе AI
+const CITATIONS_SHORT_NAME: &str = "hasJustificationCitation";
+const CITATIONS_PROPERTY: &str = "v-bpa:hasJustificationCitation";
+
+/// Добавляет в схему ответа AI массив цитат из документов обоснования
+///
+/// Поле попадает в список обязательных полей, прежние ссылки на цитаты очищаются при сохранении ответа,
+/// сами цитаты сохраняются в save_citations.
+pub fn add_citations_to_schema(schema: &mut CompiledSchema) {
+    schema.add_custom_field(
+        CITATIONS_SHORT_NAME,
+        CITATIONS_PROPERTY,
+        serde_json::json!({
+            "type": "array",
+            "description": "Цитаты из документов, на которых основана оценка",
+            "items": {
+                "type": "object",
+                "additionalProperties": false,
+                "properties": {
+                    "document": {
+                        "type": "string",
+                        "description": "Идентификатор документа (поле id)"
+                    },
+                    "excerpt": {
+                        "type": "string",
+                        "description": "Точная цитата из содержания документа без изменений"
+                    },
+                    "relevance": {
+                        "type": "string",
+                        "description": "Как цитата подтверждает или опровергает процесс"
+                    }
+                },
+                "required": ["document", "excerpt", "relevance"]
+            }
+        }),
+    );
+}
+
+/// Сохраняет цитаты из ответа AI как индивиды v-bpa:JustificationCitation и связывает их с процессом
+///
+/// Цитаты, которых нет в тексте документа процесса, отклоняются, их количество
+/// записывается в v-bpa:rejectedCitationCount. Цитаты предыдущего анализа помечаются удаленными.
+pub fn save_citations(
+    module: &mut BusinessProcessAnalysisModule,
+    process: &mut Individual,
+    ai_response: &AIResponseValues,
+    previous_citations: &[String],
+    event_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let citations = ai_response.get("result").and_then(|r| r.get(CITATIONS_SHORT_NAME)).and_then(Value::as_array).cloned().unwrap_or_default();
+
+    // Тексты документов процесса для проверки цитат
+    let mut documents = HashMap::new();
+    for document_id in process.get_literals("v-bpa:hasProcessDocument").unwrap_or_default() {
+        let mut document = Individual::default();
+        if module.backend.storage.get_individual(&document_id, &mut document) == ResultCode::Ok {
+            document.parse_all();
+            documents.insert(document_id, normalize_text(&document.get_first_literal("v-bpa:documentContent").unwrap_or_default()));
+        } else {
+            warn!("Failed to load document {} for citation check", document_id);
+        }
+    }
+
+    remove_previous_citations(module, process, previous_citations, event_id);
+
+    let mut rejected = 0;
+    for citation in citations {
+        let document_id = citation["document"].as_str().unwrap_or_default();
+        let excerpt = citation["excerpt"].as_str().unwrap_or_default();
+        let relevance = citation["relevance"].as_str().unwrap_or_default();
+
+        let is_verified = documents.get(document_id).is_some_and(|content| {
+            let normalized = normalize_text(excerpt);
+            !normalized.is_empty() && content.contains(&normalized)
+        });
+        if !is_verified {
+            warn!("Rejecting citation not found in document {} of process {}: {}", document_id, process.get_id(), excerpt);
+            rejected += 1;
+            continue;
+        }
+
+        let mut citation_indv = Individual::default();
+        let citation_id = format!("d:bpa_citation_{}", uuid::Uuid::new_v4());
+        citation_indv.set_id(&citation_id);
+        citation_indv.set_uri("rdf:type", "v-bpa:JustificationCitation");
+        citation_indv.set_uri("v-bpa:citingProcess", process.get_id());
+        citation_indv.set_uri("v-bpa:citedDocument", document_id);
+        citation_indv.set_string("v-bpa:citationExcerpt", excerpt, Lang::none());
+        citation_indv.set_string("v-bpa:citationRelevance", relevance, Lang::none());
+
+        if let Err(e) = module.backend.mstorage_api.update_or_err(&module.ticket, event_id, "BPA", IndvOp::Put, &citation_indv) {
+            error!("Failed to save citation {}: {:?}", citation_id, e);
+            return Err(format!("Failed to save citation, err={:?}", e).into());
+        }
+        process.add_uri(CITATIONS_PROPERTY, &citation_id);
+    }
+
+    process.set_integer("v-bpa:rejectedCitationCount", rejected);
+    info!("Saved citations for process {}, rejected {}", process.get_id(), rejected);
+    Ok(())
+}
+
+/// Помечает удаленными цитаты предыдущего анализа процесса
+fn remove_previous_citations(module: &mut BusinessProcessAnalysisModule, process: &mut Individual, previous_citations: &[String], event_id: &str) {
+    for citation_id in previous_citations {
+        let mut citation = Individual::default();
+        citation.set_id(citation_id);
+        citation.set_bool("v-s:deleted", true);
+        if let Err(e) = module.backend.mstorage_api.update_or_err(&module.ticket, event_id, "BPA", IndvOp::SetIn, &citation) {
+            warn!("Failed to remove previous citation {}: {:?}", citation_id, e);
+        }
+    }
+    process.remove(CITATIONS_PROPERTY);
+}
@@ -4,6 +4,7 @@
 extern crate log;
 
 use crate::queue_processor::BusinessProcessAnalysisModule;
+use crate::schema_cache::SchemaCache;
 use openai_dive::v1::api::Client;
 use serde::{Deserialize, Serialize};
 use v_common::ft_xapian::xapian_reader::XapianReader;
@@ -14,24 +15,45 @@ use v_common::module::veda_backend::Backend;
 use v_common::storage::common::StorageMode;
 
 mod ai_client;
+mod bulk_analysis_handler;
 mod business_process_handler;
+mod cluster_metrics;
+mod cluster_stability;
 mod cluster_optimizer;
+mod cluster_savings;
 mod clustering_handler;
 mod common;
+mod consensus;
+mod entity_resolution;
+mod ontology_lookup;
+mod optimization_history;
+mod output_language;
+mod prompt_lint;
 mod prompt_manager;
+mod prompt_version;
+mod proposal_handler;
 mod queue_processor;
 pub mod response_schema;
+mod review_workflow;
+mod schema_cache;
+mod schema_compiler;
 mod types;
+mod value_types;
 
 mod clustering_common;
+mod clustering_scope;
 mod extractors;
 mod generic_processing_handler;
+mod justification_citations;
 mod pipeline;
 
 mod document_status_handler;
 
 mod process_structured_schema;
 
+#[cfg(test)]
+mod tests;
+
 #[derive(Debug, Serialize, Deserialize)]
 struct ApiConfig {
     api_key: String,
@@ -119,8 +141,15 @@ fn main() -> std::io::Result<()> {
         reasoning_model: providers_config.reasoning_model,
         ticket: systicket,
         module_info: module_info.unwrap(),
+        schema_cache: SchemaCache::default(),
     };
 
+    // Check response schemas of all system prompts against the ontology
+    prompt_lint::lint_all_prompts(&mut my_module);
+
+    // Snapshot prompts changed while the module was stopped, results refer to prompt versions
+    prompt_version::snapshot_all_prompts(&mut my_module, "");
+
     module.prepare_queue(&mut my_module);
 
     Ok(())
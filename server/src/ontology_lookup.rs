@@ -0,0 +1,235 @@
+// ontology_lookup.rs
+
+use crate::common::get_individuals_by_type;
+use crate::queue_processor::BusinessProcessAnalysisModule;
+#[cfg(test)]
+use crate::value_types::parse_datetime;
+#[cfg(test)]
+use rio_api::model::{Literal, NamedOrBlankNode, Term};
+#[cfg(test)]
+use rio_api::parser::TriplesParser;
+#[cfg(test)]
+use rio_turtle::{TurtleError, TurtleParser};
+#[cfg(test)]
+use std::collections::HashMap;
+#[cfg(test)]
+use std::fs;
+#[cfg(test)]
+use std::io::BufReader;
+#[cfg(test)]
+use std::path::Path;
+use v_common::module::veda_backend::Backend;
+use v_common::onto::datatype::Lang;
+use v_common::onto::individual::Individual;
+use v_common::v_api::obj::ResultCode;
+
+/// Описание свойства онтологии, используемое при компиляции и проверке схемы
+pub struct PropertyDefinition {
+    pub is_class: bool,
+    pub is_functional: bool,
+    pub range: Option<String>,
+    pub label: Option<String>,
+    pub lang: Option<String>,
+}
+
+/// Доступ к онтологии, нужный схемам ответа AI: описания свойств и экземпляры классов перечислений
+///
+/// Реализуется модулем (хранилище и полнотекстовый индекс), хранилищем Backend и хранилищем в памяти,
+/// загруженным из Turtle, поэтому схемы можно проверять без хранилища Veda.
+pub trait OntologyLookup {
+    /// Загружает индивид, None - индивид не найден
+    fn get_individual(&mut self, id: &str) -> Option<Individual>;
+
+    /// Возвращает экземпляры класса
+    fn list_instances(&mut self, class: &str) -> Vec<Individual>;
+
+    /// Возвращает описание свойства, None - свойство не найдено
+    fn get_property(&mut self, property: &str) -> Option<PropertyDefinition> {
+        let mut prop_individual = self.get_individual(property)?;
+
+        Some(PropertyDefinition {
+            is_class: prop_individual.any_exists("rdf:type", &["owl:Class"]),
+            is_functional: prop_individual.any_exists("rdf:type", &["owl:FunctionalProperty"]),
+            range: prop_individual.get_first_literal("rdfs:range"),
+            label: prop_individual.get_first_literal_with_lang("rdfs:label", &[Lang::new_from_i64(1)]),
+            lang: prop_individual.get_first_literal("v-bpa:valueLanguage"),
+        })
+    }
+}
+
+impl OntologyLookup for BusinessProcessAnalysisModule {
+    fn get_individual(&mut self, id: &str) -> Option<Individual> {
+        OntologyLookup::get_individual(&mut self.backend, id)
+    }
+
+    fn list_instances(&mut self, class: &str) -> Vec<Individual> {
+        match get_individuals_by_type(self, class) {
+            Ok(instances) => instances,
+            Err(e) => {
+                error!("Failed to get instances of type {}: {:?}", class, e);
+                Vec::new()
+            },
+        }
+    }
+}
+
+/// Только хранилище: без полнотекстового индекса экземпляры классов не перечисляются,
+/// ссылки сопоставляются при записи ответа
+impl OntologyLookup for Backend {
+    fn get_individual(&mut self, id: &str) -> Option<Individual> {
+        let mut individual = Individual::default();
+        if self.storage.get_individual(id, &mut individual) != ResultCode::Ok {
+            return None;
+        }
+        individual.parse_all();
+        Some(individual)
+    }
+
+    fn list_instances(&mut self, _class: &str) -> Vec<Individual> {
+        Vec::new()
+    }
+}
+
+/// Онтология в памяти, загруженная из файлов Turtle
+///
+/// URI записываются в сокращенной форме по префиксам файла (v-bpa:SystemPrompt), как в хранилище Veda.
+/// Используется тестами схем ответа.
+#[cfg(test)]
+#[derive(Default)]
+pub struct TurtleOntology {
+    individuals: HashMap<String, Individual>,
+}
+
+#[cfg(test)]
+impl TurtleOntology {
+    /// Загружает все файлы *.ttl каталога
+    pub fn from_dir(dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut ontology = TurtleOntology::default();
+
+        let mut paths: Vec<_> =
+            fs::read_dir(dir)?.filter_map(|entry| entry.ok().map(|entry| entry.path())).filter(|path| path.extension().is_some_and(|ext| ext == "ttl")).collect();
+        paths.sort();
+
+        for path in paths {
+            ontology.load_file(&path)?;
+        }
+        Ok(ontology)
+    }
+
+    pub fn load_file(&mut self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        self.load_str(&content).map_err(|e| format!("Failed to parse {}: {}", path.display(), e).into())
+    }
+
+    /// Добавляет тройки документа Turtle к индивидам онтологии
+    pub fn load_str(&mut self, content: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let prefixes = read_prefixes(content);
+        let mut parser = TurtleParser::new(BufReader::new(content.as_bytes()), None);
+
+        parser.parse_all(&mut |triple| -> Result<(), TurtleError> {
+            // Пустые узлы (списки, ограничения OWL) схемам не нужны
+            let subject = match triple.subject {
+                NamedOrBlankNode::NamedNode(node) => shorten_iri(node.iri, &prefixes),
+                NamedOrBlankNode::BlankNode(_) => return Ok(()),
+            };
+            let predicate = shorten_iri(triple.predicate.iri, &prefixes);
+
+            let individual = self.individuals.entry(subject.clone()).or_insert_with(|| {
+                let mut individual = Individual::default();
+                individual.set_id(&subject);
+                individual
+            });
+
+            match triple.object {
+                Term::NamedNode(node) => individual.add_uri(&predicate, &shorten_iri(node.iri, &prefixes)),
+                Term::BlankNode(_) => {},
+                Term::Literal(Literal::Simple {
+                    value,
+                }) => individual.add_string(&predicate, value, Lang::none()),
+                Term::Literal(Literal::LanguageTaggedString {
+                    value,
+                    language,
+                }) => individual.add_string(&predicate, value, Lang::new_from_str(language)),
+                Term::Literal(Literal::Typed {
+                    value,
+                    datatype,
+                }) => add_typed_literal(individual, &predicate, value, &shorten_iri(datatype.iri, &prefixes)),
+            }
+            Ok(())
+        })?;
+
+        Ok(())
+    }
+
+    /// Добавляет индивид в онтологию, заменяя индивид с тем же идентификатором
+    pub fn insert(&mut self, individual: &Individual) {
+        self.individuals.insert(individual.get_id().to_string(), Individual::new_from_obj(individual.get_obj()));
+    }
+}
+
+#[cfg(test)]
+impl OntologyLookup for TurtleOntology {
+    fn get_individual(&mut self, id: &str) -> Option<Individual> {
+        self.individuals.get(id).map(|individual| Individual::new_from_obj(individual.get_obj()))
+    }
+
+    fn list_instances(&mut self, class: &str) -> Vec<Individual> {
+        let mut instances = Vec::new();
+        for individual in self.individuals.values_mut() {
+            if individual.any_exists("rdf:type", &[class]) {
+                instances.push(Individual::new_from_obj(individual.get_obj()));
+            }
+        }
+        instances.sort_by(|a, b| a.get_id().cmp(b.get_id()));
+        instances
+    }
+}
+
+/// Читает объявления префиксов документа: полный IRI -> префикс с двоеточием
+#[cfg(test)]
+fn read_prefixes(content: &str) -> Vec<(String, String)> {
+    let mut prefixes: Vec<(String, String)> = content
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            let declaration = line.strip_prefix("@prefix").or_else(|| line.strip_prefix("PREFIX"))?;
+            let (prefix, rest) = declaration.trim().split_once(char::is_whitespace)?;
+            let iri = rest.trim().strip_prefix('<')?.split('>').next()?;
+            Some((iri.to_string(), prefix.to_string()))
+        })
+        .collect();
+
+    // Более длинный IRI проверяется раньше, чтобы вложенные пространства имен сокращались правильно
+    prefixes.sort_by_key(|(iri, _)| std::cmp::Reverse(iri.len()));
+    prefixes
+}
+
+#[cfg(test)]
+fn shorten_iri(iri: &str, prefixes: &[(String, String)]) -> String {
+    for (full, prefix) in prefixes {
+        if let Some(local) = iri.strip_prefix(full.as_str()) {
+            return format!("{}{}", prefix, local);
+        }
+    }
+    iri.to_string()
+}
+
+#[cfg(test)]
+fn add_typed_literal(individual: &mut Individual, predicate: &str, value: &str, datatype: &str) {
+    match datatype {
+        "xsd:boolean" => individual.add_bool(predicate, value == "true" || value == "1"),
+        "xsd:integer" | "xsd:int" | "xsd:long" | "xsd:nonNegativeInteger" => match value.parse() {
+            Ok(number) => individual.add_integer(predicate, number),
+            Err(_) => individual.add_string(predicate, value, Lang::none()),
+        },
+        "xsd:decimal" | "xsd:double" | "xsd:float" => match value.parse() {
+            Ok(number) => individual.add_decimal_from_f64(predicate, number),
+            Err(_) => individual.add_string(predicate, value, Lang::none()),
+        },
+        "xsd:dateTime" | "xsd:date" => match parse_datetime(value) {
+            Some(timestamp) => individual.add_datetime(predicate, timestamp),
+            None => individual.add_string(predicate, value, Lang::none()),
+        },
+        _ => individual.add_string(predicate, value, Lang::none()),
+    }
+}
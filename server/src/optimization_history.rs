@@ -0,0 +1,141 @@
+// optimization_history.rs
+
+use crate::ai_client::AIResponseValues;
+use crate::cluster_savings::{self, SavingsEstimate};
+use crate::common::{generate_event_id, load_schema, set_to_individual_from_ai_response};
+use crate::output_language::resolve_output_language;
+use crate::prompt_version::get_version_number;
+use crate::queue_processor::BusinessProcessAnalysisModule;
+use crate::schema_compiler::CompiledSchema;
+use chrono::Utc;
+use std::collections::HashMap;
+use v_common::onto::datatype::Lang;
+use v_common::onto::individual::Individual;
+use v_common::v_api::api_client::IndvOp;
+use v_common::v_api::obj::ResultCode;
+
+/// Входные данные и условия запуска оптимизации кластера
+pub struct OptimizationInput<'a> {
+    /// Версия промпта, с которой выполнена оптимизация
+    pub prompt_id: &'a str,
+    pub model: &'a str,
+    /// Процессы кластера и их v-s:updateCounter на момент запуска
+    pub processes: &'a [(String, i64)],
+}
+
+/// Сохраняет результат оптимизации кластера отдельным индивидом v-bpa:ClusterOptimization
+/// и добавляет его в историю оптимизаций кластера
+///
+/// Предыдущие результаты не изменяются, ссылка на предыдущий результат сохраняется в v-bpa:previousOptimization.
+/// Версия промпта схемы записывается в v-bpa:usedPromptVersion, ее номер - в v-bpa:promptVersion.
+/// Текущим результат делает вызывающая сторона, если он применен к кластеру.
+pub fn record_optimization(
+    module: &mut BusinessProcessAnalysisModule,
+    cluster: &mut Individual,
+    input: &OptimizationInput,
+    result: &AIResponseValues,
+    schema: &CompiledSchema,
+    savings: Option<&SavingsEstimate>,
+    event_id: &str,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let mut optimization = Individual::default();
+    let optimization_id = format!("d:bpa_cluster_optimization_{}", uuid::Uuid::new_v4());
+    optimization.set_id(&optimization_id);
+    optimization.set_uri("rdf:type", "v-bpa:ClusterOptimization");
+    optimization.set_uri("v-bpa:optimizedCluster", cluster.get_id());
+    optimization.set_uri("v-bpa:usedPrompt", input.prompt_id);
+    if let Some(version_number) = schema.prompt_version.as_deref().and_then(|version_id| get_version_number(module, version_id)) {
+        optimization.set_integer("v-bpa:promptVersion", version_number);
+    }
+    optimization.set_string("v-bpa:aiModel", input.model, Lang::none());
+    optimization.set_datetime("v-s:created", Utc::now().timestamp());
+
+    for (process_id, counter) in input.processes {
+        optimization.add_uri("v-bpa:inputProcess", process_id);
+        optimization.add_string("v-bpa:inputSnapshot", &format!("{}:{}", process_id, counter), Lang::none());
+    }
+
+    if let Some(previous_id) = cluster.get_first_literal("v-bpa:currentOptimization") {
+        optimization.set_uri("v-bpa:previousOptimization", &previous_id);
+    }
+
+    optimization.set_string("v-bpa:optimizationOutput", &serde_json::to_string(&result.to_json_value())?, Lang::none());
+    set_to_individual_from_ai_response(module, &mut optimization, result, schema, event_id)?;
+    if let Some(savings) = savings {
+        cluster_savings::set_cluster_savings(&mut optimization, savings);
+    }
+
+    if let Err(e) = module.backend.mstorage_api.update_or_err(&module.ticket, event_id, "BPA", IndvOp::Put, &optimization) {
+        error!("Failed to save cluster optimization {}: {:?}", optimization_id, e);
+        return Err(format!("Failed to save cluster optimization, err={:?}", e).into());
+    }
+
+    cluster.add_uri("v-bpa:hasOptimization", &optimization_id);
+
+    info!("Recorded optimization {} for cluster {}", optimization_id, cluster.get_id());
+    Ok(optimization_id)
+}
+
+/// Возвращает кластер к результату оптимизации, указанному в v-bpa:rollbackToOptimization
+pub fn rollback_optimization(module: &mut BusinessProcessAnalysisModule, cluster_id: &str, in_event_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let event_id = match generate_event_id("ROPT", cluster_id, in_event_id) {
+        Some(s) => s,
+        None => return Ok(()),
+    };
+
+    let mut cluster = Individual::default();
+    if module.backend.storage.get_individual(cluster_id, &mut cluster) != ResultCode::Ok {
+        return Err(format!("Failed to load cluster {}", cluster_id).into());
+    }
+    cluster.parse_all();
+
+    let optimization_id = match cluster.get_first_literal("v-bpa:rollbackToOptimization") {
+        Some(id) => id,
+        None => return Ok(()),
+    };
+    info!("Rolling back cluster {} to optimization {}", cluster.get_id(), optimization_id);
+
+    let mut optimization = Individual::default();
+    if module.backend.storage.get_individual(&optimization_id, &mut optimization) != ResultCode::Ok {
+        return Err(format!("Failed to load cluster optimization {}", optimization_id).into());
+    }
+    optimization.parse_all();
+
+    // Неизменный кластер новой попытки наследует историю оптимизаций предыдущего кластера
+    if !cluster.get_literals("v-bpa:hasOptimization").unwrap_or_default().contains(&optimization_id) {
+        return Err(format!("Optimization {} does not belong to cluster {}", optimization_id, cluster.get_id()).into());
+    }
+
+    let output = optimization.get_first_literal("v-bpa:optimizationOutput").ok_or("Optimization output not found")?;
+    let data: HashMap<String, serde_json::Value> = serde_json::from_str(&output)?;
+    let result = AIResponseValues::new(data, 0, 0);
+
+    // Схема берется из той же версии промпта, с которой получен результат
+    let prompt_id = optimization
+        .get_first_literal("v-bpa:usedPromptVersion")
+        .or_else(|| optimization.get_first_literal("v-bpa:usedPrompt"))
+        .unwrap_or_else(|| "v-bpa:OptimizeProcessesPrompt".to_string());
+    let mut schema = load_schema(module, &prompt_id, None, None)?;
+    schema.output_language = Some(resolve_output_language(module, &prompt_id, None, None));
+
+    // Значения, отсутствующие в выбранном результате, не должны остаться от текущего
+    for full_prop in schema.mapped_properties() {
+        cluster.remove(&full_prop);
+    }
+    set_to_individual_from_ai_response(module, &mut cluster, &result, &schema, &event_id)?;
+
+    if let Some(savings) = cluster_savings::get_cluster_savings(&mut optimization) {
+        cluster_savings::set_cluster_savings(&mut cluster, &savings);
+    }
+
+    cluster.set_uri("v-bpa:currentOptimization", &optimization_id);
+    cluster.remove("v-bpa:rollbackToOptimization");
+
+    if let Err(e) = module.backend.mstorage_api.update_or_err(&module.ticket, &event_id, "BPA", IndvOp::Put, &cluster) {
+        error!("Failed to update cluster {}: {:?}", cluster.get_id(), e);
+        return Err(format!("Failed to update cluster, err={:?}", e).into());
+    }
+
+    info!("Cluster {} rolled back to optimization {}", cluster.get_id(), optimization_id);
+    Ok(())
+}
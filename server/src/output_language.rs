@@ -0,0 +1,129 @@
+// output_language.rs
+
+use crate::common::get_individuals_uris_by_query;
+use crate::queue_processor::BusinessProcessAnalysisModule;
+use crate::value_types::DEFAULT_LANGUAGE;
+use v_common::onto::individual::Individual;
+use v_common::v_api::obj::ResultCode;
+
+const OUTPUT_LANGUAGE: &str = "v-bpa:outputLanguage";
+/// Свойства запроса и целевого индивида, ссылающиеся на подразделение
+const DEPARTMENT_PROPERTIES: &[&str] = &["v-bpa:targetDepartment", "v-bpa:hasDepartment"];
+/// Свойство бизнес-процесса с названием ответственного подразделения
+const DEPARTMENT_NAME_PROPERTY: &str = "v-bpa:responsibleDepartment";
+
+/// Определяет язык ответа AI по v-bpa:outputLanguage
+///
+/// Язык берется из запроса, затем из подразделения запроса или целевого индивида, затем из промпта,
+/// по умолчанию - русский. Подразделение бизнес-процесса задано названием (v-bpa:responsibleDepartment)
+/// и ищется среди v-s:Department по метке.
+pub fn resolve_output_language(module: &mut BusinessProcessAnalysisModule, prompt_id: &str, request: Option<&mut Individual>, target: Option<&mut Individual>) -> String {
+    let mut departments = Vec::new();
+    let mut department_names = Vec::new();
+    if let Some(request) = request {
+        if let Some(language) = request.get_first_literal(OUTPUT_LANGUAGE) {
+            return language.to_lowercase();
+        }
+        departments.extend(get_departments(request));
+        department_names.extend(get_department_names(request));
+    }
+    if let Some(target) = target {
+        departments.extend(get_departments(target));
+        department_names.extend(get_department_names(target));
+    }
+
+    for department_name in department_names {
+        departments.extend(find_departments_by_name(module, &department_name));
+    }
+
+    for department_id in departments {
+        if let Some(language) = load_output_language(module, &department_id) {
+            debug!("Output language {} is set by department {}", language, department_id);
+            return language;
+        }
+    }
+
+    load_output_language(module, prompt_id).unwrap_or_else(|| DEFAULT_LANGUAGE.to_string())
+}
+
+/// Системное сообщение, требующее отвечать на языке ответа
+pub fn language_instruction(language: &str) -> String {
+    let name = match language {
+        "ru" => "Russian",
+        "en" => "English",
+        "kk" => "Kazakh",
+        other => other,
+    };
+    format!("You must respond only in {} language. Use only {} for all text fields.", name, name)
+}
+
+/// Подразделения, на которые ссылается запрос или целевой индивид
+pub fn get_departments(individual: &mut Individual) -> Vec<String> {
+    DEPARTMENT_PROPERTIES.iter().flat_map(|property| individual.get_literals(property).unwrap_or_default()).collect()
+}
+
+/// Названия подразделений, указанные в запросе или целевом индивиде
+pub fn get_department_names(individual: &mut Individual) -> Vec<String> {
+    individual.get_literals(DEPARTMENT_NAME_PROPERTY).unwrap_or_default().into_iter().map(|name| name.trim().to_string()).filter(|name| !name.is_empty()).collect()
+}
+
+/// Подразделения v-s:Department, метка которых совпадает с названием
+fn find_departments_by_name(module: &mut BusinessProcessAnalysisModule, name: &str) -> Vec<String> {
+    let query = format!("'rdf:type' === 'v-s:Department' && 'rdfs:label' == '{}'", name.replace('\'', " "));
+    let candidates = match get_individuals_uris_by_query(module, &query) {
+        Ok(candidates) => candidates,
+        Err(e) => {
+            warn!("Failed to find department {}: {:?}", name, e);
+            return Vec::new();
+        },
+    };
+
+    // Полнотекстовый поиск находит и частичные совпадения, оставляем подразделения с точно такой меткой
+    candidates
+        .into_iter()
+        .filter(|department_id| {
+            let mut department = Individual::default();
+            if module.backend.storage.get_individual(department_id, &mut department) != ResultCode::Ok {
+                return false;
+            }
+            department.parse_all();
+            department.get_literals("rdfs:label").unwrap_or_default().iter().any(|label| label.trim().to_lowercase() == name.to_lowercase())
+        })
+        .collect()
+}
+
+fn load_output_language(module: &mut BusinessProcessAnalysisModule, id: &str) -> Option<String> {
+    let mut individual = Individual::default();
+    if module.backend.storage.get_individual(id, &mut individual) != ResultCode::Ok {
+        warn!("Failed to load {} to get output language", id);
+        return None;
+    }
+    individual.parse_all();
+    individual.get_first_literal(OUTPUT_LANGUAGE).map(|language| language.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use v_common::onto::datatype::Lang;
+
+    #[test]
+    fn test_language_instruction() {
+        assert_eq!(language_instruction("ru"), "You must respond only in Russian language. Use only Russian for all text fields.");
+        assert_eq!(language_instruction("kk"), "You must respond only in Kazakh language. Use only Kazakh for all text fields.");
+    }
+
+    #[test]
+    fn test_departments_of_process_and_document() {
+        let mut process = Individual::default();
+        process.set_uri("rdf:type", "v-bpa:BusinessProcess");
+        process.set_string("v-bpa:responsibleDepartment", " Отдел закупок ", Lang::none());
+        assert!(get_departments(&mut process).is_empty());
+        assert_eq!(get_department_names(&mut process), vec!["Отдел закупок"]);
+
+        let mut document = Individual::default();
+        document.set_uri("v-bpa:hasDepartment", "d:department_1");
+        assert_eq!(get_departments(&mut document), vec!["d:department_1"]);
+        assert!(get_department_names(&mut document).is_empty());
+    }
+}
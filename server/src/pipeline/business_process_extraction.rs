@@ -1,7 +1,11 @@
 use crate::ai_client::send_text_request_to_ai;
-use crate::common::{generate_event_id, get_prompt_text, ClientType};
+use crate::common::{generate_event_id, ClientType};
 use crate::generic_processing_handler::process_generic_request;
+use crate::output_language::resolve_output_language;
+use crate::prompt_manager::{get_system_prompt, PromptContext};
+use crate::prompt_version::resolve_prompt_version;
 use crate::queue_processor::BusinessProcessAnalysisModule;
+use crate::review_workflow;
 use chrono::Utc;
 use openai_dive::v1::resources::chat::{ChatCompletionParametersBuilder, ChatMessage, ChatMessageContent};
 use serde_json::json;
@@ -68,6 +72,10 @@ fn business_process_extraction_pipeline_internal(
     pipeline_req.set_datetime("v-bpa:startDate", start_time);
     pipeline_req.set_uri("v-bpa:processingStatus", "v-bpa:Processing");
 
+    // Fix prompt versions for the whole pipeline, they are saved with the initial state
+    let prompt_version = resolve_prompt_version(module, "v-bpa:ProcessExtractionPrompt", Some(&mut pipeline_req));
+    resolve_prompt_version(module, "v-bpa:ProcessListExtractionPrompt", Some(&mut pipeline_req));
+
     // Save initial state
     info!("Saving initial pipeline state to database...");
     if let Err(e) = module.backend.mstorage_api.update_or_err(&module.ticket, &event_id, "BPA", IndvOp::SetIn, &mut pipeline_req) {
@@ -75,8 +83,10 @@ fn business_process_extraction_pipeline_internal(
         return Err(format!("Failed to update pipeline state: {:?}", e).into());
     }
 
-    // Get prompt text
-    let prompt_text = get_prompt_text(module, "v-bpa:ProcessExtractionPrompt")?;
+    // Get prompt text, the template may refer to the target department of the pipeline
+    let output_language = resolve_output_language(module, &prompt_version, Some(&mut pipeline_req), None);
+    let context = PromptContext::for_request(module, Some(&mut pipeline_req), None, &output_language);
+    let prompt_text = get_system_prompt(module, &prompt_version, &context)?;
     info!("Retrieved extraction prompt");
 
     // Get target department
@@ -288,6 +298,12 @@ fn create_and_process_extraction_request(
     request.set_string("v-bpa:targetType", "v-bpa:BusinessProcess", Lang::none());
     request.set_uri("v-bpa:processingStatus", "v-bpa:Processing");
 
+    // Request uses the prompt version fixed at the pipeline start
+    let prompt_version = resolve_prompt_version(module, "v-bpa:ProcessListExtractionPrompt", Some(&mut *pipeline));
+    if prompt_version != "v-bpa:ProcessListExtractionPrompt" {
+        request.set_uri("v-bpa:pinnedPromptVersion", &prompt_version);
+    }
+
     // Add link to pipeline source
     request.set_uri("v-s:hasParentLink", pipeline.get_id());
 
@@ -337,6 +353,8 @@ fn create_and_process_extraction_request(
                                 // Remove service fields
                                 business_process.remove("v-bpa:action");
                                 business_process.remove("v-bpa:id");
+                                let ai_properties = review_workflow::generated_properties(&business_process);
+                                review_workflow::mark_pending_review(&mut business_process, &ai_properties);
 
                                 // Save business process
                                 if let Err(e) = module.backend.mstorage_api.update_or_err(&module.ticket, event_id, "BPA", IndvOp::Put, &mut business_process) {
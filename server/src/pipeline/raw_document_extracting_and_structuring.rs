@@ -3,7 +3,9 @@ use crate::document_status_handler::reset_document_status;
 use crate::extractors::types::ExtractedContent;
 use crate::extractors::types::ExtractedContent::Text;
 use crate::extractors::{extract_count_pages_document, extract_texts_or_images_from_document};
+use crate::prompt_version::resolve_prompt_version;
 use crate::queue_processor::BusinessProcessAnalysisModule;
+use crate::review_workflow;
 use chrono::Utc;
 use std::fs;
 use std::path::Path;
@@ -180,6 +182,12 @@ fn create_processing_request(
     request.set_uri("rdf:type", "v-bpa:GenericProcessingRequest");
     request.set_uri("v-bpa:prompt", prompt_id);
 
+    // Request of the stage uses the prompt version fixed by the pipeline
+    let prompt_version = resolve_prompt_version(module, prompt_id, Some(&mut *pipeline));
+    if prompt_version != prompt_id {
+        request.set_uri("v-bpa:pinnedPromptVersion", &prompt_version);
+    }
+
     match content {
         ExtractedContent::Text(t) => {
             request.set_string("v-bpa:rawInput", &t, Lang::none());
@@ -285,6 +293,12 @@ fn raw_document_extracting_and_structuring_internal(
             let extracted_contents = extract_texts_or_images_from_document(&content, &extension)?;
             info!("Pipeline [{}]: extracted {} content parts from document [{}]", pipeline.get_id(), extracted_contents.len(), attachment_id);
 
+            // Prompt versions of all stages are fixed at start, prompt changes do not affect the running pipeline
+            for prompt_id in ["v-bpa:ImagesToTextPrompt", "v-bpa:DocumentAnalysisPrompt"] {
+                let prompt_version = resolve_prompt_version(module, prompt_id, Some(&mut pipeline));
+                info!("Pipeline [{}]: prompt [{}] version [{}]", pipeline.get_id(), prompt_id, prompt_version);
+            }
+
             // Create recognition requests for each content part
             for (idx, content) in extracted_contents.iter().enumerate() {
                 let request_id = create_processing_request(event_id, module, &mut pipeline, "v-bpa:ImagesToTextPrompt", content.clone())?;
@@ -441,6 +455,8 @@ fn raw_document_extracting_and_structuring_internal(
                 result_doc.set_uri("rdf:type", &target_type);
                 result_doc.set_uri("v-s:attachment", &pipeline.get_first_literal("v-s:attachment").ok_or("fail read attachment")?);
                 result_doc.remove("v-bpa:targetType");
+                let ai_properties = review_workflow::generated_properties(&result_doc);
+                review_workflow::mark_pending_review(&mut result_doc, &ai_properties);
                 if let Err(e) = module.backend.mstorage_api.update_or_err(&module.ticket, event_id, "BPA", IndvOp::Put, &result_doc) {
                     error!("Pipeline [{}]: failed to update result document: {:?}", result_doc_id, e);
                     return Err(format!("Failed to update result document: {:?}", e).into());
@@ -1,7 +1,10 @@
 use crate::ai_client::send_structured_request_to_ai;
-use crate::common::{generate_event_id, ClientType};
+use crate::common::{generate_event_id, load_schema, ClientType};
+use crate::entity_resolution::resolve_entities;
+use crate::output_language::{language_instruction, resolve_output_language};
+use crate::prompt_manager::{get_system_prompt, PromptContext};
+use crate::prompt_version::resolve_prompt_version;
 use crate::queue_processor::BusinessProcessAnalysisModule;
-use crate::response_schema::ResponseSchema;
 use base64::engine::general_purpose::STANDARD;
 use base64::Engine;
 use chrono::Utc;
@@ -24,12 +27,18 @@ fn process_structured_schema_internal(
     prompt_individual: &mut Individual,
     event_id: &str,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    // Parse schema
-    let response_schema = prompt_individual.get_first_literal("v-bpa:responseSchema").ok_or("No response schema found")?;
-    let mut schema = ResponseSchema::from_json(&response_schema)?;
-    let ai_schema = schema.to_ai_schema(module)?;
+    // Prompt text and schema come from the prompt version pinned by the request or from the current version
+    let prompt_version = resolve_prompt_version(module, prompt_individual.get_id(), Some(&mut *request));
 
-    let prompt_text = prompt_individual.get_first_literal("v-bpa:promptText").ok_or("Prompt text not found")?;
+    // Compiled schema is shared by all pages of the document until the prompt version or the ontology changes
+    let language = request.get_first_literal("v-bpa:promptLanguage");
+    let mut schema = load_schema(module, &prompt_version, None, language.as_deref())?;
+    let output_language = resolve_output_language(module, &prompt_version, Some(&mut *request), None);
+    schema.output_language = Some(output_language.clone());
+    let ai_schema = schema.to_ai_schema();
+
+    let context = PromptContext::for_request(module, Some(&mut *request), None, &output_language);
+    let prompt_text = get_system_prompt(module, &prompt_version, &context)?;
 
     // Get file extension and content from either attachment or raw input
     let (extension, extracted_contents) = if let Some(attachment_id) = request.get_first_literal("v-s:attachment") {
@@ -64,9 +73,19 @@ fn process_structured_schema_internal(
     };
 
     // Prepare user content for AI request
-    let user_content = vec![prepare_content_for_ai(&extension, extracted_contents)?];
+    let mut user_content = vec![prepare_content_for_ai(&extension, extracted_contents)?];
+
+    // For edit requests the current state of the individual is sent along with the instruction
+    if let Some(edited_id) = request.get_first_literal("v-bpa:editedIndividual") {
+        let current_state = schema.individual_to_json(&edited_id, module)?;
+        user_content.insert(0, prepare_content_for_ai("txt", format!("Current state:\n{}", serde_json::to_string_pretty(&current_state)?))?);
+    }
 
     let messages = vec![
+        ChatMessage::System {
+            content: ChatMessageContent::Text(language_instruction(&output_language)),
+            name: None,
+        },
         ChatMessage::System {
             content: ChatMessageContent::Text(prompt_text),
             name: None,
@@ -92,7 +111,9 @@ fn process_structured_schema_internal(
 
     // Process AI response
     let response_value = ai_response.to_json_value();
-    let mut parse_result = schema.parse_ai_response(&response_value, module)?;
+    let mut parse_result = schema.parse_response(&response_value)?;
+    let candidates = std::mem::take(&mut parse_result.entity_candidates);
+    resolve_entities(module, &mut parse_result.main_individual, &mut parse_result.related_individuals, candidates)?;
 
     // Create and save result
     let result_id = format!("d:result_{}", uuid::Uuid::new_v4());
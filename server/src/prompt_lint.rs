@@ -0,0 +1,391 @@
+// prompt_lint.rs
+
+use crate::clustering_common::update_individual;
+use crate::common::get_individuals_by_type;
+use crate::ontology_lookup::{OntologyLookup, PropertyDefinition};
+use crate::prompt_manager::{check_template, CONTEXT_VARIABLES};
+use crate::queue_processor::BusinessProcessAnalysisModule;
+use crate::response_schema::{Property, ResponseSchema};
+use crate::schema_compiler::SchemaCompiler;
+use crate::value_types::ValueType;
+use indexmap::IndexMap;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fmt;
+use v_common::onto::datatype::Lang;
+use v_common::onto::individual::Individual;
+use v_common::v_api::api_client::IndvOp;
+
+const STATUS_PASSED: &str = "v-bpa:SchemaLintPassed";
+const STATUS_WARNINGS: &str = "v-bpa:SchemaLintWarnings";
+const STATUS_ERRORS: &str = "v-bpa:SchemaLintErrors";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Схема не компилируется или значения ответа не будут записаны
+    Error,
+    /// Схема работает, но часть описания не действует или ответ AI будет хуже
+    Warning,
+}
+
+/// Замечание к схеме промпта, path - путь к полю схемы (например, sections[].title)
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        if self.path.is_empty() {
+            write!(f, "{}: {}", severity, self.message)
+        } else {
+            write!(f, "{}: {}: {}", severity, self.path, self.message)
+        }
+    }
+}
+
+/// Проверяет схемы всех системных промптов при запуске модуля
+pub fn lint_all_prompts(module: &mut BusinessProcessAnalysisModule) {
+    let prompts = match get_individuals_by_type(module, "v-bpa:SystemPrompt") {
+        Ok(prompts) => prompts,
+        Err(e) => {
+            error!("Failed to load system prompts for schema check: {:?}", e);
+            return;
+        },
+    };
+
+    for mut prompt in prompts {
+        if let Err(e) = lint_and_save(module, &mut prompt, "") {
+            error!("Failed to check schema of prompt {}: {:?}", prompt.get_id(), e);
+        }
+    }
+}
+
+/// Проверяет схему промпта и записывает замечания в промпт, если они изменились с прошлой проверки
+pub fn lint_and_save(module: &mut BusinessProcessAnalysisModule, prompt: &mut Individual, event_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let diagnostics = lint_prompt(module, prompt);
+
+    let errors = diagnostics.iter().filter(|d| d.severity == Severity::Error).count();
+    let status = if errors > 0 {
+        STATUS_ERRORS
+    } else if !diagnostics.is_empty() {
+        STATUS_WARNINGS
+    } else {
+        STATUS_PASSED
+    };
+    info!("Schema of prompt {} checked: {} errors, {} warnings", prompt.get_id(), errors, diagnostics.len() - errors);
+
+    let texts: Vec<String> = diagnostics.iter().map(ToString::to_string).collect();
+    for text in &texts {
+        warn!("Prompt {}: {}", prompt.get_id(), text);
+    }
+
+    if prompt.get_first_literal("v-bpa:schemaLintStatus").as_deref() == Some(status) && prompt.get_literals("v-bpa:schemaDiagnostic").unwrap_or_default() == texts {
+        return Ok(());
+    }
+
+    prompt.set_uri("v-bpa:schemaLintStatus", status);
+    prompt.remove("v-bpa:schemaDiagnostic");
+    for text in &texts {
+        prompt.add_string("v-bpa:schemaDiagnostic", text, Lang::none());
+    }
+    update_individual(module, prompt, IndvOp::Put, event_id)
+}
+
+/// Проверяет v-bpa:responseSchema (или v-bpa:properties) промпта по онтологии и шаблон v-bpa:promptText
+///
+/// Сообщает о неизвестных свойствах, несоответствии типа поля rdfs:range свойства, пустых перечислениях,
+/// совпадающих коротких именах и assign_properties, которые никогда не применяются,
+/// а также об ошибках шаблона, отсутствующих фрагментах и переменных, которые не задает ни один обработчик.
+pub fn lint_prompt(ontology: &mut dyn OntologyLookup, prompt: &mut Individual) -> Vec<Diagnostic> {
+    let mut linter = PromptLinter {
+        ontology,
+        diagnostics: Vec::new(),
+    };
+
+    match prompt.get_first_literal("v-bpa:responseSchema") {
+        Some(json) => {
+            if prompt.is_exists("v-bpa:properties") {
+                linter.warning("", "v-bpa:properties is ignored because v-bpa:responseSchema is set".to_string());
+            }
+            match ResponseSchema::from_json(&json) {
+                Ok(schema) => linter.lint_response_schema(&schema),
+                Err(e) => linter.error("", format!("Invalid response schema: {}", e)),
+            }
+        },
+        None => linter.lint_property_list(&prompt.get_literals("v-bpa:properties").unwrap_or_default()),
+    }
+
+    if let Some(text) = prompt.get_first_literal("v-bpa:promptText") {
+        linter.lint_template(&text);
+    }
+
+    linter.diagnostics
+}
+
+/// Поля, значения которых записываются в один индивид
+#[derive(Default)]
+struct FieldScope {
+    names: HashMap<String, String>,
+    properties: HashMap<String, String>,
+}
+
+struct PromptLinter<'a> {
+    ontology: &'a mut dyn OntologyLookup,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'a> PromptLinter<'a> {
+    fn lint_template(&mut self, text: &str) {
+        match check_template(text, &mut *self.ontology) {
+            Ok(variables) => {
+                for variable in variables.iter().filter(|variable| !CONTEXT_VARIABLES.contains(&variable.as_str())) {
+                    self.warning("v-bpa:promptText", format!("Variable {} is not set by any handler", variable));
+                }
+            },
+            Err(e) => self.error("v-bpa:promptText", format!("Invalid prompt template: {}", e)),
+        }
+    }
+
+    fn lint_property_list(&mut self, properties: &[String]) {
+        let mut names: HashMap<String, String> = HashMap::new();
+
+        for property in properties {
+            let short_name = property.rsplit(':').next().unwrap_or(property);
+            if let Some(other) = names.insert(short_name.to_string(), property.clone()) {
+                self.error(short_name, format!("Properties {} and {} have the same short name, only the last one is sent to AI", other, property));
+            }
+
+            let definition = match self.load_property(property) {
+                Some(definition) => definition,
+                None => {
+                    self.error(short_name, format!("Property {} not found in ontology", property));
+                    continue;
+                },
+            };
+            if let Some(range) = definition.range.as_deref().filter(|range| !definition.is_class && ValueType::from_range(range).is_none()) {
+                self.check_enum(short_name, range);
+            }
+        }
+    }
+
+    fn lint_response_schema(&mut self, schema: &ResponseSchema) {
+        if let Some(assign_properties) = &schema.assign_properties {
+            self.check_assign_properties("", &json!(assign_properties));
+        }
+        self.lint_object("", &schema.properties, &mut FieldScope::default());
+    }
+
+    fn lint_object(&mut self, path: &str, properties: &IndexMap<String, Property>, scope: &mut FieldScope) {
+        for (name, prop) in properties {
+            self.lint_field(path, name, prop, scope);
+        }
+    }
+
+    fn lint_field(&mut self, path: &str, name: &str, prop: &Property, scope: &mut FieldScope) {
+        let field_path = if path.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", path, name)
+        };
+
+        // Поля вложенного объекта без свойства связи записываются в тот же индивид, что и поля родителя
+        if let Some(other) = scope.names.insert(name.to_string(), field_path.clone()) {
+            self.warning(&field_path, format!("Short name {} is also used by {} in the same individual", name, other));
+        }
+        if let Some(mapping) = &prop.mapping {
+            if let Some(other) = scope.properties.insert(mapping.clone(), field_path.clone()) {
+                self.warning(&field_path, format!("Property {} is also mapped by {}, one value replaces the other", mapping, other));
+            }
+        }
+
+        let definition = match &prop.mapping {
+            Some(mapping) => {
+                let definition = self.load_property(mapping);
+                if definition.is_none() {
+                    self.error(&field_path, format!("Property {} not found in ontology", mapping));
+                }
+                definition
+            },
+            None => None,
+        };
+
+        let (value, is_array) = match &prop.items {
+            Some(items) => (items.as_ref(), true),
+            None => (prop, false),
+        };
+        match (prop.type_name.as_deref(), is_array) {
+            (Some("array"), false) => self.error(&field_path, "Array has no items".to_string()),
+            (Some(type_name), true) if type_name != "array" => self.error(&field_path, format!("Items are declared for type {}", type_name)),
+            _ => {},
+        }
+        if is_array && prop.additional.contains_key("assign_properties") {
+            self.warning(&field_path, "assign_properties of an array are never applied, declare them in items".to_string());
+        }
+
+        let mut settings = prop.additional.clone();
+        if is_array {
+            settings.extend(value.additional.clone());
+        }
+        let create_new = settings.get("create_new_individuals").and_then(Value::as_bool).unwrap_or(false);
+
+        if let (Some(definition), Some(mapping)) = (&definition, &prop.mapping) {
+            if is_array && definition.is_functional && prop.is_multiple != Some(true) {
+                self.warning(&field_path, format!("Array is mapped to functional property {}, only the last value is kept", mapping));
+            }
+            self.check_value_type(&field_path, mapping, definition, value, create_new);
+        }
+
+        let value_path = if is_array {
+            format!("{}[]", field_path)
+        } else {
+            field_path.clone()
+        };
+        match &value.properties {
+            Some(properties) => {
+                // Объект для свойства с типом owl:Class записывается JSON-строкой, индивид для него не создается
+                let as_json = definition.as_ref().is_some_and(|d| d.is_class);
+                if let Some(assign_properties) = value.additional.get("assign_properties") {
+                    if as_json {
+                        self.warning(&value_path, "assign_properties of an object stored as JSON are never applied".to_string());
+                    } else {
+                        self.check_assign_properties(&value_path, assign_properties);
+                    }
+                }
+
+                if prop.mapping.is_some() {
+                    self.lint_object(&value_path, properties, &mut FieldScope::default());
+                } else {
+                    self.lint_object(&value_path, properties, scope);
+                }
+            },
+            None => {
+                if value.additional.contains_key("assign_properties") {
+                    self.warning(&value_path, "assign_properties of a field without nested properties are never applied".to_string());
+                }
+            },
+        }
+    }
+
+    /// Сверяет тип значения поля с rdfs:range свойства
+    fn check_value_type(&mut self, path: &str, mapping: &str, definition: &PropertyDefinition, value: &Property, create_new: bool) {
+        if definition.is_class {
+            return;
+        }
+        let range = match &definition.range {
+            Some(range) => range,
+            None => {
+                self.warning(path, format!("Property {} has no rdfs:range, the value is written as xsd:string", mapping));
+                return;
+            },
+        };
+        let declared = value.type_name.as_deref();
+
+        match ValueType::from_range(range) {
+            Some(value_type) => {
+                if value.properties.is_some() {
+                    self.error(path, format!("Object is mapped to property {} with literal range {}", mapping, range));
+                } else if let Some(declared) = declared.filter(|declared| !is_compatible(value_type, declared)) {
+                    self.error(path, format!("Type {} does not match range {} of {}", declared, range, mapping));
+                }
+            },
+            None => {
+                if value.properties.is_some() {
+                    return;
+                }
+                if let Some(declared) = declared.filter(|declared| *declared != "string") {
+                    self.error(path, format!("Type {} does not match range {} of {}, references are passed to AI as string labels", declared, range, mapping));
+                }
+                if !create_new {
+                    self.check_enum(path, range);
+                }
+            },
+        }
+    }
+
+    /// Проверяет, что у класса из rdfs:range есть экземпляры для перечисления
+    fn check_enum(&mut self, path: &str, range: &str) {
+        if SchemaCompiler::new(&mut *self.ontology).list_instances(range).is_empty() {
+            self.warning(path, format!("Class {} has no labelled instances, the enum sent to AI is empty", range));
+        }
+    }
+
+    /// Проверяет assign_properties уровня схемы: значения записываются ссылками в индивид этого уровня
+    fn check_assign_properties(&mut self, path: &str, assign_properties: &Value) {
+        let assign_properties = match assign_properties.as_object() {
+            Some(assign_properties) => assign_properties,
+            None => {
+                self.error(path, "assign_properties must be an object".to_string());
+                return;
+            },
+        };
+
+        for (predicate, value) in assign_properties {
+            let value = match value.as_str() {
+                Some(value) => value,
+                None => {
+                    self.warning(path, format!("Value of {} in assign_properties is not a string and is never applied", predicate));
+                    continue;
+                },
+            };
+
+            let definition = match self.load_property(predicate) {
+                Some(definition) => definition,
+                None => {
+                    self.error(path, format!("Property {} in assign_properties not found in ontology", predicate));
+                    continue;
+                },
+            };
+            match definition.range.as_deref() {
+                Some(range) if ValueType::from_range(range).is_some() => {
+                    self.warning(path, format!("assign_properties writes {} as a link, but its range is {}", predicate, range));
+                },
+                _ => {
+                    if !self.individual_exists(value) {
+                        self.error(path, format!("Individual {} assigned to {} not found", value, predicate));
+                    }
+                },
+            }
+        }
+    }
+
+    fn load_property(&mut self, property: &str) -> Option<PropertyDefinition> {
+        self.ontology.get_property(property)
+    }
+
+    fn individual_exists(&mut self, id: &str) -> bool {
+        self.ontology.get_individual(id).is_some()
+    }
+
+    fn error(&mut self, path: &str, message: String) {
+        self.push(Severity::Error, path, message);
+    }
+
+    fn warning(&mut self, path: &str, message: String) {
+        self.push(Severity::Warning, path, message);
+    }
+
+    fn push(&mut self, severity: Severity, path: &str, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            path: path.to_string(),
+            message,
+        });
+    }
+}
+
+/// Совместим ли тип JSON-схемы с типом значения из rdfs:range
+fn is_compatible(value_type: ValueType, json_type: &str) -> bool {
+    match value_type {
+        ValueType::String | ValueType::LangString | ValueType::DateTime | ValueType::Date => json_type == "string",
+        ValueType::Integer => json_type == "integer",
+        ValueType::Decimal => json_type == "number" || json_type == "integer",
+        ValueType::Boolean => json_type == "boolean",
+    }
+}
@@ -1,10 +1,90 @@
 // prompt_manager.rs
 
+use crate::ontology_lookup::OntologyLookup;
+use crate::output_language::{get_department_names, get_departments};
 use crate::queue_processor::BusinessProcessAnalysisModule;
+use chrono::Utc;
+use std::collections::HashMap;
+use v_common::onto::datatype::Lang;
 use v_common::onto::individual::Individual;
 use v_common::v_api::obj::ResultCode;
 
-pub fn get_system_prompt(module: &mut BusinessProcessAnalysisModule, prompt_id: &str) -> Result<String, Box<dyn std::error::Error>> {
+/// Переменные, которые обработчики задают в контексте промпта
+pub const CONTEXT_VARIABLES: &[&str] = &["date", "language", "department", "target_type"];
+
+/// Значения переменных шаблона промпта
+///
+/// Шаблон v-bpa:promptText поддерживает:
+/// - `{{имя}}` - значение переменной, отсутствие переменной - ошибка
+/// - `{{> v-bpa:Fragment}}` - текст фрагмента промпта (v-bpa:PromptFragment), фрагмент тоже является шаблоном
+/// - `{{#if имя}}...{{else}}...{{/if}}` - раздел, выводимый, если переменная задана и не пуста
+#[derive(Debug, Clone, Default)]
+pub struct PromptContext {
+    variables: HashMap<String, String>,
+}
+
+impl PromptContext {
+    /// Контекст с текущей датой (date)
+    pub fn new() -> Self {
+        let mut context = PromptContext::default();
+        context.set("date", &Utc::now().format("%Y-%m-%d").to_string());
+        context
+    }
+
+    /// Контекст запроса: дата, язык ответа (language), подразделение (department) и тип результата (target_type)
+    /// запроса или целевого индивида
+    ///
+    /// Тип результата - v-bpa:targetType запроса, иначе rdf:type целевого индивида.
+    pub fn for_request(ontology: &mut dyn OntologyLookup, request: Option<&mut Individual>, target: Option<&mut Individual>, language: &str) -> Self {
+        let mut context = PromptContext::new();
+        context.set("language", language);
+
+        let mut departments = Vec::new();
+        let mut department_names = Vec::new();
+        let mut target_types = Vec::new();
+        if let Some(request) = request {
+            departments.extend(get_departments(request));
+            department_names.extend(get_department_names(request));
+            target_types.extend(request.get_literals("v-bpa:targetType").unwrap_or_default());
+        }
+        if let Some(target) = target {
+            departments.extend(get_departments(target));
+            department_names.extend(get_department_names(target));
+            target_types.extend(target.get_literals("rdf:type").unwrap_or_default());
+        }
+
+        let department = departments.iter().find_map(|id| get_label(ontology, id, language)).or_else(|| department_names.into_iter().next());
+        if let Some(department) = department {
+            context.set("department", &department);
+        }
+        if let Some(target_type) = target_types.first() {
+            context.set_target_type(ontology, target_type, language);
+        }
+        context
+    }
+
+    /// Задает тип результата (target_type) меткой класса на языке ответа
+    pub fn set_target_type(&mut self, ontology: &mut dyn OntologyLookup, target_type: &str, language: &str) -> &mut Self {
+        let label = get_label(ontology, target_type, language).unwrap_or_else(|| target_type.to_string());
+        self.set("target_type", &label)
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> &mut Self {
+        self.variables.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.variables.get(name).map(String::as_str)
+    }
+
+    fn is_set(&self, name: &str) -> bool {
+        self.get(name).is_some_and(|value| !value.trim().is_empty())
+    }
+}
+
+/// Загружает текст промпта и подставляет в него переменные контекста и фрагменты
+pub fn get_system_prompt(module: &mut BusinessProcessAnalysisModule, prompt_id: &str, context: &PromptContext) -> Result<String, Box<dyn std::error::Error>> {
     // Получаем индивидуал промпта из хранилища
     let mut prompt_individual = Individual::default();
     if module.backend.storage.get_individual(prompt_id, &mut prompt_individual) != ResultCode::Ok {
@@ -16,5 +96,402 @@ pub fn get_system_prompt(module: &mut BusinessProcessAnalysisModule, prompt_id:
     // Получаем текст промпта
     let prompt_text = prompt_individual.get_first_literal("v-bpa:promptText").ok_or("Prompt text not found")?;
 
-    Ok(prompt_text)
+    render_template(&prompt_text, context, module).map_err(|e| format!("Failed to render prompt {}: {}", prompt_id, e).into())
+}
+
+/// Подставляет в шаблон промпта переменные контекста и тексты фрагментов
+pub fn render_template(template: &str, context: &PromptContext, ontology: &mut dyn OntologyLookup) -> Result<String, Box<dyn std::error::Error>> {
+    let mut output = String::new();
+    render_nodes(&parse_template(template)?, context, ontology, &mut Vec::new(), &mut output)?;
+    Ok(output)
+}
+
+/// Проверяет синтаксис шаблона и наличие включаемых фрагментов, возвращает имена используемых переменных
+pub fn check_template(template: &str, ontology: &mut dyn OntologyLookup) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut variables = Vec::new();
+    check_nodes(&parse_template(template)?, ontology, &mut Vec::new(), &mut variables)?;
+    Ok(variables)
+}
+
+/// Подставляет в шаблон тексты фрагментов, переменные и условные разделы остаются в шаблоне
+pub fn inline_fragments(template: &str, ontology: &mut dyn OntologyLookup) -> Result<String, Box<dyn std::error::Error>> {
+    let mut output = String::new();
+    inline_nodes(&parse_template(template)?, ontology, &mut Vec::new(), &mut output)?;
+    Ok(output)
+}
+
+enum Token<'t> {
+    Text(&'t str),
+    Tag(&'t str),
+}
+
+enum Node {
+    Text(String),
+    Variable(String),
+    Include(String),
+    If { name: String, then: Vec<Node>, otherwise: Vec<Node> },
+}
+
+/// Тег, на котором закончился разбор блока
+#[derive(PartialEq)]
+enum BlockEnd {
+    Else,
+    EndIf,
+    Eof,
+}
+
+fn parse_template(template: &str) -> Result<Vec<Node>, Box<dyn std::error::Error>> {
+    let tokens = tokenize(template)?;
+    let mut pos = 0;
+    match parse_block(&tokens, &mut pos)? {
+        (nodes, BlockEnd::Eof) => Ok(nodes),
+        (_, BlockEnd::Else) => Err("Tag {{else}} outside of {{#if}} section".into()),
+        (_, BlockEnd::EndIf) => Err("Tag {{/if}} without {{#if}}".into()),
+    }
+}
+
+fn tokenize(template: &str) -> Result<Vec<Token<'_>>, Box<dyn std::error::Error>> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            tokens.push(Token::Text(&rest[..start]));
+        }
+        let tag = &rest[start + 2..];
+        let end = tag.find("}}").ok_or_else(|| format!("Tag {{{{{}... is not closed", tag.chars().take(20).collect::<String>()))?;
+        tokens.push(Token::Tag(tag[..end].trim()));
+        rest = &tag[end + 2..];
+    }
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest));
+    }
+    Ok(tokens)
+}
+
+fn parse_block(tokens: &[Token], pos: &mut usize) -> Result<(Vec<Node>, BlockEnd), Box<dyn std::error::Error>> {
+    let mut nodes = Vec::new();
+
+    while let Some(token) = tokens.get(*pos) {
+        *pos += 1;
+        let tag = match token {
+            Token::Text(text) => {
+                nodes.push(Node::Text(text.to_string()));
+                continue;
+            },
+            Token::Tag(tag) => *tag,
+        };
+
+        if tag == "else" {
+            return Ok((nodes, BlockEnd::Else));
+        }
+        if tag == "/if" {
+            return Ok((nodes, BlockEnd::EndIf));
+        }
+
+        if let Some(id) = tag.strip_prefix('>') {
+            nodes.push(Node::Include(parse_name(id)?));
+        } else if let Some(name) = tag.strip_prefix("#if ") {
+            let name = parse_name(name)?;
+            let (then, end) = parse_block(tokens, pos)?;
+            let otherwise = match end {
+                BlockEnd::Else => match parse_block(tokens, pos)? {
+                    (otherwise, BlockEnd::EndIf) => otherwise,
+                    _ => return Err(format!("Section {{{{#if {}}}}} is not closed", name).into()),
+                },
+                BlockEnd::EndIf => Vec::new(),
+                BlockEnd::Eof => return Err(format!("Section {{{{#if {}}}}} is not closed", name).into()),
+            };
+            nodes.push(Node::If {
+                name,
+                then,
+                otherwise,
+            });
+        } else {
+            nodes.push(Node::Variable(parse_name(tag)?));
+        }
+    }
+
+    Ok((nodes, BlockEnd::Eof))
+}
+
+fn parse_name(name: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(format!("Invalid name in tag {{{{{}}}}}", name).into());
+    }
+    Ok(name.to_string())
+}
+
+fn render_nodes(
+    nodes: &[Node],
+    context: &PromptContext,
+    ontology: &mut dyn OntologyLookup,
+    includes: &mut Vec<String>,
+    output: &mut String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Variable(name) => output.push_str(context.get(name).ok_or_else(|| format!("Prompt variable {} is not set", name))?),
+            Node::Include(id) => {
+                let fragment = load_fragment(ontology, id, includes)?;
+                includes.push(id.clone());
+                render_nodes(&fragment, context, ontology, includes, output)?;
+                includes.pop();
+            },
+            Node::If {
+                name,
+                then,
+                otherwise,
+            } => {
+                let branch = if context.is_set(name) {
+                    then
+                } else {
+                    otherwise
+                };
+                render_nodes(branch, context, ontology, includes, output)?;
+            },
+        }
+    }
+    Ok(())
+}
+
+fn check_nodes(nodes: &[Node], ontology: &mut dyn OntologyLookup, includes: &mut Vec<String>, variables: &mut Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    for node in nodes {
+        match node {
+            Node::Text(_) => {},
+            Node::Variable(name) => {
+                if !variables.contains(name) {
+                    variables.push(name.clone());
+                }
+            },
+            Node::Include(id) => {
+                let fragment = load_fragment(ontology, id, includes)?;
+                includes.push(id.clone());
+                check_nodes(&fragment, ontology, includes, variables)?;
+                includes.pop();
+            },
+            Node::If {
+                name,
+                then,
+                otherwise,
+            } => {
+                if !variables.contains(name) {
+                    variables.push(name.clone());
+                }
+                check_nodes(then, ontology, includes, variables)?;
+                check_nodes(otherwise, ontology, includes, variables)?;
+            },
+        }
+    }
+    Ok(())
+}
+
+fn inline_nodes(nodes: &[Node], ontology: &mut dyn OntologyLookup, includes: &mut Vec<String>, output: &mut String) -> Result<(), Box<dyn std::error::Error>> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Variable(name) => output.push_str(&format!("{{{{{}}}}}", name)),
+            Node::Include(id) => {
+                let fragment = load_fragment(ontology, id, includes)?;
+                includes.push(id.clone());
+                inline_nodes(&fragment, ontology, includes, output)?;
+                includes.pop();
+            },
+            Node::If {
+                name,
+                then,
+                otherwise,
+            } => {
+                output.push_str(&format!("{{{{#if {}}}}}", name));
+                inline_nodes(then, ontology, includes, output)?;
+                if !otherwise.is_empty() {
+                    output.push_str("{{else}}");
+                    inline_nodes(otherwise, ontology, includes, output)?;
+                }
+                output.push_str("{{/if}}");
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Загружает и разбирает текст фрагмента, includes - цепочка фрагментов, в которую он включается
+fn load_fragment(ontology: &mut dyn OntologyLookup, id: &str, includes: &[String]) -> Result<Vec<Node>, Box<dyn std::error::Error>> {
+    if includes.iter().any(|included| included == id) {
+        return Err(format!("Prompt fragment {} includes itself", id).into());
+    }
+    let text =
+        ontology.get_individual(id).and_then(|mut fragment| fragment.get_first_literal("v-bpa:promptText")).ok_or_else(|| format!("Prompt fragment {} not found", id))?;
+    parse_template(strip_line_breaks(&text)).map_err(|e| format!("Prompt fragment {}: {}", id, e).into())
+}
+
+/// Убирает переносы строк в начале и в конце текста фрагмента, отступы строк сохраняются
+fn strip_line_breaks(text: &str) -> &str {
+    text.trim_start_matches(['\r', '\n']).trim_end_matches(['\r', '\n'])
+}
+
+fn get_label(ontology: &mut dyn OntologyLookup, id: &str, language: &str) -> Option<String> {
+    let mut individual = match ontology.get_individual(id) {
+        Some(individual) => individual,
+        None => {
+            warn!("Failed to load {} for prompt context", id);
+            return None;
+        },
+    };
+    individual.get_first_literal_with_lang("rdfs:label", &[Lang::new_from_str(language)]).or_else(|| individual.get_first_literal("rdfs:label"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ontology_lookup::TurtleOntology;
+    use crate::tests::response_schema::load_module_ontology;
+
+    fn add_fragment(ontology: &mut TurtleOntology, id: &str, text: &str) {
+        let mut fragment = Individual::default();
+        fragment.set_id(id);
+        fragment.set_uri("rdf:type", "v-bpa:PromptFragment");
+        fragment.set_string("v-bpa:promptText", text, Lang::none());
+        ontology.insert(&fragment);
+    }
+
+    fn render(template: &str, context: &PromptContext, ontology: &mut TurtleOntology) -> String {
+        render_template(template, context, ontology).unwrap_or_else(|e| panic!("Failed to render template: {}", e))
+    }
+
+    #[test]
+    fn test_variables_substituted() {
+        let mut ontology = TurtleOntology::default();
+        let mut context = PromptContext::default();
+        context.set("language", "en").set("department", "Бухгалтерия");
+
+        assert_eq!(render("Язык: {{language}}, отдел: {{ department }}.", &context, &mut ontology), "Язык: en, отдел: Бухгалтерия.");
+        // Текст без тегов возвращается без изменений
+        assert_eq!(render("Ответь кратко.", &context, &mut ontology), "Ответь кратко.");
+    }
+
+    #[test]
+    fn test_missing_variable_is_error() {
+        let mut ontology = TurtleOntology::default();
+        let error = render_template("Отдел: {{department}}", &PromptContext::default(), &mut ontology).expect_err("Missing variable must fail");
+        assert!(error.to_string().contains("department"));
+    }
+
+    #[test]
+    fn test_fragments_included() {
+        let mut ontology = TurtleOntology::default();
+        add_fragment(&mut ontology, "v-bpa:FieldsFragment", "\n- 'label'\n{{> v-bpa:NestedFragment}}\n");
+        add_fragment(&mut ontology, "v-bpa:NestedFragment", "- 'date': {{date}}");
+
+        let mut context = PromptContext::default();
+        context.set("date", "2026-10-19");
+        assert_eq!(render("Поля:\n{{> v-bpa:FieldsFragment}}\nКонец", &context, &mut ontology), "Поля:\n- 'label'\n- 'date': 2026-10-19\nКонец");
+
+        assert_eq!(check_template("{{> v-bpa:FieldsFragment}} {{language}}", &mut ontology).expect("Template must be valid"), vec!["date", "language"]);
+    }
+
+    #[test]
+    fn test_fragment_indentation_kept() {
+        let mut ontology = TurtleOntology::default();
+        add_fragment(&mut ontology, "v-bpa:ListFragment", "\n  - 'date'\n    - формат: YYYY-MM-DD\n");
+
+        // Переносы строк вокруг текста фрагмента убираются, отступы вложенных строк остаются
+        assert_eq!(render("Поля:\n{{> v-bpa:ListFragment}}\nКонец", &PromptContext::default(), &mut ontology), "Поля:\n  - 'date'\n    - формат: YYYY-MM-DD\nКонец");
+    }
+
+    #[test]
+    fn test_conditional_sections() {
+        let mut ontology = TurtleOntology::default();
+        let template = "{{#if department}}Отдел: {{department}}.{{else}}Отдел не указан.{{/if}}";
+
+        let mut context = PromptContext::default();
+        assert_eq!(render(template, &context, &mut ontology), "Отдел не указан.");
+
+        // Пустое значение считается незаданным
+        context.set("department", " ");
+        assert_eq!(render(template, &context, &mut ontology), "Отдел не указан.");
+
+        context.set("department", "Склад");
+        assert_eq!(render(template, &context, &mut ontology), "Отдел: Склад.");
+        assert_eq!(render("А{{#if department}}Б{{#if language}}В{{/if}}{{/if}}", &context, &mut ontology), "АБ");
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        let mut ontology = TurtleOntology::default();
+        add_fragment(&mut ontology, "v-bpa:LoopFragment", "{{> v-bpa:LoopFragment}}");
+
+        for template in [
+            "{{#if department}}Отдел",
+            "{{#if department}}А{{else}}Б",
+            "Отдел{{/if}}",
+            "{{else}}",
+            "{{department",
+            "{{}}",
+            "{{> v-bpa:NoSuchFragment}}",
+            "{{> v-bpa:LoopFragment}}",
+        ] {
+            assert!(check_template(template, &mut ontology).is_err(), "Template '{}' must be rejected", template);
+        }
+    }
+
+    #[test]
+    fn test_module_prompt_renders_fragments() {
+        let mut ontology = load_module_ontology();
+        let mut context = PromptContext::new();
+        context.set("department", "Отдел кадров");
+
+        let mut prompt = ontology.get_individual("v-bpa:ProcessExtractionPrompt").expect("Prompt not found");
+        let text = render(&prompt.get_first_literal("v-bpa:promptText").unwrap_or_default(), &context, &mut ontology);
+        assert!(text.contains("Документы относятся к подразделению: Отдел кадров."));
+        assert!(text.contains("- 'reason' - Объясни мотивацию"));
+        assert!(text.contains("- 'frequency' — Повторений данного бизнес-процесса в год"));
+        assert!(!text.contains("{{"));
+    }
+
+    #[test]
+    fn test_request_context_sets_target_type() {
+        let mut ontology = load_module_ontology();
+
+        // Тип результата запроса передается меткой класса на языке ответа
+        let mut request = Individual::default();
+        request.set_string("v-bpa:targetType", "v-bpa:BusinessProcess", Lang::none());
+        let context = PromptContext::for_request(&mut ontology, Some(&mut request), None, "en");
+        assert_eq!(context.get("target_type"), Some("Business Process"));
+
+        // Без типа в запросе используется тип целевого индивида
+        let mut target = Individual::default();
+        target.set_uri("rdf:type", "v-bpa:ProcessDocument");
+        let context = PromptContext::for_request(&mut ontology, None, Some(&mut target), "ru");
+        assert_eq!(context.get("target_type"), Some("Документ процесса"));
+        assert_eq!(render("Извлеки {{target_type}}", &context, &mut ontology), "Извлеки Документ процесса");
+
+        assert!(PromptContext::for_request(&mut ontology, None, None, "ru").get("target_type").is_none());
+    }
+
+    #[test]
+    fn test_tokenize_and_parse_names() {
+        let tokens = tokenize("Отдел: {{ department }}{{#if language}}!{{/if}}").expect("Failed to tokenize");
+        let tags = tokens
+            .iter()
+            .map(|token| match token {
+                Token::Text(text) => format!("text:{}", text),
+                Token::Tag(tag) => format!("tag:{}", tag),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(tags, vec!["text:Отдел: ", "tag:department", "tag:#if language", "text:!", "tag:/if"]);
+        assert!(tokenize("{{department").is_err());
+
+        assert_eq!(parse_name(" v-bpa:Fragment ").expect("Name must be valid"), "v-bpa:Fragment");
+        assert!(parse_name("").is_err());
+        assert!(parse_name("two words").is_err());
+    }
+
+    #[test]
+    fn test_strip_line_breaks() {
+        assert_eq!(strip_line_breaks("\r\n  - 'date'\n\n"), "  - 'date'");
+        assert_eq!(strip_line_breaks("текст"), "текст");
+    }
 }
@@ -0,0 +1,239 @@
+// prompt_version.rs
+
+use crate::clustering_common::update_individual;
+use crate::common::get_individuals_by_type;
+use crate::prompt_manager::inline_fragments;
+use crate::queue_processor::BusinessProcessAnalysisModule;
+use crate::value_types::{get_typed_values, set_typed_value, ValueType};
+use chrono::Utc;
+use serde_json::Value;
+use v_common::onto::datatype::Lang;
+use v_common::onto::individual::Individual;
+use v_common::v_api::api_client::IndvOp;
+use v_common::v_api::obj::ResultCode;
+
+pub const PROMPT_VERSION_TYPE: &str = "v-bpa:PromptVersion";
+const CURRENT_VERSION: &str = "v-bpa:currentPromptVersion";
+const PINNED_VERSION: &str = "v-bpa:pinnedPromptVersion";
+const USED_VERSION: &str = "v-bpa:usedPromptVersion";
+
+/// Свойства промпта, определяющие ответ AI, и типы их значений (None - ссылка): копируются в версию вместе с текстом
+const VERSIONED_PROPERTIES: &[(&str, Option<ValueType>)] = &[
+    ("v-bpa:responseSchema", Some(ValueType::String)),
+    ("v-bpa:properties", None),
+    ("v-bpa:targetType", None),
+    ("v-bpa:promptLanguage", Some(ValueType::String)),
+    ("v-bpa:outputLanguage", Some(ValueType::String)),
+    ("v-bpa:consensusSamples", Some(ValueType::Integer)),
+    ("v-bpa:consensusModel", Some(ValueType::String)),
+    ("v-bpa:consensusVote", None),
+    ("v-bpa:confidenceThreshold", Some(ValueType::Decimal)),
+];
+
+/// Сохраняет новую версию промпта, если его текст или настройки отличаются от текущей версии
+///
+/// Версия v-bpa:PromptVersion - неизменяемый снимок промпта с подставленными в текст фрагментами,
+/// поэтому последующие правки промпта и фрагментов не меняют ответы, полученные с этой версией.
+/// Версии нумеруются по порядку, текущая версия записывается в v-bpa:currentPromptVersion промпта.
+///
+/// # Returns
+/// * Идентификатор созданной версии или None, если промпт не изменился
+pub fn snapshot_prompt(module: &mut BusinessProcessAnalysisModule, prompt: &mut Individual, event_id: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let text = prompt.get_first_literal("v-bpa:promptText").ok_or("Prompt text not found")?;
+
+    let mut version = Individual::default();
+    version.set_string("v-bpa:promptText", &inline_fragments(&text, module)?, Lang::none());
+    for (property, value_type) in VERSIONED_PROPERTIES {
+        match value_type {
+            Some(value_type) => {
+                for value in get_typed_values(prompt, property, *value_type) {
+                    set_typed_value(&mut version, property, &value, *value_type, None, true);
+                }
+            },
+            None => {
+                for id in prompt.get_literals(property).unwrap_or_default() {
+                    version.add_uri(property, &id);
+                }
+            },
+        }
+    }
+
+    let current_id = prompt.get_first_literal(CURRENT_VERSION);
+    let mut number = 1;
+    if let Some(mut current) = current_id.as_deref().and_then(|id| load_individual(module, id)) {
+        if versioned_content(&mut current) == versioned_content(&mut version) {
+            return Ok(None);
+        }
+        number = current.get_first_integer("v-bpa:versionNumber").unwrap_or_default() + 1;
+    }
+
+    // Промпт, загруженный заново из онтологии, теряет ссылку на текущую версию, существующие версии не перезаписываются
+    let prompt_name = prompt.get_id().rsplit(':').next().unwrap_or_default().to_string();
+    let mut version_id = format!("d:bpa_prompt_version_{}_{}", prompt_name, number);
+    while load_individual(module, &version_id).is_some() {
+        number += 1;
+        version_id = format!("d:bpa_prompt_version_{}_{}", prompt_name, number);
+    }
+
+    version.set_id(&version_id);
+    version.set_uri("rdf:type", PROMPT_VERSION_TYPE);
+    version.set_uri("v-bpa:versionOf", prompt.get_id());
+    version.set_integer("v-bpa:versionNumber", number);
+    version.set_string("rdfs:label", &format!("{} v{}", prompt_name, number), Lang::none());
+    version.set_datetime("v-s:created", Utc::now().timestamp());
+    if let Some(current_id) = &current_id {
+        version.set_uri("v-bpa:previousVersion", current_id);
+    }
+    update_individual(module, &mut version, IndvOp::Put, event_id)?;
+
+    let mut update = Individual::default();
+    update.set_id(prompt.get_id());
+    update.set_uri(CURRENT_VERSION, &version_id);
+    update_individual(module, &mut update, IndvOp::SetIn, event_id)?;
+    prompt.set_uri(CURRENT_VERSION, &version_id);
+
+    info!("Created version {} of prompt {}", version_id, prompt.get_id());
+    Ok(Some(version_id))
+}
+
+/// Создает версии всех системных промптов, изменившихся с последней версии
+///
+/// Вызывается при запуске модуля и при изменении фрагмента промпта, который может входить в любой промпт.
+pub fn snapshot_all_prompts(module: &mut BusinessProcessAnalysisModule, event_id: &str) {
+    let prompts = match get_individuals_by_type(module, "v-bpa:SystemPrompt") {
+        Ok(prompts) => prompts,
+        Err(e) => {
+            error!("Failed to load system prompts for versioning: {:?}", e);
+            return;
+        },
+    };
+
+    for mut prompt in prompts {
+        if let Err(e) = snapshot_prompt(module, &mut prompt, event_id) {
+            error!("Failed to create version of prompt {}: {:?}", prompt.get_id(), e);
+        }
+    }
+}
+
+/// Определяет версию промпта для запроса и добавляет ее в v-bpa:usedPromptVersion запроса
+///
+/// Версия берется из v-bpa:pinnedPromptVersion запроса, затем из уже использованных запросом версий этого промпта,
+/// чтобы многошаговая обработка (пайплайн, попытка кластеризации) не меняла версию на ходу, затем текущая версия промпта.
+/// Если у промпта еще нет версий, возвращается сам промпт. Запрос сохраняет вызывающая сторона.
+///
+/// # Returns
+/// * Идентификатор версии, из которой загружаются текст и схема промпта
+pub fn resolve_prompt_version(module: &mut BusinessProcessAnalysisModule, prompt_id: &str, request: Option<&mut Individual>) -> String {
+    let request = match request {
+        Some(request) => request,
+        None => return get_current_version(module, prompt_id),
+    };
+
+    let requested = [PINNED_VERSION, USED_VERSION].iter().flat_map(|property| request.get_literals(property).unwrap_or_default()).collect::<Vec<_>>();
+    let version_id = match requested.into_iter().find(|id| is_version_of(module, id, prompt_id)) {
+        Some(version_id) => version_id,
+        None => get_current_version(module, prompt_id),
+    };
+
+    if version_id != prompt_id && !request.any_exists(USED_VERSION, &[&version_id]) {
+        request.add_uri(USED_VERSION, &version_id);
+    }
+    version_id
+}
+
+/// Номер версии промпта, None для промпта без версий
+pub fn get_version_number(module: &mut BusinessProcessAnalysisModule, version_id: &str) -> Option<i64> {
+    load_individual(module, version_id)?.get_first_integer("v-bpa:versionNumber")
+}
+
+fn get_current_version(module: &mut BusinessProcessAnalysisModule, prompt_id: &str) -> String {
+    match load_individual(module, prompt_id).and_then(|mut prompt| prompt.get_first_literal(CURRENT_VERSION)) {
+        Some(version_id) => version_id,
+        None => {
+            warn!("Prompt {} has no versions, using the prompt itself", prompt_id);
+            prompt_id.to_string()
+        },
+    }
+}
+
+fn is_version_of(module: &mut BusinessProcessAnalysisModule, version_id: &str, prompt_id: &str) -> bool {
+    load_individual(module, version_id).is_some_and(|mut version| version.any_exists("v-bpa:versionOf", &[prompt_id]))
+}
+
+/// Текст и значения версионируемых свойств для сравнения версий
+fn versioned_content(individual: &mut Individual) -> Vec<Value> {
+    let mut content = get_typed_values(individual, "v-bpa:promptText", ValueType::String);
+    for (property, value_type) in VERSIONED_PROPERTIES {
+        match value_type {
+            Some(value_type) => content.push(Value::Array(get_typed_values(individual, property, *value_type))),
+            None => content.push(Value::from(individual.get_literals(property).unwrap_or_default())),
+        }
+    }
+    content
+}
+
+fn load_individual(module: &mut BusinessProcessAnalysisModule, id: &str) -> Option<Individual> {
+    let mut individual = Individual::default();
+    if module.backend.storage.get_individual(id, &mut individual) != ResultCode::Ok {
+        return None;
+    }
+    individual.parse_all();
+    Some(individual)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ontology_lookup::TurtleOntology;
+    use crate::prompt_manager::{render_template, PromptContext};
+
+    fn prompt_with(text: &str, samples: i64) -> Individual {
+        let mut prompt = Individual::default();
+        prompt.set_id("v-bpa:DocumentAnalysisPrompt");
+        prompt.set_string("v-bpa:promptText", text, Lang::none());
+        prompt.set_integer("v-bpa:consensusSamples", samples);
+        prompt.set_uri("v-bpa:targetType", "v-bpa:ProcessDocument");
+        prompt
+    }
+
+    #[test]
+    fn test_versioned_content_compares_text_and_settings() {
+        let mut prompt = prompt_with("Извлеки документ", 1);
+        let content = versioned_content(&mut prompt);
+
+        // Метка и прочие неверсионируемые свойства не порождают новую версию
+        let mut relabeled = prompt_with("Извлеки документ", 1);
+        relabeled.set_string("rdfs:label", "Анализ документа", Lang::none());
+        assert_eq!(versioned_content(&mut relabeled), content);
+
+        assert_ne!(versioned_content(&mut prompt_with("Извлеки процесс", 1)), content);
+        assert_ne!(versioned_content(&mut prompt_with("Извлеки документ", 3)), content);
+
+        let mut retargeted = prompt_with("Извлеки документ", 1);
+        retargeted.set_uri("v-bpa:targetType", "v-bpa:BusinessProcess");
+        assert_ne!(versioned_content(&mut retargeted), content);
+    }
+
+    #[test]
+    fn test_fragments_inlined_into_version_text() {
+        let mut ontology = TurtleOntology::default();
+        let mut fragment = Individual::default();
+        fragment.set_id("v-bpa:FieldsFragment");
+        fragment.set_uri("rdf:type", "v-bpa:PromptFragment");
+        fragment.set_string("v-bpa:promptText", "- 'date': {{date}}", Lang::none());
+        ontology.insert(&fragment);
+
+        let template = "Поля:\n{{> v-bpa:FieldsFragment}}\n{{#if department}}Отдел: {{department}}.{{else}}Без отдела.{{/if}}{{#if language}}{{language}}{{/if}}";
+        let text = inline_fragments(template, &mut ontology).expect("Failed to inline fragments");
+        // Переменные и условные разделы остаются в тексте версии, подставляется только фрагмент
+        assert_eq!(text, "Поля:\n- 'date': {{date}}\n{{#if department}}Отдел: {{department}}.{{else}}Без отдела.{{/if}}{{#if language}}{{language}}{{/if}}");
+
+        // Версия отображается так же, как исходный промпт, и не зависит от последующих правок фрагмента
+        let mut context = PromptContext::default();
+        context.set("date", "2026-10-19").set("department", "Склад");
+        let rendered = render_template(template, &context, &mut ontology).expect("Failed to render prompt");
+        fragment.set_string("v-bpa:promptText", "- 'label'", Lang::none());
+        ontology.insert(&fragment);
+        assert_eq!(render_template(&text, &context, &mut ontology).expect("Failed to render version"), rendered);
+    }
+}
@@ -0,0 +1,275 @@
+// proposal_handler.rs
+
+use crate::business_process_handler::analyze_process_justification;
+use crate::clustering_common;
+use crate::common::generate_event_id;
+use crate::queue_processor::BusinessProcessAnalysisModule;
+use std::collections::BTreeSet;
+use v_common::onto::datatype::Lang;
+use v_common::onto::individual::Individual;
+use v_common::v_api::api_client::IndvOp;
+use v_common::v_api::obj::ResultCode;
+
+/// Строковые свойства предложенного процесса и соответствующие им свойства бизнес-процесса
+const PROPOSED_STRING_PROPERTIES: &[(&str, &str)] = &[
+    ("v-bpa:proposedClusterName", "rdfs:label"),
+    ("v-bpa:proposedClusterDescription", "v-bpa:processDescription"),
+    ("v-bpa:proposedDepartment", "v-bpa:responsibleDepartment"),
+    ("v-bpa:proposedParticipants", "v-bpa:processParticipant"),
+];
+
+/// Выполняет действие над предложением оптимизации кластера, заданное в v-bpa:proposalAction
+///
+/// - v-bpa:AcceptProposal - создает объединенный бизнес-процесс, исходные процессы помечаются замененными
+/// - v-bpa:RevertProposal - удаляет объединенный процесс и возвращает исходные процессы
+pub fn handle_proposal_action(module: &mut BusinessProcessAnalysisModule, cluster_id: &str, in_event_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let event_id = match generate_event_id("CPA", cluster_id, in_event_id) {
+        Some(s) => s,
+        None => return Ok(()),
+    };
+
+    let mut cluster = load_individual(module, cluster_id)?;
+    let action = cluster.get_first_literal("v-bpa:proposalAction").unwrap_or_default();
+
+    let result = match action.as_str() {
+        "v-bpa:AcceptProposal" => accept_proposal(module, &mut cluster, &event_id),
+        "v-bpa:RevertProposal" => revert_proposal(module, &mut cluster, &event_id),
+        _ => return Ok(()),
+    };
+
+    cluster.set_uri("v-bpa:proposalAction", "v-bpa:NoProposalAction");
+    if let Err(e) = &result {
+        error!("Failed to execute {} for cluster {}: {}", action, cluster_id, e);
+        cluster.set_string("v-bpa:lastError", &e.to_string(), Lang::none());
+    } else {
+        cluster.remove("v-bpa:lastError");
+    }
+    clustering_common::update_individual(module, &mut cluster, IndvOp::Put, &event_id)?;
+
+    result
+}
+
+/// Создает объединенный бизнес-процесс из предложения оптимизации кластера
+///
+/// Все исходные процессы загружаются и проверяются до первой записи. Если отметить исходные процессы не удалось,
+/// уже отмеченные процессы возвращаются, а объединенный процесс помечается удаленным
+fn accept_proposal(module: &mut BusinessProcessAnalysisModule, cluster: &mut Individual, event_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(merged_id) = cluster.get_first_literal("v-bpa:mergedProcess") {
+        return Err(format!("Proposal of cluster {} is already accepted as {}", cluster.get_id(), merged_id).into());
+    }
+
+    let process_ids = cluster.get_literals("v-bpa:hasProcess").unwrap_or_default();
+    let mut originals = Vec::new();
+    for process_id in &process_ids {
+        originals.push(load_individual(module, process_id)?);
+    }
+
+    let merged_id = format!("d:bpa_process_{}", uuid::Uuid::new_v4());
+    let mut merged = build_merged_process(cluster, &merged_id, &mut originals)?;
+    info!("Accepting proposal of cluster {} for {} processes as {}", cluster.get_id(), originals.len(), merged_id);
+
+    clustering_common::update_individual(module, &mut merged, IndvOp::Put, event_id)?;
+
+    for index in 0..originals.len() {
+        mark_superseded(&mut originals[index], &merged_id);
+        if let Err(e) = clustering_common::update_individual(module, &mut originals[index], IndvOp::Put, event_id) {
+            rollback_accept(module, &mut merged, &mut originals[..index], event_id);
+            return Err(e);
+        }
+    }
+
+    cluster.set_uri("v-bpa:mergedProcess", &merged_id);
+    cluster.set_uri("v-bpa:hasProposalStatus", "v-bpa:ProposalAccepted");
+    info!("Created merged process {} from cluster {}", merged_id, cluster.get_id());
+
+    // Оцениваем обоснованность объединенного процесса по документам исходных процессов
+    if let Err(e) = analyze_process_justification(module, &mut merged, None, event_id) {
+        warn!("Failed to analyze justification of merged process {}: {}", merged_id, e);
+    }
+
+    Ok(())
+}
+
+/// Формирует объединенный бизнес-процесс по предложению кластера, проверяя, что исходные процессы еще не объединены
+fn build_merged_process(cluster: &mut Individual, merged_id: &str, originals: &mut [Individual]) -> Result<Individual, Box<dyn std::error::Error>> {
+    cluster.get_first_literal("v-bpa:proposedClusterName").ok_or("Cluster has no optimization proposal")?;
+
+    let mut merged = Individual::default();
+    merged.set_id(merged_id);
+    merged.set_uri("rdf:type", "v-bpa:BusinessProcess");
+
+    for (proposed, predicate) in PROPOSED_STRING_PROPERTIES {
+        if let Some(value) = cluster.get_first_literal(proposed) {
+            merged.set_string(predicate, &value, Lang::none());
+        }
+    }
+    if let Some(frequency) = cluster.get_first_integer("v-bpa:proposedFrequency") {
+        merged.set_integer("v-bpa:processFrequency", frequency);
+    }
+    if let Some(labor_cost) = cluster.get_first_integer("v-bpa:estimatedLaborCost") {
+        merged.set_decimal_from_f64("v-bpa:laborCosts", labor_cost as f64);
+    }
+
+    // Обоснования объединенного процесса - все документы исходных процессов
+    let mut documents = BTreeSet::new();
+    let mut process_ids = Vec::new();
+    for process in originals.iter_mut() {
+        if let Some(merged_into) = process.get_first_literal("v-bpa:mergedInto") {
+            return Err(format!("Process {} is already merged into {}", process.get_id(), merged_into).into());
+        }
+        documents.extend(process.get_literals("v-bpa:hasProcessDocument").unwrap_or_default());
+        process_ids.push(process.get_id().to_string());
+    }
+
+    merged.set_uris("v-bpa:hasProcessDocument", documents.into_iter().collect());
+    merged.set_uris("v-bpa:supersedes", process_ids);
+    merged.set_uri("v-bpa:mergedFromCluster", cluster.get_id());
+    if let Some(optimization_id) = cluster.get_first_literal("v-bpa:currentOptimization") {
+        merged.set_uri("v-bpa:basedOnOptimization", &optimization_id);
+    }
+
+    Ok(merged)
+}
+
+/// Отмечает исходный процесс как замененный объединенным процессом
+fn mark_superseded(process: &mut Individual, merged_id: &str) {
+    process.set_uri("v-bpa:mergedInto", merged_id);
+    process.set_bool("v-bpa:isSuperseded", true);
+}
+
+/// Снимает отметку о замене, если процесс заменен именно этим объединенным процессом
+fn unmark_superseded(process: &mut Individual, merged_id: &str) -> bool {
+    if process.get_first_literal("v-bpa:mergedInto").as_deref() != Some(merged_id) {
+        return false;
+    }
+    process.remove("v-bpa:mergedInto");
+    process.remove("v-bpa:isSuperseded");
+    true
+}
+
+/// Откатывает частично выполненное принятие предложения
+fn rollback_accept(module: &mut BusinessProcessAnalysisModule, merged: &mut Individual, marked: &mut [Individual], event_id: &str) {
+    let merged_id = merged.get_id().to_string();
+    for process in marked.iter_mut() {
+        if unmark_superseded(process, &merged_id) {
+            if let Err(e) = clustering_common::update_individual(module, process, IndvOp::Put, event_id) {
+                error!("Failed to restore process {} after failed merge into {}: {}", process.get_id(), merged_id, e);
+            }
+        }
+    }
+
+    merged.set_bool("v-s:deleted", true);
+    if let Err(e) = clustering_common::update_individual(module, merged, IndvOp::Put, event_id) {
+        error!("Failed to delete merged process {} after failed merge: {}", merged_id, e);
+    }
+}
+
+/// Отменяет принятое предложение: удаляет объединенный процесс и снимает отметку с исходных процессов
+fn revert_proposal(module: &mut BusinessProcessAnalysisModule, cluster: &mut Individual, event_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let merged_id = cluster.get_first_literal("v-bpa:mergedProcess").ok_or("Cluster proposal is not accepted")?;
+    info!("Reverting merged process {} of cluster {}", merged_id, cluster.get_id());
+
+    let mut merged = load_individual(module, &merged_id)?;
+
+    for process_id in merged.get_literals("v-bpa:supersedes").unwrap_or_default() {
+        let mut process = load_individual(module, &process_id)?;
+        if !unmark_superseded(&mut process, &merged_id) {
+            warn!("Process {} is not merged into {}, skipping", process_id, merged_id);
+            continue;
+        }
+        clustering_common::update_individual(module, &mut process, IndvOp::Put, event_id)?;
+    }
+
+    merged.set_bool("v-s:deleted", true);
+    clustering_common::update_individual(module, &mut merged, IndvOp::Put, event_id)?;
+
+    cluster.remove("v-bpa:mergedProcess");
+    cluster.set_uri("v-bpa:hasProposalStatus", "v-bpa:ProposalReverted");
+    info!("Reverted merged process {} of cluster {}", merged_id, cluster.get_id());
+
+    Ok(())
+}
+
+fn load_individual(module: &mut BusinessProcessAnalysisModule, id: &str) -> Result<Individual, Box<dyn std::error::Error>> {
+    let mut individual = Individual::default();
+    if module.backend.storage.get_individual(id, &mut individual) != ResultCode::Ok {
+        return Err(format!("Failed to load individual {}", id).into());
+    }
+    individual.parse_all();
+    Ok(individual)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(id: &str, documents: &[&str]) -> Individual {
+        let mut process = Individual::default();
+        process.set_id(id);
+        process.set_uri("rdf:type", "v-bpa:BusinessProcess");
+        process.set_uris("v-bpa:hasProcessDocument", documents.iter().map(|d| d.to_string()).collect());
+        process
+    }
+
+    fn proposal_cluster() -> Individual {
+        let mut cluster = Individual::default();
+        cluster.set_id("d:cluster_1");
+        cluster.set_string("v-bpa:proposedClusterName", "Merged process", Lang::none());
+        cluster.set_integer("v-bpa:proposedFrequency", 12);
+        cluster.set_integer("v-bpa:estimatedLaborCost", 40);
+        cluster.set_uri("v-bpa:currentOptimization", "d:optimization_1");
+        cluster
+    }
+
+    #[test]
+    fn test_build_merged_process() {
+        let mut cluster = proposal_cluster();
+        let mut originals = vec![process("d:p1", &["d:doc1", "d:doc2"]), process("d:p2", &["d:doc2", "d:doc3"])];
+
+        let mut merged = build_merged_process(&mut cluster, "d:merged", &mut originals).unwrap();
+
+        assert_eq!(merged.get_id(), "d:merged");
+        assert_eq!(merged.get_first_literal("rdfs:label").as_deref(), Some("Merged process"));
+        assert_eq!(merged.get_first_integer("v-bpa:processFrequency"), Some(12));
+        assert_eq!(merged.get_literals("v-bpa:hasProcessDocument").unwrap(), vec!["d:doc1", "d:doc2", "d:doc3"]);
+        assert_eq!(merged.get_literals("v-bpa:supersedes").unwrap(), vec!["d:p1", "d:p2"]);
+        assert_eq!(merged.get_first_literal("v-bpa:mergedFromCluster").as_deref(), Some("d:cluster_1"));
+        assert_eq!(merged.get_first_literal("v-bpa:basedOnOptimization").as_deref(), Some("d:optimization_1"));
+    }
+
+    #[test]
+    fn test_build_merged_process_rejects_merged_original() {
+        let mut cluster = proposal_cluster();
+        let mut merged_original = process("d:p2", &[]);
+        mark_superseded(&mut merged_original, "d:other_merged");
+        let mut originals = vec![process("d:p1", &[]), merged_original];
+
+        assert!(build_merged_process(&mut cluster, "d:merged", &mut originals).is_err());
+    }
+
+    #[test]
+    fn test_build_merged_process_requires_proposal() {
+        let mut cluster = Individual::default();
+        cluster.set_id("d:cluster_1");
+        let mut originals = vec![process("d:p1", &[])];
+
+        assert!(build_merged_process(&mut cluster, "d:merged", &mut originals).is_err());
+    }
+
+    #[test]
+    fn test_mark_and_unmark_superseded() {
+        let mut original = process("d:p1", &[]);
+
+        mark_superseded(&mut original, "d:merged");
+        assert_eq!(original.get_first_literal("v-bpa:mergedInto").as_deref(), Some("d:merged"));
+        assert_eq!(original.get_first_bool("v-bpa:isSuperseded"), Some(true));
+
+        // Процесс, замененный другим объединенным процессом, не возвращается
+        assert!(!unmark_superseded(&mut original, "d:other_merged"));
+        assert_eq!(original.get_first_literal("v-bpa:mergedInto").as_deref(), Some("d:merged"));
+
+        assert!(unmark_superseded(&mut original, "d:merged"));
+        assert_eq!(original.get_first_literal("v-bpa:mergedInto"), None);
+        assert_eq!(original.get_first_bool("v-bpa:isSuperseded"), None);
+    }
+}
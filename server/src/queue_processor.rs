@@ -1,12 +1,19 @@
 // queue_processor.rs
 
+use crate::bulk_analysis_handler::process_bulk_analysis;
 use crate::business_process_handler::analyze_process_justification;
 use crate::cluster_optimizer::analyze_and_optimize_cluster;
 use crate::clustering_handler::analyze_process_clusters;
 use crate::document_status_handler::handle_document_status;
 use crate::generic_processing_handler::process_generic_request;
+use crate::optimization_history::rollback_optimization;
 use crate::pipeline::business_process_extraction::business_process_extraction_pipeline;
 use crate::pipeline::raw_document_extracting_and_structuring::raw_document_extracting_and_structuring;
+use crate::prompt_lint::lint_and_save;
+use crate::prompt_version::{snapshot_all_prompts, snapshot_prompt};
+use crate::proposal_handler::handle_proposal_action;
+use crate::review_workflow::handle_human_review;
+use crate::schema_cache::SchemaCache;
 use openai_dive::v1::api::Client;
 use v_common::ft_xapian::xapian_reader::XapianReader;
 use v_common::module::info::ModuleInfo;
@@ -26,6 +33,7 @@ pub struct BusinessProcessAnalysisModule {
     pub reasoning_model: String,
     pub ticket: String,
     pub module_info: ModuleInfo,
+    pub schema_cache: SchemaCache,
 }
 
 impl VedaQueueModule for BusinessProcessAnalysisModule {
@@ -63,6 +71,8 @@ fn prepare_queue_element(module: &mut BusinessProcessAnalysisModule, queue_eleme
     let event_id = queue_element.get_first_literal("event_id").unwrap_or_default();
     let source = queue_element.get_first_literal("src").unwrap_or_default();
 
+    invalidate_compiled_schemas(module, queue_element);
+
     let cmd = IndvOp::from_i64(queue_element.get_first_integer("cmd").unwrap_or(IndvOp::None.to_i64()));
     if cmd == IndvOp::Remove || cmd == IndvOp::None {
         return Ok(true);
@@ -89,16 +99,20 @@ fn prepare_queue_element(module: &mut BusinessProcessAnalysisModule, queue_eleme
 
     let counter = new_state.get_first_integer("v-s:updateCounter").unwrap_or(-1);
 
+    // Проверка результата AI человеком фиксируется в состоянии проверки
+    let is_review = source != "BPA" && handle_review(module, queue_element, &mut new_state, &event_id);
+
     // Обработка в зависимости от типа индивида
     if new_state.any_exists("rdf:type", &[&"v-bpa:BusinessProcess".to_string()]) {
-        if source == "BPA" {
+        // Проверка результатов человеком не требует повторного анализа
+        if source == "BPA" || is_review {
             return Ok(true);
         }
 
         info!("Found a saved object of type 'v-bpa:BusinessProcess' with ID: {}", new_state.get_id());
 
         // Анализируем обоснованность бизнес-процесса
-        if let Err(e) = analyze_process_justification(module, &mut new_state, &event_id) {
+        if let Err(e) = analyze_process_justification(module, &mut new_state, None, &event_id) {
             error!("Error analyzing business process justification: {:?}", e);
         }
     } else if new_state.any_exists("rdf:type", &[&"v-bpa:ClusterizationAttempt".to_string()]) {
@@ -113,6 +127,22 @@ fn prepare_queue_element(module: &mut BusinessProcessAnalysisModule, queue_eleme
             error!("Error analyzing process clusters: {:?}", e);
         }
     } else if new_state.any_exists("rdf:type", &[&"v-bpa:ProcessCluster".to_string()]) {
+        if source != "BPA" && new_state.get_first_literal("v-bpa:rollbackToOptimization").is_some() {
+            info!("Rollback of optimization requested for cluster: {}", new_state.get_id());
+            if let Err(e) = rollback_optimization(module, new_state.get_id(), &event_id) {
+                error!("Error rolling back cluster optimization: {:?}", e);
+            }
+            return Ok(true);
+        }
+
+        if source != "BPA" && new_state.any_exists("v-bpa:proposalAction", &["v-bpa:AcceptProposal", "v-bpa:RevertProposal"]) {
+            info!("Proposal action requested for cluster: {}", new_state.get_id());
+            if let Err(e) = handle_proposal_action(module, new_state.get_id(), &event_id) {
+                error!("Error handling cluster proposal action: {:?}", e);
+            }
+            return Ok(true);
+        }
+
         if counter > 1 {
             return Ok(true);
         }
@@ -121,6 +151,15 @@ fn prepare_queue_element(module: &mut BusinessProcessAnalysisModule, queue_eleme
         if let Err(e) = analyze_and_optimize_cluster(module, new_state.get_id(), &event_id) {
             error!("Error analyze and_optimize cluster: {:?}", e);
         }
+    } else if new_state.any_exists("rdf:type", &["v-bpa:BulkAnalysisRequest"]) {
+        if source == "BPA" {
+            return Ok(true);
+        }
+
+        info!("Found bulk analysis request: {}:{}", new_state.get_id(), counter);
+        if let Err(e) = process_bulk_analysis(module, &mut new_state, &event_id) {
+            error!("Error processing bulk analysis request: {:?}", e);
+        }
     } else if new_state.any_exists("rdf:type", &[&"v-bpa:GenericProcessingRequest".to_string()]) {
         if source == "BPA" {
             return Ok(true);
@@ -163,7 +202,67 @@ fn prepare_queue_element(module: &mut BusinessProcessAnalysisModule, queue_eleme
         if let Err(e) = handle_document_status(module, &mut new_state, &event_id) {
             error!("Error handling document status: {:?}", e);
         }
+    } else if new_state.any_exists("rdf:type", &["v-bpa:SystemPrompt"]) {
+        // Результат проверки записывается в промпт от имени BPA
+        if source == "BPA" {
+            return Ok(true);
+        }
+
+        info!("Found a saved system prompt: {}", new_state.get_id());
+        if let Err(e) = snapshot_prompt(module, &mut new_state, &event_id) {
+            error!("Error creating prompt version: {:?}", e);
+        }
+        if let Err(e) = lint_and_save(module, &mut new_state, &event_id) {
+            error!("Error checking prompt schema: {:?}", e);
+        }
+    } else if new_state.any_exists("rdf:type", &["v-bpa:PromptFragment"]) {
+        if source == "BPA" {
+            return Ok(true);
+        }
+
+        // Фрагмент может входить в любой промпт, промпты с измененным текстом получают новые версии
+        info!("Found a saved prompt fragment: {}", new_state.get_id());
+        snapshot_all_prompts(module, &event_id);
     }
 
     Ok(true)
 }
+
+/// Удаляет из кэша скомпилированные схемы, зависящие от измененного или удаленного индивида
+fn invalidate_compiled_schemas(module: &mut BusinessProcessAnalysisModule, queue_element: &mut Individual) {
+    if module.schema_cache.is_empty() {
+        return;
+    }
+
+    // Прежнее состояние нужно, чтобы учесть удаление индивида и смену его типа
+    for state in ["prev_state", "new_state"] {
+        let mut individual = Individual::default();
+        if get_inner_binobj_as_individual(queue_element, state, &mut individual) && parse_raw(&mut individual).is_ok() {
+            module.schema_cache.invalidate(&mut individual);
+        }
+    }
+}
+
+/// Сравнивает новое состояние результата AI с предыдущим и фиксирует проверку человеком
+///
+/// # Returns
+/// * `true`, если сохранение является проверкой или исправлением результата AI
+fn handle_review(module: &mut BusinessProcessAnalysisModule, queue_element: &mut Individual, new_state: &mut Individual, event_id: &str) -> bool {
+    if !new_state.is_exists("v-bpa:hasReviewState") {
+        return false;
+    }
+
+    let mut prev_state = Individual::default();
+    if !get_inner_binobj_as_individual(queue_element, "prev_state", &mut prev_state) || parse_raw(&mut prev_state).is_err() {
+        return false;
+    }
+
+    let user_uri = queue_element.get_first_literal("user_uri").unwrap_or_default();
+    match handle_human_review(module, &mut prev_state, new_state, &user_uri, event_id) {
+        Ok(is_review) => is_review,
+        Err(e) => {
+            error!("Error handling review of {}: {:?}", new_state.get_id(), e);
+            false
+        },
+    }
+}
@@ -0,0 +1,141 @@
+// review_workflow.rs
+
+use crate::clustering_common;
+use crate::queue_processor::BusinessProcessAnalysisModule;
+use chrono::Utc;
+use v_common::onto::individual::Individual;
+use v_common::v_api::api_client::IndvOp;
+
+/// Служебные свойства, которые не считаются результатом AI
+const SERVICE_PROPERTIES: &[&str] = &["rdf:type", "v-s:attachment", "v-s:created", "v-s:creator", "v-s:updateCounter", "v-s:hasParentLink", "v-bpa:usedPromptVersion"];
+
+/// Состояние проверки результата AI человеком
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReviewState {
+    /// Результат записан AI и ожидает проверки
+    Pending,
+    /// Результат подтвержден человеком
+    Approved,
+    /// Результат отклонен человеком, повторный анализ может его заменить
+    Rejected,
+    /// Результат исправлен человеком
+    Edited,
+}
+
+impl ReviewState {
+    pub fn uri(&self) -> &'static str {
+        match self {
+            ReviewState::Pending => "v-bpa:ReviewPending",
+            ReviewState::Approved => "v-bpa:ReviewApproved",
+            ReviewState::Rejected => "v-bpa:ReviewRejected",
+            ReviewState::Edited => "v-bpa:ReviewEdited",
+        }
+    }
+
+    pub fn from_uri(uri: &str) -> Option<Self> {
+        match uri {
+            "v-bpa:ReviewPending" => Some(ReviewState::Pending),
+            "v-bpa:ReviewApproved" => Some(ReviewState::Approved),
+            "v-bpa:ReviewRejected" => Some(ReviewState::Rejected),
+            "v-bpa:ReviewEdited" => Some(ReviewState::Edited),
+            _ => None,
+        }
+    }
+}
+
+pub fn get_review_state(individual: &mut Individual) -> Option<ReviewState> {
+    individual.get_first_literal("v-bpa:hasReviewState").and_then(|uri| ReviewState::from_uri(&uri))
+}
+
+/// Результат, подтвержденный или исправленный человеком, не перезаписывается повторным анализом
+pub fn is_protected(individual: &mut Individual) -> bool {
+    matches!(get_review_state(individual), Some(ReviewState::Approved) | Some(ReviewState::Edited))
+}
+
+/// Возвращает все свойства индивида, созданного из ответа AI, кроме служебных
+pub fn generated_properties(individual: &Individual) -> Vec<String> {
+    individual.get_obj().get_resources().keys().filter(|predicate| !SERVICE_PROPERTIES.contains(&predicate.as_str())).cloned().collect()
+}
+
+/// Помечает результат AI ожидающим проверки и запоминает свойства, записанные AI,
+/// чтобы распознать их исправление человеком
+pub fn mark_pending_review(individual: &mut Individual, ai_properties: &[String]) {
+    individual.set_uri("v-bpa:hasReviewState", ReviewState::Pending.uri());
+    individual.set_uris("v-bpa:aiGeneratedProperty", ai_properties.to_vec());
+    individual.remove("v-bpa:reviewedBy");
+    individual.remove("v-bpa:reviewedAt");
+}
+
+/// Создает индивид предложения AI для защищенного результата, значения предложения записываются в него вызывающей стороной
+pub fn new_suggestion(target: &mut Individual) -> Individual {
+    let mut suggestion = Individual::default();
+    suggestion.set_id(&format!("d:bpa_suggestion_{}", uuid::Uuid::new_v4()));
+    suggestion.set_uri("rdf:type", "v-bpa:AISuggestion");
+    suggestion.set_uri("v-bpa:suggestionFor", target.get_id());
+    if let Some(previous_id) = target.get_first_literal("v-bpa:hasAISuggestion") {
+        suggestion.set_uri("v-bpa:previousSuggestion", &previous_id);
+    }
+    suggestion
+}
+
+/// Сохраняет предложение AI рядом с защищенным результатом и делает его текущим предложением
+pub fn save_suggestion(
+    module: &mut BusinessProcessAnalysisModule,
+    target: &mut Individual,
+    suggestion: &mut Individual,
+    ai_properties: &[String],
+    event_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    suggestion.set_uris("v-bpa:aiGeneratedProperty", ai_properties.to_vec());
+    suggestion.set_datetime("v-s:created", Utc::now().timestamp());
+    clustering_common::update_individual(module, suggestion, IndvOp::Put, event_id)?;
+
+    target.set_uri("v-bpa:hasAISuggestion", suggestion.get_id());
+    info!("Result of {} is reviewed by a person, AI suggestion saved as {}", target.get_id(), suggestion.get_id());
+    Ok(())
+}
+
+/// Фиксирует проверку результата AI человеком: изменение v-bpa:hasReviewState
+/// или исправление свойств, записанных AI
+///
+/// Исправленный результат переводится в состояние v-bpa:ReviewEdited.
+/// Проверивший и время проверки записываются в v-bpa:reviewedBy и v-bpa:reviewedAt.
+///
+/// # Returns
+/// * `true`, если изменение является проверкой результата
+pub fn handle_human_review(
+    module: &mut BusinessProcessAnalysisModule,
+    prev_state: &mut Individual,
+    new_state: &mut Individual,
+    user_uri: &str,
+    event_id: &str,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let ai_properties = new_state.get_literals("v-bpa:aiGeneratedProperty").unwrap_or_default();
+    let is_edited = ai_properties.iter().any(|predicate| prev_state.get_resources(predicate) != new_state.get_resources(predicate));
+
+    let new_review = get_review_state(new_state);
+    if !is_edited && get_review_state(prev_state) == new_review {
+        return Ok(false);
+    }
+
+    let state = if is_edited {
+        ReviewState::Edited
+    } else {
+        match new_review {
+            Some(state) => state,
+            None => return Ok(false),
+        }
+    };
+    info!("Result {} reviewed by {}: {}", new_state.get_id(), user_uri, state.uri());
+
+    let mut review = Individual::default();
+    review.set_id(new_state.get_id());
+    review.set_uri("v-bpa:hasReviewState", state.uri());
+    if !user_uri.is_empty() {
+        review.set_uri("v-bpa:reviewedBy", user_uri);
+    }
+    review.set_datetime("v-bpa:reviewedAt", Utc::now().timestamp());
+    clustering_common::update_individual(module, &mut review, IndvOp::SetIn, event_id)?;
+
+    Ok(true)
+}
@@ -0,0 +1,71 @@
+// schema_cache.rs
+
+use crate::schema_compiler::CompiledSchema;
+use std::collections::{HashMap, HashSet};
+use v_common::onto::individual::Individual;
+
+/// Кэш скомпилированных схем ответа AI по идентификатору промпта
+///
+/// Компиляция схемы загружает описания всех свойств и ищет экземпляры классов перечислений в индексе,
+/// поэтому схема компилируется один раз и используется, пока в очереди не появится изменение
+/// промпта, свойства схемы или экземпляра класса перечисления.
+#[derive(Default)]
+pub struct SchemaCache {
+    entries: HashMap<String, CachedSchema>,
+}
+
+struct CachedSchema {
+    prompt_id: String,
+    schema: CompiledSchema,
+}
+
+impl SchemaCache {
+    /// Ключ кэша: промпт, исключенные из перечислений экземпляры и язык, заданный запросом
+    pub fn key(prompt_id: &str, excluded: Option<&HashSet<&str>>, language: Option<&str>) -> String {
+        let mut key = prompt_id.to_string();
+
+        let mut excluded: Vec<&str> = excluded.map(|excluded| excluded.iter().copied().collect()).unwrap_or_default();
+        if !excluded.is_empty() {
+            excluded.sort_unstable();
+            key = format!("{}|{}", key, excluded.join(","));
+        }
+        if let Some(language) = language {
+            key = format!("{}@{}", key, language);
+        }
+        key
+    }
+
+    pub fn get(&self, key: &str) -> Option<CompiledSchema> {
+        self.entries.get(key).map(|entry| entry.schema.clone())
+    }
+
+    pub fn insert(&mut self, key: String, prompt_id: &str, schema: CompiledSchema) {
+        self.entries.insert(
+            key,
+            CachedSchema {
+                prompt_id: prompt_id.to_string(),
+                schema,
+            },
+        );
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Удаляет схемы, зависящие от измененного индивида: самого промпта, свойства схемы
+    /// или экземпляра класса, метки которого перечислены в схеме
+    pub fn invalidate(&mut self, changed: &mut Individual) {
+        let id = changed.get_id().to_string();
+        let types = changed.get_literals("rdf:type").unwrap_or_default();
+
+        self.entries.retain(|key, entry| {
+            let dependencies = &entry.schema.dependencies;
+            let is_stale = entry.prompt_id == id || dependencies.properties.contains(&id) || types.iter().any(|type_uri| dependencies.enum_classes.contains(type_uri));
+            if is_stale {
+                info!("Compiled schema {} invalidated by change of {}", key, id);
+            }
+            !is_stale
+        });
+    }
+}
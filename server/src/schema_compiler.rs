@@ -0,0 +1,866 @@
+// schema_compiler.rs
+
+use crate::common::normalize_text;
+use crate::entity_resolution::{label_similarity, EntityCandidate, MatchSettings};
+use crate::ontology_lookup::{OntologyLookup, PropertyDefinition};
+use crate::response_schema::{ParseResult, Property, ResponseSchema};
+use crate::types::PropertyMapping;
+use crate::value_types::{get_typed_values, set_typed_value, ValueType, DEFAULT_LANGUAGE};
+use indexmap::IndexMap;
+use serde_json::{json, Map, Value};
+use std::collections::HashSet;
+use v_common::onto::datatype::Lang;
+use v_common::onto::individual::Individual;
+
+/// Служебные ключи описания поля, которые не передаются в JSON-схему для AI
+const SERVICE_KEYS: &[&str] = &["mapping", "assign_properties", "create_new_individuals", "lang", "match_by", "match_threshold", "required"];
+
+/// Схема ответа AI, скомпилированная из v-bpa:properties или v-bpa:responseSchema промпта
+///
+/// По схеме строится JSON-схема запроса к AI и записываются значения ответа в индивиды,
+/// поэтому обработка типов значений, перечислений и вложенных объектов одинакова для всех обработчиков.
+#[derive(Debug, Clone)]
+pub struct CompiledSchema {
+    pub type_name: String,
+    pub root: ObjectSchema,
+    /// Язык промпта, на котором AI передаются метки перечислений
+    pub language: String,
+    /// Язык ответа AI, с которым записываются строки результата; задается обработчиком для запроса
+    pub output_language: Option<String>,
+    /// Версия промпта, из которой скомпилирована схема; записывается в v-bpa:usedPromptVersion индивидов результата
+    pub prompt_version: Option<String>,
+    pub dependencies: SchemaDependencies,
+}
+
+/// Индивиды онтологии, из которых собрана схема: при их изменении схему нужно компилировать заново
+#[derive(Debug, Clone, Default)]
+pub struct SchemaDependencies {
+    /// Свойства, описания которых загружались при компиляции, в том числе не найденные
+    pub properties: HashSet<String>,
+    /// Классы, экземпляры которых перечислены в схеме
+    pub enum_classes: HashSet<String>,
+}
+
+/// Объект схемы ответа: корень схемы или вложенный объект
+#[derive(Debug, Clone, Default)]
+pub struct ObjectSchema {
+    pub fields: IndexMap<String, SchemaField>,
+    /// Тип индивида, создаваемого для объекта: rdfs:range свойства связи
+    pub type_uri: Option<String>,
+    /// Список обязательных полей, объявленный в схеме
+    pub required: Option<Value>,
+    /// Значения, которые записываются в индивид этого уровня схемы
+    pub assign_properties: Vec<(String, String)>,
+}
+
+/// Поле ответа AI и свойство онтологии, в которое записывается его значение
+#[derive(Debug, Clone)]
+pub struct SchemaField {
+    pub property: Option<String>,
+    pub kind: FieldKind,
+    /// Поле содержит массив значений
+    pub is_array: bool,
+    /// Свойство допускает несколько значений
+    pub is_multiple: bool,
+    /// Тип значения (элемента массива), объявленный в схеме
+    pub json_type: Option<String>,
+    /// Ключи описания массива, передаваемые в JSON-схему без изменений
+    pub array_extra: Map<String, Value>,
+    /// Ключи описания значения (элемента массива), передаваемые в JSON-схему без изменений
+    pub value_extra: Map<String, Value>,
+    pub match_settings: MatchSettings,
+}
+
+/// Вид значения поля
+#[derive(Debug, Clone)]
+pub enum FieldKind {
+    /// Литерал, тип которого определяется rdfs:range свойства
+    Literal { value_type: ValueType, lang: Option<String> },
+    /// Ссылка на экземпляр класса из rdfs:range, метки экземпляров передаются AI как перечисление
+    Reference { type_uri: String, enum_values: Vec<EnumValue> },
+    /// Вложенный объект: отдельный индивид для свойства связи, JSON-строка для свойства с типом owl:Class,
+    /// значения родительского индивида для поля без свойства
+    Object { schema: ObjectSchema, as_json: bool },
+    /// Значение описано обработчиком и записывается им самим, при записи ответа свойство только очищается
+    Custom(Value),
+}
+
+/// Значение перечисления ссылочного поля: экземпляр класса и его метки
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumValue {
+    pub uri: String,
+    /// Метка на языке промпта, передаваемая AI
+    pub label: String,
+    /// Метки экземпляра на всех языках, с которыми сопоставляется значение ответа
+    pub labels: Vec<String>,
+}
+
+/// Компилятор схем ответа AI
+pub struct SchemaCompiler<'a> {
+    ontology: &'a mut dyn OntologyLookup,
+    excluded: HashSet<String>,
+    language: String,
+    dependencies: SchemaDependencies,
+}
+
+impl<'a> SchemaCompiler<'a> {
+    pub fn new(ontology: &'a mut dyn OntologyLookup) -> Self {
+        SchemaCompiler {
+            ontology,
+            excluded: HashSet::new(),
+            language: DEFAULT_LANGUAGE.to_string(),
+            dependencies: SchemaDependencies::default(),
+        }
+    }
+
+    /// Задает язык промпта, на котором перечисляются метки экземпляров
+    pub fn language(mut self, language: &str) -> Self {
+        self.language = language.to_string();
+        self
+    }
+
+    /// Исключает экземпляры из перечислений схемы
+    pub fn exclude_instances(mut self, excluded: Option<HashSet<&str>>) -> Self {
+        self.excluded = excluded.unwrap_or_default().into_iter().map(str::to_string).collect();
+        self
+    }
+
+    /// Компилирует схему промпта: v-bpa:responseSchema, если она задана, иначе список v-bpa:properties
+    pub fn compile_prompt(&mut self, prompt: &mut Individual) -> Result<CompiledSchema, Box<dyn std::error::Error>> {
+        if let Some(response_schema) = prompt.get_first_literal("v-bpa:responseSchema") {
+            return self.compile_response_schema(&ResponseSchema::from_json(&response_schema)?);
+        }
+        let properties = prompt.get_literals("v-bpa:properties").unwrap_or_default();
+        Ok(self.compile_property_list(&properties))
+    }
+
+    /// Компилирует плоский список свойств v-bpa:properties, короткое имя поля - локальное имя свойства
+    pub fn compile_property_list(&mut self, properties: &[String]) -> CompiledSchema {
+        let mut root = ObjectSchema::default();
+
+        for full_prop in properties {
+            let definition = match self.load_property(full_prop) {
+                Some(definition) => definition,
+                None => {
+                    warn!("Failed to load property definition for {}", full_prop);
+                    continue;
+                },
+            };
+
+            let short_name = full_prop.rsplit(':').next().unwrap_or(full_prop).to_string();
+            let is_array = !definition.is_functional;
+
+            let mut description = Map::new();
+            description.insert("description".to_string(), Value::String(definition.label.clone().unwrap_or_else(|| full_prop.clone())));
+
+            let field = SchemaField {
+                property: Some(full_prop.clone()),
+                kind: self.value_kind(Some(&definition), None, false),
+                is_array,
+                is_multiple: is_array,
+                json_type: None,
+                array_extra: if is_array {
+                    description.clone()
+                } else {
+                    Map::new()
+                },
+                value_extra: if is_array {
+                    Map::new()
+                } else {
+                    description
+                },
+                match_settings: MatchSettings::default(),
+            };
+            root.fields.insert(short_name, field);
+        }
+
+        CompiledSchema {
+            type_name: "object".to_string(),
+            root,
+            language: self.language.clone(),
+            output_language: None,
+            prompt_version: None,
+            dependencies: std::mem::take(&mut self.dependencies),
+        }
+    }
+
+    /// Компилирует вложенную схему v-bpa:responseSchema
+    pub fn compile_response_schema(&mut self, schema: &ResponseSchema) -> Result<CompiledSchema, Box<dyn std::error::Error>> {
+        let mut root = self.compile_object(&schema.properties, &Map::new(), None)?;
+        root.assign_properties = schema.assign_properties.as_ref().map(|props| props.iter().map(|(k, v)| (k.clone(), v.clone())).collect()).unwrap_or_default();
+
+        Ok(CompiledSchema {
+            type_name: schema.type_name.clone(),
+            root,
+            language: self.language.clone(),
+            output_language: None,
+            prompt_version: None,
+            dependencies: std::mem::take(&mut self.dependencies),
+        })
+    }
+
+    fn compile_object(
+        &mut self,
+        properties: &IndexMap<String, Property>,
+        additional: &Map<String, Value>,
+        type_uri: Option<String>,
+    ) -> Result<ObjectSchema, Box<dyn std::error::Error>> {
+        let mut fields = IndexMap::new();
+        for (key, prop) in properties {
+            fields.insert(key.clone(), self.compile_field(prop)?);
+        }
+
+        Ok(ObjectSchema {
+            fields,
+            type_uri,
+            required: additional.get("required").cloned(),
+            assign_properties: get_assign_properties(additional),
+        })
+    }
+
+    fn compile_field(&mut self, prop: &Property) -> Result<SchemaField, Box<dyn std::error::Error>> {
+        let definition = match &prop.mapping {
+            Some(mapping_uri) => Some(self.load_property(mapping_uri).ok_or_else(|| format!("Property {} not found in ontology", mapping_uri))?),
+            None => None,
+        };
+
+        // Для массива значение описывается в items, настройки элементов дополняют настройки массива
+        let (value, is_array) = match &prop.items {
+            Some(items) => (items.as_ref(), true),
+            None => (prop, false),
+        };
+        let mut settings = prop.additional.clone();
+        if is_array {
+            settings.extend(value.additional.clone());
+        }
+        let match_settings = MatchSettings::from_schema(&settings);
+
+        let kind = match &value.properties {
+            Some(properties) => {
+                let is_class = definition.as_ref().is_some_and(|d| d.is_class);
+                let type_uri = definition.as_ref().filter(|d| !d.is_class).and_then(|d| d.range.clone()).filter(|range| ValueType::from_range(range).is_none());
+                FieldKind::Object {
+                    schema: self.compile_object(properties, &value.additional, type_uri)?,
+                    as_json: is_class,
+                }
+            },
+            None => {
+                let lang = settings.get("lang").and_then(Value::as_str).map(str::to_string);
+                self.value_kind(definition.as_ref(), lang, match_settings.create_new)
+            },
+        };
+
+        Ok(SchemaField {
+            property: prop.mapping.clone(),
+            kind,
+            is_array,
+            is_multiple: prop.is_multiple.unwrap_or_else(|| definition.as_ref().map_or(is_array, |d| !d.is_functional)),
+            json_type: value.type_name.clone(),
+            array_extra: if is_array {
+                get_schema_extra(&prop.additional)
+            } else {
+                Map::new()
+            },
+            value_extra: get_schema_extra(&value.additional),
+            match_settings,
+        })
+    }
+
+    /// Определяет вид скалярного значения по rdfs:range свойства
+    ///
+    /// Язык, объявленный в схеме, имеет приоритет над v-bpa:valueLanguage свойства.
+    /// Экземпляры класса не перечисляются, если разрешено создание новых индивидов.
+    fn value_kind(&mut self, definition: Option<&PropertyDefinition>, lang: Option<String>, create_new: bool) -> FieldKind {
+        let definition = match definition {
+            Some(definition) if !definition.is_class => definition,
+            _ => {
+                return FieldKind::Literal {
+                    value_type: ValueType::String,
+                    lang: None,
+                }
+            },
+        };
+
+        let range = definition.range.clone().unwrap_or_else(|| "xsd:string".to_string());
+        match ValueType::from_range(&range) {
+            Some(value_type) => FieldKind::Literal {
+                value_type,
+                lang: lang.or_else(|| definition.lang.clone()),
+            },
+            None => FieldKind::Reference {
+                enum_values: if create_new {
+                    Vec::new()
+                } else {
+                    self.list_instances(&range)
+                },
+                type_uri: range,
+            },
+        }
+    }
+
+    /// Загружает описание свойства из онтологии, None - свойство не найдено
+    pub fn load_property(&mut self, property: &str) -> Option<PropertyDefinition> {
+        self.dependencies.properties.insert(property.to_string());
+        self.ontology.get_property(property)
+    }
+
+    /// Возвращает экземпляры класса с метками для перечисления в схеме
+    ///
+    /// AI передается метка на языке промпта, экземпляр без такой метки перечисляется с меткой на другом языке.
+    pub fn list_instances(&mut self, type_uri: &str) -> Vec<EnumValue> {
+        self.dependencies.enum_classes.insert(type_uri.to_string());
+
+        let mut enum_values = Vec::new();
+        for mut instance in self.ontology.list_instances(type_uri) {
+            if self.excluded.contains(instance.get_id()) {
+                continue;
+            }
+
+            let labels = instance.get_literals("rdfs:label").unwrap_or_default();
+            let label = match instance.get_first_literal_with_lang("rdfs:label", &[Lang::new_from_str(&self.language)]).or_else(|| labels.first().cloned()) {
+                Some(label) => label,
+                None => continue,
+            };
+            enum_values.push(EnumValue {
+                uri: instance.get_id().to_string(),
+                label,
+                labels,
+            });
+        }
+        enum_values
+    }
+}
+
+impl CompiledSchema {
+    /// JSON-схема ответа, передаваемая AI
+    pub fn to_ai_schema(&self) -> Value {
+        self.root.to_json_schema(&self.type_name, true)
+    }
+
+    /// Добавляет поле, JSON-схема и запись значения которого выполняются обработчиком
+    pub fn add_custom_field(&mut self, name: &str, property: &str, schema: Value) {
+        self.root.fields.insert(
+            name.to_string(),
+            SchemaField {
+                property: Some(property.to_string()),
+                kind: FieldKind::Custom(schema),
+                is_array: false,
+                is_multiple: true,
+                json_type: None,
+                array_extra: Map::new(),
+                value_extra: Map::new(),
+                match_settings: MatchSettings::default(),
+            },
+        );
+    }
+
+    /// Возвращает поле верхнего уровня, значение которого записывается в свойство
+    pub fn field_for_property(&self, property: &str) -> Option<&str> {
+        self.root.fields.iter().find(|(_, field)| field.property.as_deref() == Some(property)).map(|(name, _)| name.as_str())
+    }
+
+    /// Возвращает свойства, в которые записываются значения ответа в индивид верхнего уровня
+    pub fn mapped_properties(&self) -> Vec<String> {
+        let mut properties = Vec::new();
+        self.root.collect_properties(&mut properties);
+        properties
+    }
+
+    /// Маппинг коротких имен полей и меток перечислений на языке промпта ("поле*метка") в URI
+    pub fn property_mapping(&self) -> PropertyMapping {
+        let mut mapping = PropertyMapping::new();
+        self.root.collect_mapping(&mut mapping);
+        mapping
+    }
+
+    /// Находит экземпляр перечисления поля верхнего уровня по метке на любом языке, в том числе по похожей метке
+    pub fn find_enum_uri(&self, field_name: &str, label: &str) -> Option<String> {
+        match self.root.fields.get(field_name) {
+            Some(SchemaField {
+                kind: FieldKind::Reference {
+                    enum_values,
+                    ..
+                },
+                match_settings,
+                ..
+            }) => find_enum_value(enum_values, label, match_settings.threshold).map(|value| value.uri.clone()),
+            _ => None,
+        }
+    }
+
+    /// Записывает значения ответа AI в индивид
+    ///
+    /// При replace_existing прежние значения свойств полей, присутствующих в ответе, удаляются.
+    /// Значения ссылочных свойств, не найденные в перечислениях, возвращаются в entity_candidates
+    /// и записываются после сопоставления сущностей. Индивид и созданные для вложенных объектов индивиды
+    /// получают ссылку на версию промпта схемы.
+    pub fn apply_values(
+        &self,
+        values: &Map<String, Value>,
+        individual: &mut Individual,
+        replace_existing: bool,
+    ) -> Result<(Vec<Individual>, Vec<EntityCandidate>), Box<dyn std::error::Error>> {
+        let mut mapped = MappedValues {
+            output_language: self.output_language.clone(),
+            prompt_version: self.prompt_version.clone(),
+            ..MappedValues::default()
+        };
+        self.root.apply(values, individual, &mut mapped, replace_existing)?;
+        apply_assign_properties(individual, &self.root.assign_properties);
+        set_prompt_version(individual, &mapped);
+        Ok((mapped.related_individuals, mapped.entity_candidates))
+    }
+
+    /// Разбирает ответ AI в новый индивид результата и связанные индивиды вложенных объектов
+    pub fn parse_response(&self, response: &Value) -> Result<ParseResult, Box<dyn std::error::Error>> {
+        let mut main_individual = Individual::default();
+        let result_id = format!("d:result_{}", uuid::Uuid::new_v4());
+        info!("Created result ID: {}", result_id);
+        main_individual.set_id(&result_id);
+        main_individual.set_uri("rdf:type", "v-bpa:GenericProcessingResult");
+
+        let (related_individuals, entity_candidates) = match response.as_object() {
+            Some(values) => self.apply_values(values, &mut main_individual, false)?,
+            None => (Vec::new(), Vec::new()),
+        };
+
+        Ok(ParseResult {
+            main_individual,
+            related_individuals,
+            entity_candidates,
+        })
+    }
+
+    /// Представляет индивид и связанные с ним индивиды в виде JSON, описанного схемой, обратное преобразование к apply_values
+    ///
+    /// Ссылки заменяются метками значений перечислений или rdfs:label связанного индивида,
+    /// индивиды вложенных объектов загружаются из хранилища.
+    pub fn individual_to_json(&self, id: &str, ontology: &mut dyn OntologyLookup) -> Result<Value, Box<dyn std::error::Error>> {
+        let mut individual = ontology.get_individual(id).ok_or_else(|| format!("Failed to load individual {}", id))?;
+        let mut visited = HashSet::from([id.to_string()]);
+        Ok(Value::Object(self.root.to_json(&mut individual, ontology, &self.language, &mut visited)))
+    }
+}
+
+/// Язык строк и версия промпта результата, индивиды и сущности, накопленные при записи ответа AI
+#[derive(Default)]
+struct MappedValues {
+    output_language: Option<String>,
+    prompt_version: Option<String>,
+    related_individuals: Vec<Individual>,
+    entity_candidates: Vec<EntityCandidate>,
+}
+
+impl ObjectSchema {
+    fn to_json_schema(&self, type_name: &str, require_all: bool) -> Value {
+        let properties: Map<String, Value> = self.fields.iter().map(|(name, field)| (name.clone(), field.to_json_schema())).collect();
+        let mut schema = json!({
+            "type": type_name,
+            "additionalProperties": false,
+            "properties": properties,
+        });
+
+        if require_all {
+            schema["required"] = json!(self.fields.keys().collect::<Vec<_>>());
+        } else if let Some(required) = &self.required {
+            schema["required"] = required.clone();
+        }
+        schema
+    }
+
+    fn collect_properties(&self, properties: &mut Vec<String>) {
+        for field in self.fields.values() {
+            match (&field.property, &field.kind) {
+                (Some(property), _) if !properties.contains(property) => properties.push(property.clone()),
+                (
+                    None,
+                    FieldKind::Object {
+                        schema,
+                        ..
+                    },
+                ) => schema.collect_properties(properties),
+                _ => {},
+            }
+        }
+    }
+
+    fn collect_mapping(&self, mapping: &mut PropertyMapping) {
+        for (name, field) in &self.fields {
+            match (&field.property, &field.kind) {
+                (
+                    Some(property),
+                    FieldKind::Reference {
+                        enum_values,
+                        ..
+                    },
+                ) => {
+                    mapping.insert(name.clone(), property.clone());
+                    for value in enum_values {
+                        mapping.insert(format!("{}*{}", name, value.label), value.uri.clone());
+                    }
+                },
+                (Some(property), _) => {
+                    mapping.insert(name.clone(), property.clone());
+                },
+                (
+                    None,
+                    FieldKind::Object {
+                        schema,
+                        ..
+                    },
+                ) => schema.collect_mapping(mapping),
+                _ => {},
+            }
+        }
+    }
+
+    fn to_json(&self, individual: &mut Individual, ontology: &mut dyn OntologyLookup, language: &str, visited: &mut HashSet<String>) -> Map<String, Value> {
+        self.fields.iter().filter_map(|(name, field)| field.to_json(individual, ontology, language, visited).map(|value| (name.clone(), value))).collect()
+    }
+
+    fn apply(
+        &self,
+        values: &Map<String, Value>,
+        individual: &mut Individual,
+        mapped: &mut MappedValues,
+        replace_existing: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for (name, field) in &self.fields {
+            if let Some(value) = values.get(name) {
+                field.apply(value, individual, mapped, replace_existing)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl SchemaField {
+    fn to_json_schema(&self) -> Value {
+        if let FieldKind::Custom(schema) = &self.kind {
+            return schema.clone();
+        }
+        if !self.is_array {
+            return self.value_schema();
+        }
+
+        let mut schema = json!({
+            "type": "array",
+            "items": self.value_schema()
+        });
+        extend_schema(&mut schema, &self.array_extra);
+        schema
+    }
+
+    fn value_schema(&self) -> Value {
+        let mut schema = match &self.kind {
+            FieldKind::Literal {
+                value_type,
+                ..
+            } => match &self.json_type {
+                Some(json_type) => {
+                    let mut schema = json!({ "type": json_type });
+                    if let (true, Some(format)) = (json_type == "string", value_type.json_format()) {
+                        schema["format"] = json!(format);
+                    }
+                    schema
+                },
+                None => value_type.json_schema(),
+            },
+            FieldKind::Reference {
+                enum_values,
+                ..
+            } => {
+                let mut schema = json!({ "type": self.json_type.as_deref().unwrap_or("string") });
+                if !enum_values.is_empty() {
+                    schema["enum"] = json!(enum_values.iter().map(|value| &value.label).collect::<Vec<_>>());
+                }
+                schema
+            },
+            FieldKind::Object {
+                schema,
+                ..
+            } => schema.to_json_schema(self.json_type.as_deref().unwrap_or("object"), self.is_array),
+            FieldKind::Custom(schema) => return schema.clone(),
+        };
+        extend_schema(&mut schema, &self.value_extra);
+        schema
+    }
+
+    /// Значение поля, прочитанное из индивида, None - свойство не заполнено
+    fn to_json(&self, individual: &mut Individual, ontology: &mut dyn OntologyLookup, language: &str, visited: &mut HashSet<String>) -> Option<Value> {
+        let property = match (&self.property, &self.kind) {
+            (Some(property), _) => property,
+            (
+                None,
+                FieldKind::Object {
+                    schema,
+                    ..
+                },
+            ) => {
+                let values = schema.to_json(individual, ontology, language, visited);
+                return if values.is_empty() {
+                    None
+                } else {
+                    Some(Value::Object(values))
+                };
+            },
+            (None, _) => return None,
+        };
+
+        let values: Vec<Value> = match &self.kind {
+            FieldKind::Literal {
+                value_type,
+                ..
+            } => get_typed_values(individual, property, *value_type),
+            FieldKind::Reference {
+                enum_values,
+                ..
+            } => individual
+                .get_literals(property)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|uri| match enum_values.iter().find(|value| value.uri == uri) {
+                    Some(value) => Value::String(value.label.clone()),
+                    None => Value::String(
+                        ontology
+                            .get_individual(&uri)
+                            .and_then(|mut linked| {
+                                linked.get_first_literal_with_lang("rdfs:label", &[Lang::new_from_str(language)]).or_else(|| linked.get_first_literal("rdfs:label"))
+                            })
+                            .unwrap_or(uri),
+                    ),
+                })
+                .collect(),
+            FieldKind::Object {
+                as_json: true,
+                ..
+            } => individual.get_literals(property).unwrap_or_default().iter().filter_map(|text| serde_json::from_str(text).ok()).collect(),
+            FieldKind::Object {
+                schema,
+                ..
+            } => {
+                let mut values = Vec::new();
+                for id in individual.get_literals(property).unwrap_or_default() {
+                    // Связи между индивидами могут быть циклическими
+                    if !visited.insert(id.clone()) {
+                        continue;
+                    }
+                    match ontology.get_individual(&id) {
+                        Some(mut linked) => values.push(Value::Object(schema.to_json(&mut linked, ontology, language, visited))),
+                        None => warn!("Failed to load individual {} linked by {}", id, property),
+                    }
+                }
+                values
+            },
+            FieldKind::Custom(_) => return None,
+        };
+
+        if values.is_empty() {
+            None
+        } else if self.is_array {
+            Some(Value::Array(values))
+        } else {
+            values.into_iter().next()
+        }
+    }
+
+    fn apply(&self, value: &Value, individual: &mut Individual, mapped: &mut MappedValues, replace_existing: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let values: Vec<&Value> = match value {
+            Value::Array(arr) => arr.iter().collect(),
+            _ => vec![value],
+        };
+        let is_multiple = value.is_array() || self.is_multiple;
+
+        let property = match (&self.property, &self.kind) {
+            (Some(property), _) => property,
+            // Вложенный объект без связи: свойства записываются в родительский индивид
+            (
+                None,
+                FieldKind::Object {
+                    schema,
+                    ..
+                },
+            ) => {
+                if let Value::Object(obj) = value {
+                    schema.apply(obj, individual, mapped, replace_existing)?;
+                    apply_assign_properties(individual, &schema.assign_properties);
+                }
+                return Ok(());
+            },
+            (None, _) => return Ok(()),
+        };
+
+        if replace_existing {
+            individual.remove(property);
+        }
+
+        match &self.kind {
+            FieldKind::Literal {
+                value_type,
+                lang,
+            } => {
+                // Язык, объявленный для поля, имеет приоритет над языком ответа
+                let lang = lang.as_deref().or(mapped.output_language.as_deref());
+                for val in values {
+                    if !set_typed_value(individual, property, val, *value_type, lang, is_multiple) {
+                        warn!("Failed to convert value {} to {:?} for property {}", val, value_type, property);
+                    }
+                }
+            },
+            FieldKind::Reference {
+                type_uri,
+                enum_values,
+            } => {
+                for val in values {
+                    let label = match val.as_str() {
+                        Some(label) => label,
+                        None => {
+                            warn!("Skipping non-string value {} of property {}", val, property);
+                            continue;
+                        },
+                    };
+
+                    match find_enum_value(enum_values, label, self.match_settings.threshold) {
+                        Some(value) if is_multiple => individual.add_uri(property, &value.uri),
+                        Some(value) => individual.set_uri(property, &value.uri),
+                        None => mapped.entity_candidates.push(EntityCandidate::Reference {
+                            parent_id: individual.get_id().to_string(),
+                            property: property.clone(),
+                            type_uri: type_uri.clone(),
+                            label: label.to_string(),
+                            is_multiple,
+                            settings: self.match_settings.clone(),
+                        }),
+                    }
+                }
+            },
+            // Свойства с признаком owl:Class хранят вложенные объекты как JSON
+            FieldKind::Object {
+                as_json: true,
+                ..
+            } => {
+                for val in values.into_iter().filter(|val| val.is_object()) {
+                    let text = serde_json::to_string_pretty(val)?;
+                    if is_multiple {
+                        individual.add_string(property, &text, Lang::none());
+                    } else {
+                        individual.set_string(property, &text, Lang::none());
+                    }
+                }
+            },
+            // Объект, связанный через объектное свойство: отдельный индивид на каждый объект
+            FieldKind::Object {
+                schema,
+                ..
+            } => {
+                for val in values {
+                    if let Value::Object(obj) = val {
+                        self.create_related_individual(schema, obj, property, individual, mapped)?;
+                    }
+                }
+            },
+            FieldKind::Custom(_) => {},
+        }
+
+        Ok(())
+    }
+
+    /// Создает индивид для вложенного объекта и связывает его с родителем
+    ///
+    /// Тип индивида берется из rdfs:range свойства связи, после значений объекта применяются assign_properties его уровня.
+    fn create_related_individual(
+        &self,
+        schema: &ObjectSchema,
+        values: &Map<String, Value>,
+        link_property: &str,
+        parent_individual: &mut Individual,
+        mapped: &mut MappedValues,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut created = Individual::default();
+        created.set_id(&format!("d:{}", uuid::Uuid::new_v4()));
+        if let Some(type_uri) = &schema.type_uri {
+            created.set_uri("rdf:type", type_uri);
+        }
+
+        schema.apply(values, &mut created, mapped, false)?;
+        apply_assign_properties(&mut created, &schema.assign_properties);
+        set_prompt_version(&mut created, mapped);
+
+        parent_individual.add_uri(link_property, created.get_id());
+        if let Some(type_uri) = &schema.type_uri {
+            mapped.entity_candidates.push(EntityCandidate::Individual {
+                id: created.get_id().to_string(),
+                parent_id: parent_individual.get_id().to_string(),
+                link_property: link_property.to_string(),
+                type_uri: type_uri.clone(),
+                settings: self.match_settings.clone(),
+            });
+        }
+        mapped.related_individuals.push(created);
+        Ok(())
+    }
+}
+
+fn set_prompt_version(individual: &mut Individual, mapped: &MappedValues) {
+    if let Some(prompt_version) = &mapped.prompt_version {
+        individual.set_uri("v-bpa:usedPromptVersion", prompt_version);
+    }
+}
+
+fn get_schema_extra(additional: &Map<String, Value>) -> Map<String, Value> {
+    additional.iter().filter(|(key, _)| !SERVICE_KEYS.contains(&key.as_str())).map(|(key, value)| (key.clone(), value.clone())).collect()
+}
+
+fn get_assign_properties(additional: &Map<String, Value>) -> Vec<(String, String)> {
+    additional
+        .get("assign_properties")
+        .and_then(Value::as_object)
+        .map(|props| props.iter().filter_map(|(pred, val)| val.as_str().map(|val| (pred.clone(), val.to_string()))).collect())
+        .unwrap_or_default()
+}
+
+fn extend_schema(schema: &mut Value, extra: &Map<String, Value>) {
+    if let Some(obj) = schema.as_object_mut() {
+        for (key, value) in extra {
+            obj.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+/// Применяет assign_properties уровня схемы к индивиду, созданному для этого уровня
+fn apply_assign_properties(individual: &mut Individual, assign_properties: &[(String, String)]) {
+    for (pred, val) in assign_properties {
+        individual.set_uri(pred, val);
+    }
+}
+
+/// Находит значение перечисления по метке из ответа AI
+///
+/// Метка сравнивается с метками экземпляров на всех языках, затем без учета регистра и знаков препинания.
+/// Если точного совпадения нет, выбирается самая похожая метка со сходством не ниже порога,
+/// чтобы метка с опечаткой или в другой форме слова не записывалась строкой.
+pub fn find_enum_value<'e>(enum_values: &'e [EnumValue], label: &str, threshold: f64) -> Option<&'e EnumValue> {
+    if let Some(value) = enum_values.iter().find(|value| value.labels.iter().any(|enum_label| enum_label == label)) {
+        return Some(value);
+    }
+
+    let normalized = normalize_text(label);
+    if normalized.is_empty() {
+        return None;
+    }
+
+    let mut best: Option<(&EnumValue, f64)> = None;
+    for value in enum_values {
+        for enum_label in &value.labels {
+            let score = label_similarity(&normalized, &normalize_text(enum_label));
+            if score >= threshold && best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((value, score));
+            }
+        }
+    }
+
+    if let Some((value, score)) = best {
+        info!("Value '{}' matched to {} by similar label ({:.2})", label, value.uri, score);
+    }
+    best.map(|(value, _)| value)
+}
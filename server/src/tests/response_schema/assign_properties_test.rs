@@ -0,0 +1,80 @@
+use super::{compile_schema, load_fixture, setup_ontology};
+use crate::response_schema::ParseResult;
+use serde_json::Value;
+use v_common::onto::individual::Individual;
+
+fn parse_nested_response() -> ParseResult {
+    let mut ontology = setup_ontology();
+    let schema = compile_schema(&load_fixture("nested_assign_schema.json"), &mut ontology);
+    let response: Value = serde_json::from_str(&load_fixture("nested_assign_response.json")).expect("Failed to parse test response");
+
+    schema.parse_response(&response).expect("Failed to parse AI response")
+}
+
+fn find_related<'a>(parse_result: &'a mut ParseResult, id: &str) -> &'a mut Individual {
+    parse_result.related_individuals.iter_mut().find(|i| i.get_id() == id).unwrap_or_else(|| panic!("Related individual {} not found", id))
+}
+
+fn assert_uri(individual: &mut Individual, predicate: &str, expected: &str) {
+    assert_eq!(individual.get_first_literal(predicate).as_deref(), Some(expected), "Unexpected {} of {}", predicate, individual.get_id());
+}
+
+fn assert_absent(individual: &mut Individual, predicates: &[&str]) {
+    for predicate in predicates {
+        assert!(individual.get_first_literal(predicate).is_none(), "{} should not have {} assigned at another level", individual.get_id(), predicate);
+    }
+}
+
+#[test]
+fn test_assign_properties_on_main_individual() {
+    let mut parse_result = parse_nested_response();
+    let main = &mut parse_result.main_individual;
+
+    assert_uri(main, "v-bpa:targetType", "v-bpa:ProcessDocument");
+    assert_uri(main, "v-bpa:documentTitle", "Положение об отделе закупок");
+
+    // Вложенный объект без связи записывается в основной индивид вместе со своими assign_properties
+    assert_uri(main, "v-bpa:hasKeyData", "v-bpa:KeyDataExtracted");
+
+    assert_absent(main, &["v-bpa:personRole", "v-bpa:approvalStatus", "v-bpa:decisionSource"]);
+    assert_eq!(parse_result.related_individuals.len(), 5, "Expected author, two approvals and two decisions");
+}
+
+#[test]
+fn test_assign_properties_on_nested_object() {
+    let mut parse_result = parse_nested_response();
+    let author_id = parse_result.main_individual.get_first_literal("v-bpa:documentAuthor").expect("Author link not found");
+
+    let author = find_related(&mut parse_result, &author_id);
+    assert_uri(author, "rdf:type", "v-bpa:Person");
+    assert_uri(author, "v-bpa:personName", "Иван Иванов");
+    assert_uri(author, "v-bpa:personRole", "v-bpa:AuthorRole");
+    assert_absent(author, &["v-bpa:targetType", "v-bpa:hasKeyData", "v-bpa:approvalStatus"]);
+}
+
+#[test]
+fn test_assign_properties_on_array_items_and_deeper_levels() {
+    let mut parse_result = parse_nested_response();
+    let approval_ids = parse_result.main_individual.get_literals("v-bpa:documentApproval").expect("Approval links not found");
+    assert_eq!(approval_ids.len(), 2, "Each array item should become a separate individual");
+
+    let mut approvers = Vec::new();
+    let mut comments = Vec::new();
+    for approval_id in approval_ids {
+        let approval = find_related(&mut parse_result, &approval_id);
+        assert_uri(approval, "rdf:type", "v-bpa:DocumentApproval");
+        assert_uri(approval, "v-bpa:approvalStatus", "v-bpa:ApprovalExtracted");
+        assert_absent(approval, &["v-bpa:targetType", "v-bpa:decisionSource", "v-bpa:personRole"]);
+        approvers.push(approval.get_first_literal("v-bpa:approverName").unwrap_or_default());
+
+        let decision_id = approval.get_first_literal("v-bpa:approvalDecision").expect("Decision link not found");
+        let decision = find_related(&mut parse_result, &decision_id);
+        assert_uri(decision, "rdf:type", "v-bpa:ApprovalDecision");
+        assert_uri(decision, "v-bpa:decisionSource", "v-bpa:AIExtraction");
+        assert_absent(decision, &["v-bpa:approvalStatus", "v-bpa:targetType"]);
+        comments.push(decision.get_first_literal("v-bpa:decisionComment").unwrap_or_default());
+    }
+
+    assert_eq!(approvers, vec!["Петр Петров", "Анна Смирнова"]);
+    assert_eq!(comments, vec!["Согласовано", "Согласовано с замечаниями"]);
+}
@@ -0,0 +1,73 @@
+use super::setup_ontology;
+use crate::ontology_lookup::TurtleOntology;
+use crate::schema_compiler::{CompiledSchema, SchemaCompiler};
+use serde_json::{json, Value};
+use v_common::onto::individual::Individual;
+
+fn compile_priority_schema(ontology: &mut TurtleOntology, language: &str) -> CompiledSchema {
+    SchemaCompiler::new(ontology).language(language).compile_property_list(&["v-bpa:documentPriority".to_string()])
+}
+
+/// Записывает значение поля documentPriority в новый индивид, возвращает индивид и количество несопоставленных сущностей
+fn apply_priority(schema: &CompiledSchema, label: &str) -> (Individual, usize) {
+    let mut individual = Individual::default();
+    individual.set_id("d:test_document");
+
+    let values = json!({ "documentPriority": label });
+    let (_, candidates) = schema.apply_values(values.as_object().unwrap(), &mut individual, false).expect("Failed to apply values");
+    (individual, candidates.len())
+}
+
+#[test]
+fn test_enum_labels_in_prompt_language() {
+    let mut ontology = setup_ontology();
+
+    let schema = compile_priority_schema(&mut ontology, "en");
+    // Экземпляр без метки на языке промпта перечисляется с меткой на другом языке
+    assert_eq!(schema.to_ai_schema()["properties"]["documentPriority"]["enum"], json!(["High", "Low", "Срочный"]));
+    assert_eq!(schema.property_mapping().get("documentPriority*High").map(String::as_str), Some("v-bpa:HighPriority"));
+
+    let schema = compile_priority_schema(&mut ontology, "ru");
+    assert_eq!(schema.to_ai_schema()["properties"]["documentPriority"]["enum"], json!(["Высокий", "Низкий", "Срочный"]));
+}
+
+#[test]
+fn test_enum_value_matched_by_label_in_any_language() {
+    let mut ontology = setup_ontology();
+    let schema = compile_priority_schema(&mut ontology, "en");
+
+    for label in ["High", "Высокий", "high"] {
+        let (mut individual, unresolved) = apply_priority(&schema, label);
+        assert_eq!(individual.get_first_literal("v-bpa:documentPriority").as_deref(), Some("v-bpa:HighPriority"), "Label '{}' should map to HighPriority", label);
+        assert_eq!(unresolved, 0);
+    }
+    assert_eq!(schema.find_enum_uri("documentPriority", "Низкий").as_deref(), Some("v-bpa:LowPriority"));
+}
+
+#[test]
+fn test_enum_value_fuzzy_fallback() {
+    let mut ontology = setup_ontology();
+    let schema = compile_priority_schema(&mut ontology, "ru");
+
+    // Опечатка в метке не мешает сопоставлению
+    let (mut individual, unresolved) = apply_priority(&schema, "Срочныи");
+    assert_eq!(individual.get_first_literal("v-bpa:documentPriority").as_deref(), Some("v-bpa:UrgentPriority"));
+    assert_eq!(unresolved, 0);
+
+    // Метка, не похожая ни на одно значение, передается на сопоставление сущностей
+    let (mut individual, unresolved) = apply_priority(&schema, "Средний");
+    assert!(individual.get_first_literal("v-bpa:documentPriority").is_none());
+    assert_eq!(unresolved, 1);
+}
+
+#[test]
+fn test_individual_to_json_uses_prompt_language() {
+    let mut ontology = setup_ontology();
+    let schema = compile_priority_schema(&mut ontology, "en");
+
+    let (individual, _) = apply_priority(&schema, "Высокий");
+    ontology.insert(&individual);
+
+    let rendered: Value = schema.individual_to_json(individual.get_id(), &mut ontology).expect("Failed to render individual");
+    assert_eq!(rendered, json!({ "documentPriority": "High" }));
+}
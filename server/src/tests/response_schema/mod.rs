@@ -1,2 +1,46 @@
+pub mod assign_properties_test;
+pub mod enum_labels_test;
+pub mod ontology_lookup_test;
+pub mod result_stamp_test;
+pub mod reverse_mapping_test;
 pub mod schema_processing_test;
 pub mod schema_validation_test;
+
+use crate::ontology_lookup::TurtleOntology;
+use crate::response_schema::ResponseSchema;
+use crate::schema_compiler::{CompiledSchema, SchemaCompiler};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Свойства схем тестов описаны в fixtures/test_ontology.ttl, хранилище Veda не нужно
+pub fn setup_ontology() -> TurtleOntology {
+    let mut ontology = TurtleOntology::default();
+    ontology.load_file(&fixture_path("test_ontology.ttl")).expect("Failed to load test ontology");
+    ontology
+}
+
+pub fn fixture_path(filename: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src").join("tests").join("response_schema").join("fixtures").join(filename)
+}
+
+pub fn load_fixture(filename: &str) -> String {
+    fs::read_to_string(fixture_path(filename)).unwrap_or_else(|e| panic!("Failed to read fixture file {}: {}", filename, e))
+}
+
+/// Компилирует схему ответа из JSON так же, как схему промпта при обработке запроса
+pub fn compile_schema(json: &str, ontology: &mut TurtleOntology) -> CompiledSchema {
+    let schema = ResponseSchema::from_json(json).expect("Failed to parse test schema");
+    SchemaCompiler::new(ontology).compile_response_schema(&schema).expect("Failed to compile test schema")
+}
+
+/// Онтология модуля из ontology/*.ttl и ../src/ontology вместе со свойствами базовой онтологии Veda
+pub fn load_module_ontology() -> TurtleOntology {
+    let manifest_dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut ontology = TurtleOntology::from_dir(&manifest_dir.join("ontology")).expect("Failed to load module ontology");
+
+    for filename in ["business-process-analysis.ttl", "bpa-process-document.ttl"] {
+        ontology.load_file(&manifest_dir.join("..").join("src").join("ontology").join(filename)).expect("Failed to load process ontology");
+    }
+    ontology.load_file(&fixture_path("veda_base.ttl")).expect("Failed to load base ontology");
+    ontology
+}
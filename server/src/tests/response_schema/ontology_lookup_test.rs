@@ -0,0 +1,46 @@
+use super::load_module_ontology;
+use crate::ontology_lookup::OntologyLookup;
+use crate::prompt_lint::{lint_prompt, Severity};
+use crate::schema_compiler::SchemaCompiler;
+
+#[test]
+fn test_property_definition_from_turtle() {
+    let mut ontology = load_module_ontology();
+
+    let definition = ontology.get_property("v-bpa:valueLanguage").expect("Property not found");
+    assert!(definition.is_functional);
+    assert!(!definition.is_class);
+    assert_eq!(definition.range.as_deref(), Some("xsd:string"));
+    assert_eq!(definition.label.as_deref(), Some("Язык значения"));
+
+    assert!(ontology.get_property("v-bpa:noSuchProperty").is_none());
+}
+
+#[test]
+fn test_class_instances_from_turtle() {
+    let mut ontology = load_module_ontology();
+
+    let instances: Vec<String> = ontology.list_instances("v-bpa:VoteMethod").iter().map(|instance| instance.get_id().to_string()).collect();
+    assert_eq!(instances, vec!["v-bpa:MajorityVote", "v-bpa:WeightedVote"]);
+
+    // Метки экземпляров становятся значениями перечисления схемы
+    let enum_values = SchemaCompiler::new(&mut ontology).list_instances("v-bpa:VoteMethod");
+    assert!(enum_values.iter().any(|value| value.label == "Большинством голосов" && value.uri == "v-bpa:MajorityVote"));
+}
+
+#[test]
+fn test_module_prompts_compile_and_lint_without_errors() {
+    let mut ontology = load_module_ontology();
+
+    let prompts = ontology.list_instances("v-bpa:SystemPrompt");
+    assert!(!prompts.is_empty(), "No system prompts loaded");
+
+    for mut prompt in prompts {
+        if let Err(e) = SchemaCompiler::new(&mut ontology).compile_prompt(&mut prompt) {
+            panic!("Schema of prompt {} does not compile: {}", prompt.get_id(), e);
+        }
+
+        let errors: Vec<String> = lint_prompt(&mut ontology, &mut prompt).iter().filter(|d| d.severity == Severity::Error).map(ToString::to_string).collect();
+        assert!(errors.is_empty(), "Schema of prompt {} has errors: {:?}", prompt.get_id(), errors);
+    }
+}
@@ -0,0 +1,38 @@
+use super::setup_ontology;
+use crate::schema_compiler::{CompiledSchema, SchemaCompiler};
+use serde_json::json;
+use v_common::onto::datatype::Lang;
+use v_common::onto::individual::Individual;
+
+fn compile_title_schema() -> CompiledSchema {
+    let mut ontology = setup_ontology();
+    SchemaCompiler::new(&mut ontology).compile_property_list(&["v-bpa:documentTitle".to_string()])
+}
+
+fn apply_title(schema: &CompiledSchema, title: &str) -> Individual {
+    let values = json!({ "documentTitle": title });
+    let mut individual = Individual::default();
+    schema.apply_values(values.as_object().unwrap(), &mut individual, false).expect("Failed to apply values");
+    individual
+}
+
+#[test]
+fn test_result_strings_tagged_with_output_language() {
+    let mut schema = compile_title_schema();
+    schema.output_language = Some("en".to_string());
+
+    let mut individual = apply_title(&schema, "Purchase order");
+    assert_eq!(individual.get_first_literal_with_lang("v-bpa:documentTitle", &[Lang::new_from_str("en")]).as_deref(), Some("Purchase order"));
+}
+
+#[test]
+fn test_results_stamped_with_prompt_version() {
+    let mut schema = compile_title_schema();
+    schema.prompt_version = Some("d:bpa_prompt_version_DocumentAnalysisPrompt_3".to_string());
+    let mut individual = apply_title(&schema, "Заявка на закупку");
+    assert_eq!(individual.get_first_literal("v-bpa:usedPromptVersion").as_deref(), Some("d:bpa_prompt_version_DocumentAnalysisPrompt_3"));
+
+    // Схема промпта без версий не добавляет ссылку
+    schema.prompt_version = None;
+    assert!(!apply_title(&schema, "Заявка на закупку").is_exists("v-bpa:usedPromptVersion"));
+}
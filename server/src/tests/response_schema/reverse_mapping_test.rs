@@ -0,0 +1,33 @@
+use super::{compile_schema, load_fixture, setup_ontology};
+use crate::ontology_lookup::TurtleOntology;
+use serde_json::{json, Value};
+
+/// Записывает ответ AI в индивиды, сохраняет их в онтологию и возвращает JSON, восстановленный из основного индивида
+fn round_trip(ontology: &mut TurtleOntology, response: &Value) -> Value {
+    let schema = compile_schema(&load_fixture("nested_assign_schema.json"), ontology);
+
+    let parse_result = schema.parse_response(response).expect("Failed to parse AI response");
+    ontology.insert(&parse_result.main_individual);
+    for related in parse_result.related_individuals.iter() {
+        ontology.insert(related);
+    }
+
+    schema.individual_to_json(parse_result.main_individual.get_id(), ontology).expect("Failed to render individual")
+}
+
+#[test]
+fn test_individual_to_json_round_trip() {
+    let mut ontology = setup_ontology();
+    let response: Value = serde_json::from_str(&load_fixture("nested_assign_response.json")).expect("Failed to parse test response");
+
+    // Вложенные объекты и массивы восстанавливаются по связям, объект без связи - из основного индивида
+    assert_eq!(round_trip(&mut ontology, &response), response);
+}
+
+#[test]
+fn test_individual_to_json_skips_empty_fields() {
+    let mut ontology = setup_ontology();
+    let response = json!({ "author": { "name": "Иван Иванов" } });
+
+    assert_eq!(round_trip(&mut ontology, &response), response, "Fields without values should not appear in the rendered JSON");
+}
@@ -1,4 +1,8 @@
+use super::{compile_schema, setup_ontology};
+use crate::ontology_lookup::TurtleOntology;
 use crate::response_schema::{ParseResult, ResponseSchema};
+use crate::schema_compiler::CompiledSchema;
+use crate::value_types::parse_datetime;
 use rio_api::model::Literal::{LanguageTaggedString, Simple, Typed};
 use rio_api::model::NamedOrBlankNode;
 use rio_api::model::Term::{BlankNode, Literal, NamedNode};
@@ -8,82 +12,8 @@ use serde_json::{json, Value};
 use std::fs;
 use std::io::BufReader;
 use std::path::Path;
-use v_common::module::veda_backend::Backend;
 use v_common::onto::datatype::Lang;
 use v_common::onto::individual::Individual;
-use v_common::storage::common::StorageMode;
-use v_common::v_api::api_client::IndvOp;
-use v_common::v_api::obj::ResultCode;
-
-struct TestContext {
-    backend: Backend,
-    sys_ticket: String,
-}
-
-fn setup_test_context() -> TestContext {
-    // Создаем бэкенд в режиме чтения-записи
-    let mut backend = Backend::create(StorageMode::ReadWrite, false);
-
-    // Получаем системный тикет
-    let sys_ticket = backend.get_sys_ticket_id().expect("Failed to get system ticket");
-
-    // Создаем базовые классы и свойства
-    let base_classes = [("owl:Class", "rdf:type", "owl:Class"), ("owl:DatatypeProperty", "rdf:type", "owl:Class"), ("owl:FunctionalProperty", "rdf:type", "owl:Class")];
-
-    for (id, pred, val) in base_classes.iter() {
-        let mut indv = Individual::default();
-        indv.set_id(id);
-        indv.add_uri(pred, val);
-
-        if backend.mstorage_api.update_or_err(&sys_ticket, "", "schema", IndvOp::Put, &mut indv).is_err() {
-            panic!("Failed to store base class {}", id);
-        }
-    }
-
-    // Создаем определения свойств
-    let properties = [
-        ("v-bpa:documentSections", "owl:Class", true),
-        ("v-bpa:sectionTitle", "xsd:string", false),
-        ("v-bpa:sectionContent", "xsd:string", false),
-        ("v-bpa:documentTitle", "xsd:string", false),
-        ("v-bpa:documentType", "xsd:string", false),
-        ("v-bpa:documentSource", "xsd:string", false),
-        ("v-bpa:documentDate", "xsd:dateTime", false),
-        ("v-bpa:documentSignedBy", "xsd:string", false),
-        ("v-bpa:documentErrors", "xsd:string", true),
-    ];
-
-    for (uri, property_type, is_multiple) in properties.iter() {
-        let mut individual = Individual::default();
-        individual.set_id(uri);
-
-        if *property_type == "owl:Class" {
-            individual.add_uri("rdf:type", "owl:Class");
-        } else {
-            individual.add_uri("rdf:type", "owl:DatatypeProperty");
-            individual.add_string("rdfs:range", property_type, Lang::none());
-
-            if !is_multiple {
-                individual.add_uri("rdf:type", "owl:FunctionalProperty");
-            }
-        }
-
-        if backend.mstorage_api.update_or_err(&sys_ticket, "", "schema", IndvOp::Put, &mut individual).is_err() {
-            panic!("Failed to store property definition {}", uri);
-        }
-
-        // Проверяем сохранение
-        let mut check_indv = Individual::default();
-        if backend.storage.get_individual(uri, &mut check_indv) != ResultCode::Ok {
-            panic!("Failed to read back property {}", uri);
-        }
-    }
-
-    TestContext {
-        backend,
-        sys_ticket,
-    }
-}
 
 fn load_fixture(filename: &str) -> Value {
     let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("src").join("tests").join("response_schema").join("fixtures").join(filename);
@@ -95,11 +25,11 @@ fn load_fixture(filename: &str) -> Value {
     serde_json::from_str(&content).unwrap_or_else(|e| panic!("Failed to parse JSON from fixture {}: {}", filename, e))
 }
 
-fn load_test_schema() -> ResponseSchema {
+fn compile_test_schema(ontology: &mut TurtleOntology) -> CompiledSchema {
     println!("Loading test schema...");
     let schema_value = load_fixture("test_schema.json");
     println!("Schema value: {:#?}", schema_value);
-    ResponseSchema::from_value(&schema_value).expect("Failed to parse test schema")
+    compile_schema(&schema_value.to_string(), ontology)
 }
 
 fn load_test_response() -> Value {
@@ -132,8 +62,8 @@ fn load_expected_result() -> Individual {
 
     fn shorten_uri(uri: &str, namespaces: &[(&str, &str)]) -> String {
         for (prefix, full) in namespaces {
-            if uri.starts_with(full) {
-                return format!("{}{}", prefix, &uri[full.len()..]);
+            if let Some(local) = uri.strip_prefix(full) {
+                return format!("{}{}", prefix, local);
             }
         }
         uri.to_string()
@@ -186,10 +116,14 @@ fn load_expected_result() -> Individual {
                         },
                         Typed {
                             value,
-                            datatype: _,
+                            datatype,
                         } => {
                             println!("Adding typed literal: {} -> {}", predicate, value);
-                            individual.add_string(&predicate, value, Lang::none());
+                            // Даты из ответа AI записываются как xsd:dateTime
+                            match parse_datetime(value).filter(|_| datatype.iri == "http://www.w3.org/2001/XMLSchema#dateTime") {
+                                Some(timestamp) => individual.add_datetime(&predicate, timestamp),
+                                None => individual.add_string(&predicate, value, Lang::none()),
+                            }
                         },
                     }
                     Ok(())
@@ -221,7 +155,7 @@ fn compare_json_str(left: &str, right: &str) -> bool {
     }
 }
 
-fn verify_parsing_result(parse_result: &mut ParseResult, context: &mut TestContext) {
+fn verify_parsing_result(parse_result: &mut ParseResult) {
     println!("Loading expected result...");
     let expected = load_expected_result();
     println!("Expected individual id: {}", expected.get_id());
@@ -255,20 +189,15 @@ fn verify_parsing_result(parse_result: &mut ParseResult, context: &mut TestConte
             }
         }
     }
-
-    // Сохраняем основной индивид в хранилище
-    if let Err(e) = context.backend.mstorage_api.update_or_err(&context.sys_ticket, "", "test", IndvOp::Put, main) {
-        panic!("Failed to store main individual: {:?}", e);
-    }
 }
 
 #[test]
 fn test_schema_parsing_and_response_processing() {
-    let mut context = setup_test_context();
-    let test_schema = load_test_schema();
+    let mut ontology = setup_ontology();
+    let test_schema = compile_test_schema(&mut ontology);
 
     // Проверяем создание схемы для AI
-    let ai_schema = test_schema.to_ai_schema().expect("Failed to create AI schema");
+    let ai_schema = test_schema.to_ai_schema();
     println!("AI schema: {:#?}", ai_schema);
 
     let ai_schema_obj = ai_schema.as_object().unwrap();
@@ -281,9 +210,9 @@ fn test_schema_parsing_and_response_processing() {
     assert!(!schema_str.contains("is_multiple"), "AI schema should not contain is_multiple field");
 
     // Парсим ответ
-    let mut parse_result = test_schema.parse_ai_response(&load_test_response(), &mut context.backend, &context.sys_ticket).expect("Failed to parse AI response");
+    let mut parse_result = test_schema.parse_response(&load_test_response()).expect("Failed to parse AI response");
 
-    verify_parsing_result(&mut parse_result, &mut context);
+    verify_parsing_result(&mut parse_result);
 }
 
 #[test]
@@ -296,22 +225,22 @@ fn test_invalid_schema() {
         }
     });
 
-    let result = ResponseSchema::from_value(&invalid_schema);
+    let result = ResponseSchema::from_json(&invalid_schema.to_string());
     assert!(result.is_err(), "Schema with invalid root type should fail validation");
 
     let incomplete_schema = json!({
         "type": "object"
     });
 
-    let result = ResponseSchema::from_value(&incomplete_schema);
+    let result = ResponseSchema::from_json(&incomplete_schema.to_string());
     assert!(result.is_err(), "Schema without required fields should fail validation");
 }
 
 #[test]
 fn test_schema_ai_conversion() {
     println!("Testing schema AI conversion...");
-    let test_schema = load_test_schema();
-    let ai_schema = test_schema.to_ai_schema().expect("Failed to create AI schema");
+    let mut ontology = setup_ontology();
+    let ai_schema = compile_test_schema(&mut ontology).to_ai_schema();
     println!("AI converted schema: {:#?}", ai_schema);
 
     fn check_no_service_fields(value: &Value) {
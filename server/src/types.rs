@@ -1,8 +1,6 @@
-use serde_json::Value;
 use std::collections::HashMap;
 
 pub type PropertyMapping = HashMap<String, String>; // short_name -> full_name
-pub type PropertySchema = HashMap<String, Value>;
 
 /*
 pub static SYSTEM_PREDICATE: &[&str] = &[
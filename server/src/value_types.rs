@@ -0,0 +1,187 @@
+// value_types.rs
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, SecondsFormat};
+use serde_json::{json, Value};
+use v_common::onto::datatype::Lang;
+use v_common::onto::individual::Individual;
+
+/// Язык строк rdf:langString, если он не объявлен в схеме
+pub const DEFAULT_LANGUAGE: &str = "ru";
+
+/// Форматы даты и времени, которые принимаются от AI помимо RFC 3339
+const DATETIME_FORMATS: &[&str] = &["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%d.%m.%Y %H:%M:%S", "%d.%m.%Y %H:%M", "%d/%m/%Y %H:%M"];
+const DATE_FORMATS: &[&str] = &["%Y-%m-%d", "%d.%m.%Y", "%d.%m.%y", "%d/%m/%Y", "%Y/%m/%d", "%d-%m-%Y", "%Y.%m.%d"];
+
+/// Тип литерального значения свойства, определяемый по его rdfs:range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueType {
+    String,
+    LangString,
+    Integer,
+    Decimal,
+    Boolean,
+    DateTime,
+    Date,
+}
+
+impl ValueType {
+    /// Возвращает тип значения для литерального rdfs:range, для ссылок на классы возвращает None
+    pub fn from_range(range: &str) -> Option<Self> {
+        match range {
+            "rdf:langString" => Some(ValueType::LangString),
+            "xsd:integer" | "xsd:int" | "xsd:long" | "xsd:nonNegativeInteger" | "xsd:positiveInteger" => Some(ValueType::Integer),
+            "xsd:decimal" | "xsd:double" | "xsd:float" => Some(ValueType::Decimal),
+            "xsd:boolean" => Some(ValueType::Boolean),
+            "xsd:dateTime" => Some(ValueType::DateTime),
+            "xsd:date" => Some(ValueType::Date),
+            _ if range.starts_with("xsd:") => Some(ValueType::String),
+            _ => None,
+        }
+    }
+
+    /// Описание значения в JSON-схеме ответа AI
+    pub fn json_schema(&self) -> Value {
+        match self {
+            ValueType::String | ValueType::LangString => json!({"type": "string"}),
+            ValueType::Integer => json!({"type": "integer"}),
+            ValueType::Decimal => json!({"type": "number"}),
+            ValueType::Boolean => json!({"type": "boolean"}),
+            ValueType::DateTime => json!({"type": "string", "format": "date-time"}),
+            ValueType::Date => json!({"type": "string", "format": "date"}),
+        }
+    }
+
+    /// Формат строкового значения в JSON-схеме, если он есть
+    pub fn json_format(&self) -> Option<&'static str> {
+        match self {
+            ValueType::DateTime => Some("date-time"),
+            ValueType::Date => Some("date"),
+            _ => None,
+        }
+    }
+}
+
+/// Разбирает дату или дату со временем в допустимых форматах ("2024-03-12", "12.03.2024", "2024-03-12T10:00:00Z" и т.п.)
+///
+/// Значения без часового пояса считаются заданными в UTC. Возвращает время в секундах Unix.
+pub fn parse_datetime(value: &str) -> Option<i64> {
+    let value = value.trim();
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Some(datetime.timestamp());
+    }
+    for format in DATETIME_FORMATS {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(value, format) {
+            return Some(datetime.and_utc().timestamp());
+        }
+    }
+    for format in DATE_FORMATS {
+        if let Ok(date) = NaiveDate::parse_from_str(value, format) {
+            return date.and_hms_opt(0, 0, 0).map(|datetime| datetime.and_utc().timestamp());
+        }
+    }
+    None
+}
+
+/// Разбирает логическое значение, в том числе переданное строкой
+fn parse_bool(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        Value::String(s) => match s.trim().to_lowercase().as_str() {
+            "true" | "yes" | "да" | "1" => Some(true),
+            "false" | "no" | "нет" | "0" => Some(false),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Записывает одно значение из ответа AI в свойство индивида с учетом типа значения
+///
+/// Строки записываются с языком `lang`, строки rdf:langString без языка - с языком по умолчанию.
+/// Возвращает false, если значение не удалось привести к типу свойства.
+pub fn set_typed_value(individual: &mut Individual, predicate: &str, value: &Value, value_type: ValueType, lang: Option<&str>, is_multiple: bool) -> bool {
+    match value_type {
+        ValueType::String | ValueType::LangString => {
+            let text = match value {
+                Value::String(s) => s.clone(),
+                Value::Null => return false,
+                other => other.to_string(),
+            };
+            let lang = match (value_type, lang) {
+                (ValueType::LangString, lang) => Lang::new_from_str(lang.unwrap_or(DEFAULT_LANGUAGE)),
+                (_, Some(lang)) => Lang::new_from_str(lang),
+                (_, None) => Lang::none(),
+            };
+            if is_multiple {
+                individual.add_string(predicate, &text, lang);
+            } else {
+                individual.set_string(predicate, &text, lang);
+            }
+        },
+        ValueType::Integer => {
+            let number = match value.as_i64().or_else(|| value.as_str().and_then(|s| s.trim().parse().ok())) {
+                Some(n) => n,
+                None => return false,
+            };
+            if is_multiple {
+                individual.add_integer(predicate, number);
+            } else {
+                individual.set_integer(predicate, number);
+            }
+        },
+        ValueType::Decimal => {
+            let number = match value.as_f64().or_else(|| value.as_str().and_then(|s| s.trim().replace(',', ".").parse().ok())) {
+                Some(n) => n,
+                None => return false,
+            };
+            if is_multiple {
+                individual.add_decimal_from_f64(predicate, number);
+            } else {
+                individual.set_decimal_from_f64(predicate, number);
+            }
+        },
+        ValueType::Boolean => {
+            let flag = match parse_bool(value) {
+                Some(b) => b,
+                None => return false,
+            };
+            if is_multiple {
+                individual.add_bool(predicate, flag);
+            } else {
+                individual.set_bool(predicate, flag);
+            }
+        },
+        ValueType::DateTime | ValueType::Date => {
+            let timestamp = match value.as_str().and_then(parse_datetime) {
+                Some(t) => t,
+                None => return false,
+            };
+            if is_multiple {
+                individual.add_datetime(predicate, timestamp);
+            } else {
+                individual.set_datetime(predicate, timestamp);
+            }
+        },
+    }
+    true
+}
+
+/// Возвращает значения свойства индивида в виде JSON согласно типу значения, обратное преобразование к set_typed_value
+///
+/// Дата со временем возвращается в RFC 3339, дата - в формате ГГГГ-ММ-ДД.
+pub fn get_typed_values(individual: &mut Individual, predicate: &str, value_type: ValueType) -> Vec<Value> {
+    let resources = individual.get_resources(predicate).unwrap_or_default();
+
+    resources
+        .iter()
+        .filter_map(|resource| match value_type {
+            ValueType::String | ValueType::LangString => Some(json!(resource.get_str())),
+            ValueType::Integer => Some(json!(resource.get_int())),
+            ValueType::Decimal => Some(json!(resource.get_float())),
+            ValueType::Boolean => Some(json!(resource.get_bool())),
+            ValueType::DateTime => DateTime::from_timestamp(resource.get_datetime(), 0).map(|datetime| json!(datetime.to_rfc3339_opts(SecondsFormat::Secs, true))),
+            ValueType::Date => DateTime::from_timestamp(resource.get_datetime(), 0).map(|datetime| json!(datetime.format("%Y-%m-%d").to_string())),
+        })
+        .collect()
+}